@@ -1,8 +1,9 @@
 const { app, BrowserWindow, ipcMain } = require('electron');
 const path = require('path');
-const { exec } = require('child_process');
+const { exec, execFile } = require('child_process');
 const util = require('util');
 const execPromise = util.promisify(exec);
+const execFilePromise = util.promisify(execFile);
 const fs = require('fs');
 
 let mainWindow;
@@ -36,15 +37,6 @@ async function getAppIconPath(processName) {
     return null;
 }
 
-async function getProcessCommand(pid) {
-    try {
-        const { stdout } = await execPromise(`ps -p ${pid} -o command=`);
-        return stdout.trim();
-    } catch (error) {
-        return null;
-    }
-}
-
 function createWindow() {
     let iconPath;
     if (process.platform === 'win32') {
@@ -68,78 +60,68 @@ function createWindow() {
     mainWindow.loadFile('index.html');
 }
 
+// Process data comes from the same Rust snapshot used by the CLI and TUI,
+// so the numbers agree across all front ends
+const PROCMANAGER_BIN = process.env.PROCMANAGER_BIN ||
+    path.join(__dirname, '../src-tauri/target/debug/procmanager');
+
+// The latest full snapshot, shared with getTrackedProcesses
+let latestSnapshot = [];
+
+const stateLabels = {
+    'DiskSleep': 'Uninterruptible Sleep'
+};
+
+async function getSnapshot() {
+    const { stdout } = await execFilePromise(PROCMANAGER_BIN, ['ptable', '--format', 'json'], {
+        maxBuffer: 64 * 1024 * 1024
+    });
+    latestSnapshot = JSON.parse(stdout);
+    return latestSnapshot;
+}
+
+function toGuiProcess(snapshot) {
+    const command = snapshot.cmdline || '';
+    let name = '';
+    if (command && command.includes('/')) {
+        // If command has a path, get the last part
+        const parts = command.split('/');
+        name = parts[parts.length - 1].split(' ')[0];
+    } else {
+        name = snapshot.cmd;
+    }
+
+    if (!name || name === '' || name === '.') {
+        name = snapshot.cmd || 'Unknown';
+    }
+
+    return {
+        pid: snapshot.pid,
+        ppid: snapshot.ppid || 0,
+        cpu: snapshot.cpu || 0,
+        memory: (snapshot.mem || 0) * 1024 * 1024, // MB to bytes
+        name,
+        state: stateLabels[snapshot.state] || snapshot.state || 'Unknown',
+        command,
+        niceness: snapshot.nice || 0
+    };
+}
+
 async function getProcesses() {
     try {
-        const { stdout } = await execPromise('ps -ax -o pid,ppid,pcpu,pmem,comm,state,command,ni');
-        const processLines = stdout.split('\n')
-            .slice(1) 
-            .filter(line => line.trim());
+        const snapshot = await getSnapshot();
 
-        const processPromises = processLines.map(async line => {
+        const processPromises = snapshot.map(async entry => {
             try {
-                const parts = line.trim().split(/\s+/);
-                const pid = parts[0];
-                const ppid = parts[1];
-                const cpu = parts[2];
-                const mem = parts[3];
-                const comm = parts[4];
-                const state = parts[5];
-                const niceness = parts[parts.length - 1]; // Get niceness value
-                const command = await getProcessCommand(pid).catch(() => parts.slice(6, -1).join(' '));
-
-                let name = '';
-                if (command && command.includes('/')) {
-                    // If command has a path, get the last part
-                    const parts = command.split('/');
-                    name = parts[parts.length - 1].split(' ')[0];
-                } else {
-                    name = comm && comm.includes('/') ? 
-                        comm.split('/').pop() : 
-                        comm;
-                }
-
-                if (!name || name === '' || name === '.' || name === 'ps') {
-                    name = comm || 'Unknown';
-                }
-                
-                const stateMap = {
-                    'R': 'Running',
-                    'S': 'Sleeping',
-                    'I': 'Idle',
-                    'T': 'Stopped',
-                    'U': 'Uninterruptible Sleep',
-                    'Z': 'Zombie',
-                    'D': 'Uninterruptible Sleep'
-                };
-
-                let processState = state && stateMap[state[0]] ? stateMap[state[0]] : state;
-                if (state) {
-                    if (state.includes('+')) processState += ' (Foreground)';
-                    if (state.includes('s')) processState += ' (Session Leader)';
-                    if (state.includes('<')) processState += ' (High Priority)';
-                    if (state.includes('N')) processState += ' (Low Priority)';
-                }
-
-                let iconPath = null;
+                const proc = toGuiProcess(entry);
                 try {
-                    iconPath = await getAppIconPath(name);
+                    proc.iconPath = await getAppIconPath(proc.name);
                 } catch (error) {
-                    iconPath = null;
+                    proc.iconPath = null;
                 }
-
-                return {
-                    pid: parseInt(pid),
-                    ppid: parseInt(ppid),
-                    cpu: parseFloat(cpu) || 0,
-                    memory: parseFloat(mem) * 1024 * 1024 || 0, // Convert percentage to bytes
-                    name,
-                    state: processState || 'Unknown',
-                    command: command || '',
-                    iconPath,
-                    niceness: parseInt(niceness) || 0
-                };
+                return proc;
             } catch (error) {
-                console.error('Error processing process line:', error);
+                console.error('Error processing snapshot entry:', error);
                 return null;
             }
         });
@@ -227,32 +209,13 @@ async function getTrackedProcesses() {
     try {
         const trackedProcessesData = [];
         const processesToRemove = new Set();
-        
+
         for (const pid of trackedProcesses) {
-            try {
-                const { stdout } = await execPromise(`ps -p ${pid} -o pid,ppid,pcpu,pmem,comm,stat,command`);
-                const lines = stdout.split('\n').slice(1).filter(line => line.trim());
-                if (lines.length > 0) {
-                    const [pid, ppid, cpu, mem, comm, stat, ...cmdParts] = lines[0].trim().split(/\s+/);
-                    const command = cmdParts.join(' ');
-                    let name = command.split('/').pop().split(' ')[0];
-                    if (name === '') name = comm;
-                    
-                    trackedProcessesData.push({
-                        pid: parseInt(pid),
-                        ppid: parseInt(ppid),
-                        cpu: parseFloat(cpu),
-                        memory: parseFloat(mem) * 1024 * 1024,
-                        name,
-                        state: stat,
-                        command
-                    });
-                } else {
-                    // process doesn't exist anymore, mark for removal
-                    processesToRemove.add(pid);
-                }
-            } catch (error) {
-                //process doesn't exist or error occurred, mark for removal
+            const entry = latestSnapshot.find(p => p.pid === pid);
+            if (entry) {
+                trackedProcessesData.push(toGuiProcess(entry));
+            } else {
+                // process doesn't exist anymore, mark for removal
                 processesToRemove.add(pid);
             }
         }
@@ -270,84 +233,60 @@ async function getTrackedProcesses() {
 }
 
 // drop down menu 
-async function handleProcessAction(action, pid, niceness) {
+// Actions go through the procmanager binary so they land in the same audit log as the CLI and TUI
+const actionCommands = {
+    kill: (pid) => ['kill', `${pid}`],
+    pause: (pid) => ['pause', `${pid}`],
+    resume: (pid) => ['resume', `${pid}`],
+    niceness: (pid, niceness) => ['change_nice', `${pid}`, `${niceness}`]
+};
+
+// With --format json a failed action prints its records, with the reason, on stdout
+function failureReason(error) {
     try {
-        switch (action) {
-            case 'kill':
-                try {
-                    // First try normal kill
-                    await execPromise(`kill -9 ${pid}`);
-                } catch (error) {
-                    console.log(`Normal kill failed for PID ${pid}, error:`, error);
-                    // If normal kill fails, try with sudo
-                    try {
-                        await execPromise(`sudo -n kill -9 ${pid}`);
-                    } catch (sudoError) {
-                        console.log(`Sudo kill failed for PID ${pid}, error:`, sudoError);
-                        throw new Error(`Cannot terminate process ${pid}. This may be a system process that requires elevated privileges.`);
-                    }
-                }
-                mainWindow.webContents.send('process-action-result', {
-                    success: true,
-                    action,
-                    pid
-                });
-                break;
-            case 'pause':
-                await execPromise(`kill -STOP ${pid}`);
-                mainWindow.webContents.send('process-action-result', {
-                    success: true,
-                    action,
-                    pid
-                });
-                break;
-            case 'resume':
-                await execPromise(`kill -CONT ${pid}`);
-                mainWindow.webContents.send('process-action-result', {
-                    success: true,
-                    action,
-                    pid
-                });
-                break;
-            case 'niceness':
-                try {
-                    await execPromise(`renice ${niceness} -p ${pid}`);
-                    mainWindow.webContents.send('process-action-result', {
-                        success: true,
-                        action,
-                        pid,
-                        niceness
-                    });
-                } catch (error) {
-                    // If permission denied, try with sudo
-                    if (error.stderr && error.stderr.includes('Permission denied')) {
-                        try {
-                            await execPromise(`sudo -n renice ${niceness} -p ${pid}`);
-                            mainWindow.webContents.send('process-action-result', {
-                                success: true,
-                                action,
-                                pid,
-                                niceness
-                            });
-                        } catch (sudoError) {
-                            mainWindow.webContents.send('process-action-result', {
-                                success: false,
-                                error: sudoError.message,
-                                action,
-                                pid
-                            });
-                        }
-                    } else {
-                        mainWindow.webContents.send('process-action-result', {
-                            success: false,
-                            error: error.message,
-                            action,
-                            pid
-                        });
-                    }
-                }
-                break;
+        const records = [].concat(JSON.parse(error.stdout));
+        const failed = records.find(record => !record.success);
+        if (failed) {
+            return failed.message;
+        }
+    } catch (parseError) {
+        // Not JSON: clap usage errors and the like go to stderr
+    }
+    return (error.stderr || '').trim() || error.message;
+}
+
+async function runProcmanagerAction(args) {
+    const fullArgs = [...args, '--format', 'json', '--audit-source', 'gui'];
+    try {
+        await execFilePromise(PROCMANAGER_BIN, fullArgs);
+    } catch (error) {
+        const reason = failureReason(error);
+        // Only permission errors get a non-interactive sudo retry, which the audit log records as such
+        if (!/permission denied/i.test(reason)) {
+            throw new Error(`Cannot ${args[0]} process ${args[1]}: ${reason}`);
         }
+        console.log(`${args.join(' ')} failed, retrying with sudo:`, reason);
+        try {
+            await execFilePromise('sudo', ['-n', PROCMANAGER_BIN, ...fullArgs]);
+        } catch (sudoError) {
+            throw new Error(`Cannot ${args[0]} process ${args[1]}: ${reason}`);
+        }
+    }
+}
+
+async function handleProcessAction(action, pid, niceness) {
+    const command = actionCommands[action];
+    if (!command) {
+        return;
+    }
+    try {
+        await runProcmanagerAction(command(pid, niceness));
+        mainWindow.webContents.send('process-action-result', {
+            success: true,
+            action,
+            pid,
+            ...(action === 'niceness' ? { niceness } : {})
+        });
     } catch (error) {
         console.error(`Error performing action ${action} on process ${pid}:`, error);
         mainWindow.webContents.send('process-action-result', {
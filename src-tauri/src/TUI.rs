@@ -3,23 +3,40 @@
 
 use cursive::align::HAlign;
 use cursive::traits::*;
-use cursive::views::{Dialog, TextView, ScrollView, LinearLayout, DummyView, SelectView, EditView};
+use cursive::event::Key;
+use cursive::views::{Dialog, TextView, ScrollView, LinearLayout, DummyView, SelectView, EditView, HideableView, OnEventView, NamedView};
 use cursive::Cursive;
 use cursive::CursiveExt;
 use cursive::view::Nameable;
-use cursive_table_view::{TableView, TableViewItem};
-use sysinfo::{ProcessStatus, System};
+use cursive_table_view::{TableColumn, TableView, TableViewItem};
+use sysinfo::System;
+use procmanager_lib::actions::{self, ActionError};
+use procmanager_lib::audit::{self, AuditLog, Source};
+use procmanager_lib::config::{self, Config, SortDirection};
+use procmanager_lib::delta;
+use procmanager_lib::files::{self, OpenFile};
+use procmanager_lib::filter::{Field, Filter, Filterable, Value};
+use nix::sys::signal::Signal;
+use procmanager_lib::maps::{self, Mapping, MappingTotal};
+use procmanager_lib::net::Socket;
+use procmanager_lib::procfs::ProcFs;
+use procmanager_lib::selector::{self, Selector};
+use procmanager_lib::snapshot::{self, ProcessSnapshot, ProcessState, Snapshotter};
+use procmanager_lib::system::{self, SystemInfo};
+use procmanager_lib::threads::{ThreadSampler, ThreadSnapshot};
+use procmanager_lib::tree::{build_process_tree, subtree_pids, ProcessNode, TreeItem};
 use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering as AtomicOrdering};
 use std::thread;
 use std::time::Duration;
 use lazy_static::lazy_static;
 use num_cpus;
-use sysinfo::{Pid, Signal};
 use std::io::{BufRead, BufReader};
 use std::fs::File;
-use std::fs::read_to_string;
 // use std::collections::HashMap;
 use cursive::theme::{BaseColor, Color, ColorStyle, PaletteColor, Theme, Effect, Style};
 use cursive::utils::markup::StyledString;
@@ -36,11 +53,86 @@ pub struct Process {
     pub ppid: Option<u32>,
     pub user: Option<String>,
     pub cpu: f32,
+    /// Resident memory (RSS) in MB
     pub mem: f32,
+    pub mem_percent: f32,
+    /// Virtual, shared, swapped out, proportional and unique memory in MB, as in `ProcessSnapshot`
+    pub vsz: f32,
+    pub shared: Option<f32>,
+    pub swap: Option<f32>,
+    pub pss: Option<f32>,
+    pub uss: Option<f32>,
     pub cmd: String,
     pub start_time: u64,
-    pub process_state: ProcessStatus,
+    pub process_state: ProcessState,
     pub priority: i32,
+    pub uid: Option<u32>,
+    pub cmdline: String,
+    pub threads: u32,
+    pub exe: Option<String>,
+    pub cwd: Option<String>,
+    /// TCP and UDP ports the process listens on; only looked up while the PORTS column or the port filter is in use
+    pub ports: Vec<u16>,
+}
+
+impl From<ProcessSnapshot> for Process {
+    fn from(snapshot: ProcessSnapshot) -> Self {
+        Process {
+            pid: snapshot.pid,
+            ppid: snapshot.ppid,
+            user: snapshot.user,
+            cpu: snapshot.cpu,
+            mem: snapshot.mem,
+            mem_percent: snapshot.mem_percent,
+            vsz: snapshot.vsz,
+            shared: snapshot.shared,
+            swap: snapshot.swap,
+            pss: snapshot.pss,
+            uss: snapshot.uss,
+            cmd: snapshot.cmd,
+            start_time: snapshot.start_time,
+            process_state: snapshot.state,
+            priority: snapshot.nice,
+            uid: snapshot.uid,
+            cmdline: snapshot.cmdline,
+            threads: snapshot.threads,
+            exe: snapshot.exe,
+            cwd: snapshot.cwd,
+            ports: snapshot.ports,
+        }
+    }
+}
+
+impl Filterable for Process {
+    fn value(&self, field: Field) -> Value<'_> {
+        let megabytes = |value: Option<f32>| value.map_or(Value::Missing, |mb| Value::Number(mb as f64 * 1024.0 * 1024.0));
+        match field {
+            Field::Pid => Value::Number(self.pid as f64),
+            Field::Ppid => self.ppid.map_or(Value::Missing, |ppid| Value::Number(ppid as f64)),
+            Field::Uid => self.uid.map_or(Value::Missing, |uid| Value::Number(uid as f64)),
+            Field::User => self.user.as_deref().map_or(Value::Missing, Value::Text),
+            Field::Cpu => Value::Number(self.cpu as f64),
+            Field::Mem => Value::Number(self.mem as f64 * 1024.0 * 1024.0),
+            Field::MemPercent => Value::Number(self.mem_percent as f64),
+            Field::Vsz => Value::Number(self.vsz as f64 * 1024.0 * 1024.0),
+            Field::Shared => megabytes(self.shared),
+            Field::Swap => megabytes(self.swap),
+            // Only known while the PSS or USS column is shown
+            Field::Pss => megabytes(self.pss),
+            Field::Uss => megabytes(self.uss),
+            Field::Cmd => Value::Text(&self.cmd),
+            Field::Cmdline => Value::Text(&self.cmdline),
+            Field::Exe => self.exe.as_deref().map_or(Value::Missing, Value::Text),
+            Field::Cwd => self.cwd.as_deref().map_or(Value::Missing, Value::Text),
+            Field::State => Value::State(self.process_state),
+            Field::Nice => Value::Number(self.priority as f64),
+            Field::Threads => Value::Number(self.threads as f64),
+            Field::Start => Value::Number(self.start_time as f64),
+            Field::Age => Value::Number(chrono::Utc::now().timestamp().saturating_sub(self.start_time as i64) as f64),
+            // IO counters are not kept in the table
+            Field::ReadBytes | Field::WriteBytes => Value::Missing,
+        }
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
@@ -54,6 +146,17 @@ pub enum BasicColumn {
     START,
     STATUS,
     PRIORITY,
+    CMDLINE,
+    EXE,
+    CWD,
+    THREADS,
+    PORTS,
+    MEMPCT,
+    PSS,
+    USS,
+    SHARED,
+    SWAP,
+    VSZ,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -62,17 +165,42 @@ enum FilterType {
     PPID,
     USER,
     STATUS,
+    PORT,
 }
 
-impl TableViewItem<BasicColumn> for Process {
-    fn to_column(&self, column: BasicColumn) -> String {
+impl Process {
+    /// The full command line, or the name in brackets like `ps` for kernel threads, which have none.
+    /// Tabs and newlines in arguments (`bash -c` scripts) would break the row, so they become spaces.
+    fn full_command(&self) -> String {
+        if self.cmdline.is_empty() {
+            format!("[{}]", self.cmd)
+        } else {
+            self.cmdline.replace(char::is_control, " ")
+        }
+    }
+
+    /// The text of a cell, before it is fitted to the column width
+    fn cell(&self, column: BasicColumn) -> String {
+        let megabytes = |value: Option<f32>| value.map_or("N/A".to_string(), |mb| format!("{:.2}", mb));
         match column {
+            BasicColumn::PID if is_marked(self.pid) => format!("* {}", self.pid),
             BasicColumn::PID => format!("{}", self.pid),
             BasicColumn::PPID => self.ppid.map_or("N/A".to_string(), |ppid| ppid.to_string()),
             BasicColumn::USER => self.user.clone().unwrap_or_else(|| "N/A".to_string()),
             BasicColumn::CPU => format!("{:.2}", self.cpu),
-            BasicColumn::MEM => format!("{:.2}", self.mem/1024.0),
-            BasicColumn::CMD => self.cmd.clone(),
+            BasicColumn::MEM => format!("{:.2}", self.mem),
+            BasicColumn::CMD => self.command(),
+            BasicColumn::CMDLINE => self.full_command(),
+            BasicColumn::EXE => self.exe.clone().unwrap_or_else(|| "N/A".to_string()),
+            BasicColumn::CWD => self.cwd.clone().unwrap_or_else(|| "N/A".to_string()),
+            BasicColumn::THREADS => format!("{}", self.threads),
+            BasicColumn::MEMPCT => format!("{:.2}", self.mem_percent),
+            BasicColumn::VSZ => format!("{:.2}", self.vsz),
+            BasicColumn::SHARED => megabytes(self.shared),
+            BasicColumn::SWAP => megabytes(self.swap),
+            BasicColumn::PSS => megabytes(self.pss),
+            BasicColumn::USS => megabytes(self.uss),
+            BasicColumn::PORTS => self.ports.iter().map(|port| port.to_string()).collect::<Vec<_>>().join(","),
             BasicColumn::START => {
                 // Convert start_time to a readable format
                 let datetime = chrono::DateTime::from_timestamp(self.start_time as i64, 0)
@@ -84,6 +212,86 @@ impl TableViewItem<BasicColumn> for Process {
         }
     }
 
+    /// What the CMD column shows, depending on the `l` toggle
+    fn command(&self) -> String {
+        if CMD_SHOWS_CMDLINE.load(AtomicOrdering::Relaxed) {
+            self.full_command()
+        } else {
+            self.cmd.clone()
+        }
+    }
+}
+
+/// The column for a config column name, with its header and alignment
+fn column_spec(name: &str) -> Option<(BasicColumn, &'static str, HAlign)> {
+    Some(match name {
+        "PID" => (BasicColumn::PID, "PID", HAlign::Right),
+        "PPID" => (BasicColumn::PPID, "PPID", HAlign::Right),
+        "USER" => (BasicColumn::USER, "OWNER", HAlign::Left),
+        "CPU" => (BasicColumn::CPU, "CPU %", HAlign::Right),
+        "MEM" => (BasicColumn::MEM, "RSS MB", HAlign::Right),
+        "MEM%" => (BasicColumn::MEMPCT, "MEM %", HAlign::Right),
+        "PSS" => (BasicColumn::PSS, "PSS MB", HAlign::Right),
+        "USS" => (BasicColumn::USS, "USS MB", HAlign::Right),
+        "SHARED" => (BasicColumn::SHARED, "SHR MB", HAlign::Right),
+        "SWAP" => (BasicColumn::SWAP, "SWAP MB", HAlign::Right),
+        "VSZ" => (BasicColumn::VSZ, "VSZ MB", HAlign::Right),
+        "NI" => (BasicColumn::PRIORITY, "PRI", HAlign::Right),
+        "CMD" => (BasicColumn::CMD, "CMD", HAlign::Right),
+        "CMDLINE" => (BasicColumn::CMDLINE, "COMMAND LINE", HAlign::Left),
+        "EXE" => (BasicColumn::EXE, "EXE", HAlign::Left),
+        "CWD" => (BasicColumn::CWD, "CWD", HAlign::Left),
+        "THREADS" => (BasicColumn::THREADS, "THR", HAlign::Right),
+        "PORTS" => (BasicColumn::PORTS, "PORTS", HAlign::Left),
+        "START" => (BasicColumn::START, "STARTED", HAlign::Left),
+        "STATUS" => (BasicColumn::STATUS, "STATE", HAlign::Left),
+        _ => return None,
+    })
+}
+
+fn column_width(column: BasicColumn) -> usize {
+    COLUMN_WIDTHS.lock().unwrap().get(&column).copied().unwrap_or(usize::MAX)
+}
+
+/// Replaces the table's columns with the configured ones and applies the configured sort
+fn configure_table(table: &mut TableView<Process, BasicColumn>, config: &Config, previous_columns: usize) {
+    for _ in 0..previous_columns {
+        table.remove_column(0);
+    }
+    let mut widths = COLUMN_WIDTHS.lock().unwrap();
+    widths.clear();
+    for column in &config.columns {
+        if let Some((basic, title, align)) = column_spec(&column.name) {
+            let width = column.width();
+            widths.insert(basic, width);
+            table.add_column(basic, title, |c| c.align(align).width(width));
+        }
+    }
+    drop(widths);
+    if let Some((basic, _, _)) = column_spec(&config.sort.column) {
+        let order = match config.sort.direction {
+            SortDirection::Ascending => Ordering::Less,
+            SortDirection::Descending => Ordering::Greater,
+        };
+        table.sort_by(basic, order);
+    }
+}
+
+// The table does not clip cells, so long text would spill into the next columns
+fn fit(text: String, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text;
+    }
+    let mut fitted: String = text.chars().take(width.saturating_sub(1)).collect();
+    fitted.push('…');
+    fitted
+}
+
+impl TableViewItem<BasicColumn> for Process {
+    fn to_column(&self, column: BasicColumn) -> String {
+        fit(self.cell(column), column_width(column))
+    }
+
     fn cmp(&self, other: &Self, column: BasicColumn) -> Ordering {
         match column {
             BasicColumn::PID => self.pid.cmp(&other.pid),
@@ -91,7 +299,18 @@ impl TableViewItem<BasicColumn> for Process {
             BasicColumn::USER => self.user.cmp(&other.user),
             BasicColumn::CPU => self.cpu.partial_cmp(&other.cpu).unwrap_or(Ordering::Equal),
             BasicColumn::MEM => self.mem.partial_cmp(&other.mem).unwrap_or(Ordering::Equal),
-            BasicColumn::CMD => self.cmd.cmp(&other.cmd),
+            BasicColumn::CMD => self.command().cmp(&other.command()),
+            BasicColumn::CMDLINE => self.full_command().cmp(&other.full_command()),
+            BasicColumn::EXE => self.exe.cmp(&other.exe),
+            BasicColumn::CWD => self.cwd.cmp(&other.cwd),
+            BasicColumn::THREADS => self.threads.cmp(&other.threads),
+            BasicColumn::PORTS => self.ports.cmp(&other.ports),
+            BasicColumn::MEMPCT => self.mem_percent.partial_cmp(&other.mem_percent).unwrap_or(Ordering::Equal),
+            BasicColumn::VSZ => self.vsz.partial_cmp(&other.vsz).unwrap_or(Ordering::Equal),
+            BasicColumn::SHARED => self.shared.partial_cmp(&other.shared).unwrap_or(Ordering::Equal),
+            BasicColumn::SWAP => self.swap.partial_cmp(&other.swap).unwrap_or(Ordering::Equal),
+            BasicColumn::PSS => self.pss.partial_cmp(&other.pss).unwrap_or(Ordering::Equal),
+            BasicColumn::USS => self.uss.partial_cmp(&other.uss).unwrap_or(Ordering::Equal),
             BasicColumn::START => self.start_time.cmp(&other.start_time),
             BasicColumn::STATUS => format!("{:?}", self.process_state).cmp(&format!("{:?}", other.process_state)),
             BasicColumn::PRIORITY => self.priority.cmp(&other.priority),
@@ -99,15 +318,24 @@ impl TableViewItem<BasicColumn> for Process {
     }
 }
 
+
 // Atomic flags as static variables
 static TUI_RUNNING: AtomicBool = AtomicBool::new(true);
 static UPDATES_PAUSED: AtomicBool = AtomicBool::new(false);
+// Whether the CMD column shows the full command line instead of the name
+static CMD_SHOWS_CMDLINE: AtomicBool = AtomicBool::new(false);
+// Time between table refreshes, from the config
+static REFRESH_INTERVAL_MS: AtomicU64 = AtomicU64::new(1000);
+// Most processes a batch action works on at once
+const BATCH_WORKERS: usize = 8;
 
 // Add filter state tracking
 #[derive(Clone)]
 struct FilterState {
     filter_type: Option<FilterType>,
     filter_value: String,
+    /// Set instead of `filter_type` when filtering with an expression
+    expression: Option<Filter>,
 }
 
 impl Default for FilterState {
@@ -115,6 +343,7 @@ impl Default for FilterState {
         FilterState {
             filter_type: None,
             filter_value: String::new(),
+            expression: None,
         }
     }
 }
@@ -122,7 +351,23 @@ impl Default for FilterState {
 // Create a singleton for the System and filter state
 lazy_static! {
     static ref SYSTEM: Mutex<System> = Mutex::new(System::new_all());
+    static ref SNAPSHOTTER: Mutex<Snapshotter> = Mutex::new(Snapshotter::new(snapshot::DEFAULT_INTERVAL));
     static ref CURRENT_FILTER: Mutex<FilterState> = Mutex::new(FilterState::default());
+    // Every process of the last refresh, unfiltered; the refresh thread keeps it up to date
+    static ref PROCESSES: Arc<Mutex<Vec<Process>>> = Arc::new(Mutex::new(Vec::new()));
+    // PIDs marked for batch actions
+    static ref MARKED: Mutex<HashSet<u32>> = Mutex::new(HashSet::new());
+    // What the `/` search bar holds; empty when not searching
+    static ref SEARCH: Mutex<String> = Mutex::new(String::new());
+    // Width of every shown column, for clipping cells
+    static ref COLUMN_WIDTHS: Mutex<HashMap<BasicColumn, usize>> = Mutex::new(HashMap::new());
+    // The settings in effect, as edited on the Setup screen
+    static ref CONFIG: Mutex<Config> = Mutex::new(Config::default());
+    // The tree shown by `t`, and the PIDs whose subtrees are folded in it
+    static ref TREE: Mutex<Vec<ProcessNode<Process>>> = Mutex::new(Vec::new());
+    static ref FOLDED: Mutex<HashSet<u32>> = Mutex::new(HashSet::new());
+    // Samples the threads shown in the thread view, while it is open
+    static ref THREAD_SAMPLER: Mutex<Option<ThreadSampler>> = Mutex::new(None);
 }
 
 // Add a static flag to track if the tree view is open
@@ -130,111 +375,632 @@ static TREE_VIEW_OPEN: AtomicBool = AtomicBool::new(false);
 
 fn act_on_selected_process<F>(siv: &mut Cursive, action: F, action_name: &str)
 where
-    F: Fn(&sysinfo::Process) -> bool + Send + 'static + Clone,
+    F: Fn(u32) -> Result<(), ActionError> + Send + Sync + 'static + Clone,
+{
+    if has_marks() {
+        confirm_batch(siv, action_name.to_string(), audited_batch_action(action_name, action));
+        return;
+    }
+    let selected = siv.find_name::<TableView<Process, BasicColumn>>("table").and_then(|table| {
+        let process = table.borrow_item(table.item()?)?;
+        Some((process.pid, process.cmd.clone()))
+    });
+    match selected {
+        Some((pid, cmd)) => act_on_process(siv, pid, cmd, action, action_name),
+        None => siv.add_layer(Dialog::info("No process selected. Please select a process first.")),
+    }
+}
+
+// Wraps an action for confirm_targets, recording every PID it runs on in the audit log
+fn audited_batch_action<F>(action_name: &str, action: F) -> impl Fn(u32) -> Result<String, String> + Send + Sync + 'static
+where
+    F: Fn(u32) -> Result<(), ActionError> + Send + Sync + 'static,
+{
+    let audit_action = action_name.to_lowercase();
+    move |pid| {
+        audit::audited(Source::Tui, pid, &audit_action, &[], false, || action(pid))
+            .map(|()| "ok".to_string())
+            .map_err(|e| e.to_string())
+    }
+}
+
+fn act_on_process<F>(siv: &mut Cursive, pid: u32, cmd: String, action: F, action_name: &str)
+where
+    F: Fn(u32) -> Result<(), ActionError> + Send + Sync + 'static + Clone,
 {
-    if let Some(table) = siv.find_name::<TableView<Process, BasicColumn>>("table") {
-        if let Some(selected_row) = table.item() {
-            if let Some(process) = table.borrow_item(selected_row) {
-                let pid = process.pid;
-                let cmd = process.cmd.clone();
-                let action_name = action_name.to_string();
+    let action_name = action_name.to_string();
+    
+    siv.add_layer(
+        Dialog::text(format!("Are you sure you want to {} process {} ({})?", action_name, pid, cmd))
+            .button("Yes", move |s| {
+                s.pop_layer();
+                let sink = s.cb_sink().clone();
+                let action_clone = action.clone();
+                let cmd_clone = cmd.clone();
+                let action_name_clone = action_name.clone();
                 
-                siv.add_layer(
-                    Dialog::text(format!("Are you sure you want to {} process {} ({})?", action_name, pid, cmd))
-                        .button("Yes", move |s| {
-                            s.pop_layer();
-                            let sink = s.cb_sink().clone();
-                            let action_clone = action.clone();
-                            let cmd_clone = cmd.clone();
-                            let action_name_clone = action_name.clone();
-                            
-                            thread::spawn(move || {
-                                let mut system = SYSTEM.lock().unwrap();
-                                system.refresh_processes_specifics(
-                                    sysinfo::ProcessesToUpdate::Some(&[Pid::from(pid as usize)]),
-                                    true,
-                                    sysinfo::ProcessRefreshKind::everything(),
-                                );
-
-                                if let Some(sys_proc) = system.process(Pid::from(pid as usize)) {
-                                    let result = action_clone(sys_proc);
-                                    let msg = if result {
-                                        format!("{} signal sent to PID {} ({})", action_name_clone, pid, cmd_clone)
-                                    } else {
-                                        format!("Failed to send {} signal to PID {} ({})", action_name_clone, pid, cmd_clone)
-                                    };
-                                    
-                                    thread::sleep(Duration::from_millis(100));
-                                    system.refresh_all();
-                                    drop(system);
-
-                                    let sink_clone = sink.clone();
-                                    sink.send(Box::new(move |s| {
-                                        s.add_layer(Dialog::info(msg).button("OK", |s| { s.pop_layer(); }));
-                                        
-                                        thread::spawn(move || {
-                                            thread::sleep(Duration::from_millis(200));
-                                            sink_clone.send(Box::new(move |s| {
-                                                if let Some(mut table_view) = s.find_name::<TableView<Process, BasicColumn>>("table") {
-                                                    table_view.set_items(get_processes());
-                                                }
-                                            })).ok();
-                                        });
-                                    })).ok();
-                                } else {
-                                    sink.send(Box::new(move |s| {
-                                        s.add_layer(Dialog::info(format!("Process {} ({}) not found.", pid, cmd_clone)));
-                                    })).ok();
-                                }
-                            });
-                        })
-                        .button("No", |s| { s.pop_layer(); })
-                );
+                thread::spawn(move || {
+                    let result = audit::audited(Source::Tui, pid, &action_name_clone.to_lowercase(), &[], false, || action_clone(pid));
+                    if let Err(ActionError::NotFound { .. }) = result {
+                        sink.send(Box::new(move |s| {
+                            s.add_layer(Dialog::info(format!("Process {} ({}) not found.", pid, cmd_clone)));
+                        })).ok();
+                        return;
+                    }
+                    let msg = match result {
+                        Ok(()) => format!("{} signal sent to PID {} ({})", action_name_clone, pid, cmd_clone),
+                        Err(e) => format!("Failed to send {} signal to PID {} ({}): {}", action_name_clone, pid, cmd_clone, e),
+                    };
+                    thread::sleep(Duration::from_millis(100));
+
+                    let sink_clone = sink.clone();
+                    sink.send(Box::new(move |s| {
+                        s.add_layer(Dialog::info(msg).button("OK", |s| { s.pop_layer(); }));
+                        
+                        thread::spawn(move || {
+                            thread::sleep(Duration::from_millis(200));
+                            sink_clone.send(Box::new(show_cached_processes)).ok();
+                        });
+                    })).ok();
+                });
+            })
+            .button("No", |s| { s.pop_layer(); })
+    );
+}
+
+fn search_query() -> String {
+    SEARCH.lock().unwrap().clone()
+}
+
+/// Where `query` matches `text`, as ranges of character indexes. A case-insensitive substring
+/// match wins; otherwise the query's characters must appear in order (`ffx` finds `firefox`).
+fn search_matches(text: &str, query: &str) -> Option<Vec<(usize, usize)>> {
+    let fold = |c: char| c.to_lowercase().next().unwrap_or(c);
+    let text: Vec<char> = text.chars().map(fold).collect();
+    let query: Vec<char> = query.chars().map(fold).collect();
+    if query.is_empty() || query.len() > text.len() {
+        return None;
+    }
+    if let Some(start) = text.windows(query.len()).position(|window| window == query.as_slice()) {
+        return Some(vec![(start, start + query.len())]);
+    }
+
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    let mut wanted = query.iter().peekable();
+    for (i, c) in text.iter().enumerate() {
+        if wanted.peek() == Some(&c) {
+            wanted.next();
+            match ranges.last_mut() {
+                Some(last) if last.1 == i => last.1 = i + 1,
+                _ => ranges.push((i, i + 1)),
             }
+        }
+    }
+    if wanted.peek().is_none() { Some(ranges) } else { None }
+}
+
+fn matches_search(process: &Process, query: &str) -> bool {
+    query.is_empty()
+        || search_matches(&process.cmd, query).is_some()
+        || search_matches(&process.cmdline, query).is_some()
+}
+
+/// Most characters of the current match's command shown next to the search bar
+const SEARCH_STATUS_TEXT_WIDTH: usize = 30;
+
+/// Up to `width` characters of `text` from a little before the first match, with the characters
+/// matching `query` in reverse video
+fn highlight_search(text: &str, query: &str, width: usize) -> StyledString {
+    let chars: Vec<char> = text.chars().collect();
+    let ranges = search_matches(text, query).unwrap_or_default();
+    let first = ranges.first().map_or(0, |&(start, _)| start);
+    let from = if chars.len() > width { first.saturating_sub(width / 4) } else { 0 };
+    let to = chars.len().min(from + width);
+    let collect = |start: usize, end: usize| chars[start.clamp(from, to)..end.clamp(from, to)].iter().collect::<String>();
+
+    let mut highlighted = StyledString::plain(if from > 0 { "…" } else { "" });
+    let mut done = from;
+    for (start, end) in ranges {
+        highlighted.append_plain(collect(done, start));
+        highlighted.append_styled(collect(start, end), Style::from(Effect::Reverse).combine(Effect::Bold));
+        done = end.max(from);
+    }
+    highlighted.append_plain(collect(done, to));
+    if to < chars.len() {
+        highlighted.append_plain("…");
+    }
+    highlighted
+}
+
+/// The processes the table should show: those passing the filter and the search
+fn visible_processes(processes: Vec<Process>) -> Vec<Process> {
+    let processes = {
+        let filter_state = CURRENT_FILTER.lock().unwrap();
+        if let Some(expression) = &filter_state.expression {
+            processes.into_iter().filter(|process| expression.matches(process)).collect()
+        } else if let Some(filter_type) = filter_state.filter_type {
+            filter_processes(&processes, filter_type, &filter_state.filter_value)
         } else {
-            siv.add_layer(Dialog::info("No process selected. Please select a process first."));
+            processes
         }
+    };
+    let query = search_query();
+    if query.is_empty() {
+        processes
+    } else {
+        processes.into_iter().filter(|process| matches_search(process, &query)).collect()
     }
 }
 
-// Helper functions for nice value management
-fn get_process_nice(pid: u32) -> Option<i32> {
-    match read_to_string(format!("/proc/{}/stat", pid)) {
-        Ok(contents) => {
-            let fields: Vec<&str> = contents.split_whitespace().collect();
-            fields.get(18).and_then(|&nice| nice.parse::<i32>().ok())
+// Shows the cached processes again through the current filter and search, e.g. after the filter
+// changed or an action ran; what the action changed comes in with the next refresh
+fn show_cached_processes(siv: &mut Cursive) {
+    let visible = visible_processes(PROCESSES.lock().unwrap().clone());
+    siv.call_on_name("table", |table: &mut TableView<Process, BasicColumn>| set_table_items(table, visible));
+}
+
+// Replaces the rows, keeping the selection on the same process when it is still shown
+fn set_table_items(table: &mut TableView<Process, BasicColumn>, processes: Vec<Process>) {
+    let selected_pid = table.item().and_then(|index| table.borrow_item(index)).map(|process| process.pid);
+    let selected = selected_pid.and_then(|pid| processes.iter().position(|process| process.pid == pid));
+    table.set_items(processes);
+    if let Some(index) = selected {
+        table.set_selected_item(index);
+    }
+}
+
+// Table cells are plain strings, so the table shows the current match as its selected row and the
+// status repeats that process's command with the matched characters highlighted
+fn update_search_status(siv: &mut Cursive) {
+    let query = search_query();
+    let status = siv
+        .call_on_name("table", |table: &mut TableView<Process, BasicColumn>| {
+            match (table.row(), table.len()) {
+                (_, 0) => StyledString::plain("no matches"),
+                (Some(row), count) => {
+                    let mut status = StyledString::plain(format!("{}/{} ", row + 1, count));
+                    let matched = table.item().and_then(|index| table.borrow_item(index)).and_then(|process| {
+                        [process.command(), process.full_command()]
+                            .into_iter()
+                            .find(|text| search_matches(text, &query).is_some())
+                    });
+                    if let Some(text) = matched {
+                        status.append(highlight_search(&text, &query, SEARCH_STATUS_TEXT_WIDTH));
+                    }
+                    status.append_plain(" (Down/Up: next/previous, Enter: go to, Esc: cancel)");
+                    status
+                }
+                (None, count) => StyledString::plain(format!("{} matches", count)),
+            }
+        })
+        .unwrap_or_default();
+    siv.call_on_name("search_status", |view: &mut TextView| view.set_content(status));
+}
+
+// Narrows the table as the query is typed; `processes` is the table's full, unfiltered list
+fn apply_search(siv: &mut Cursive, query: &str, processes: &Mutex<Vec<Process>>) {
+    *SEARCH.lock().unwrap() = query.to_string();
+    let visible = visible_processes(processes.lock().unwrap().clone());
+    siv.call_on_name("table", |table: &mut TableView<Process, BasicColumn>| {
+        table.set_items(visible);
+        table.set_selected_row(0);
+    });
+    update_search_status(siv);
+}
+
+fn open_search(siv: &mut Cursive) {
+    siv.call_on_name("search_bar", |view: &mut HideableView<LinearLayout>| view.unhide());
+    siv.focus_name("search_edit").ok();
+}
+
+// Enter leaves the selected match selected in the whole, unsearched table
+fn accept_search(siv: &mut Cursive, processes: &Mutex<Vec<Process>>) {
+    SEARCH.lock().unwrap().clear();
+    siv.call_on_name("search_edit", |view: &mut EditView| view.set_content(""));
+    let visible = visible_processes(processes.lock().unwrap().clone());
+    siv.call_on_name("table", |table: &mut TableView<Process, BasicColumn>| set_table_items(table, visible));
+    siv.call_on_name("search_bar", |view: &mut HideableView<LinearLayout>| view.hide());
+    siv.focus_name("table").ok();
+}
+
+fn close_search(siv: &mut Cursive, processes: &Mutex<Vec<Process>>) {
+    siv.call_on_name("search_edit", |view: &mut EditView| view.set_content(""));
+    apply_search(siv, "", processes);
+    siv.call_on_name("search_bar", |view: &mut HideableView<LinearLayout>| view.hide());
+    siv.focus_name("table").ok();
+}
+
+// Down/Up in the search bar move to the next or previous match, wrapping around
+fn step_search(siv: &mut Cursive, forward: bool) {
+    siv.call_on_name("table", |table: &mut TableView<Process, BasicColumn>| {
+        let count = table.len();
+        if count == 0 {
+            return;
+        }
+        let row = table.row().unwrap_or(0);
+        table.set_selected_row(if forward { (row + 1) % count } else { (row + count - 1) % count });
+    });
+    update_search_status(siv);
+}
+
+fn is_marked(pid: u32) -> bool {
+    MARKED.lock().unwrap().contains(&pid)
+}
+
+fn has_marks() -> bool {
+    !MARKED.lock().unwrap().is_empty()
+}
+
+/// Marked processes with their names, in PID order
+fn marked_targets() -> Vec<(u32, String)> {
+    let mut pids: Vec<u32> = MARKED.lock().unwrap().iter().copied().collect();
+    pids.sort_unstable();
+    pids.into_iter().map(|pid| (pid, actions::process_name(pid).unwrap_or_else(|| "?".to_string()))).collect()
+}
+
+// Space marks or unmarks the selected row and moves to the next one
+fn toggle_mark(siv: &mut Cursive) {
+    siv.call_on_name("table", |table: &mut TableView<Process, BasicColumn>| {
+        let Some(pid) = table.item().and_then(|index| table.borrow_item(index)).map(|process| process.pid) else {
+            return;
+        };
+        {
+            let mut marked = MARKED.lock().unwrap();
+            if !marked.remove(&pid) {
+                marked.insert(pid);
+            }
+        }
+        if let Some(row) = table.row() {
+            if row + 1 < table.len() {
+                table.set_selected_row(row + 1);
+            }
+        }
+    });
+}
+
+/// Marks every row the table shows, i.e. everything the current filter lets through
+fn mark_all_shown(siv: &mut Cursive) {
+    siv.call_on_name("table", |table: &mut TableView<Process, BasicColumn>| {
+        let pids: Vec<u32> = table.borrow_items().iter().map(|process| process.pid).collect();
+        MARKED.lock().unwrap().extend(pids);
+    });
+}
+
+/// Flips the mark of every row the table shows
+fn invert_marks(siv: &mut Cursive) {
+    siv.call_on_name("table", |table: &mut TableView<Process, BasicColumn>| {
+        let pids: Vec<u32> = table.borrow_items().iter().map(|process| process.pid).collect();
+        let mut marked = MARKED.lock().unwrap();
+        for pid in pids {
+            if !marked.remove(&pid) {
+                marked.insert(pid);
+            }
+        }
+    });
+}
+
+fn clear_marks(_siv: &mut Cursive) {
+    MARKED.lock().unwrap().clear();
+}
+
+// Marks the processes matching the same selectors the CLI actions take
+fn show_mark_matching_dialog(siv: &mut Cursive) {
+    let field = |label: &str, name: &str| {
+        LinearLayout::horizontal()
+            .child(TextView::new(format!("{:<22}", label)))
+            .child(EditView::new().with_name(name).fixed_width(30))
+    };
+    siv.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(TextView::new("Every field filled in must match; regexes for name and command line."))
+                .child(DummyView)
+                .child(field("Name:", "select_name"))
+                .child(field("Command line:", "select_cmdline"))
+                .child(field("User or uid:", "select_user"))
+                .child(field("Parent PID:", "select_ppid"))
+                .child(field("Subtree of PID:", "select_tree"))
+                .child(field("Older than (e.g. 2h):", "select_older_than"))
+                .child(field("Socket on port:", "select_port"))
+        )
+        .title("Mark Matching Processes")
+        .button("Mark", |s| {
+            let read = |s: &mut Cursive, name: &str| {
+                s.call_on_name(name, |view: &mut EditView| view.get_content().trim().to_string())
+                    .filter(|value| !value.is_empty())
+            };
+            let fields = [
+                read(s, "select_name"),
+                read(s, "select_cmdline"),
+                read(s, "select_user"),
+                read(s, "select_ppid"),
+                read(s, "select_tree"),
+                read(s, "select_older_than"),
+                read(s, "select_port"),
+            ];
+            match build_selector(fields) {
+                Ok(selector) if selector.is_empty() => {
+                    s.add_layer(Dialog::info("Fill in at least one field."));
+                }
+                Ok(selector) => {
+                    s.pop_layer();
+                    let sink = s.cb_sink().clone();
+                    // Taking a snapshot reads all of /proc, so keep it off the UI thread
+                    thread::spawn(move || {
+                        let processes = snapshot::take_snapshot(Duration::ZERO);
+                        let matched: Vec<u32> = selector.select(&processes).iter().map(|process| process.pid).collect();
+                        sink.send(Box::new(move |s| {
+                            let count = matched.len();
+                            MARKED.lock().unwrap().extend(matched);
+                            s.add_layer(Dialog::info(format!("Marked {} matching process(es).", count)));
+                            show_cached_processes(s);
+                        })).ok();
+                    });
+                }
+                Err(e) => {
+                    s.add_layer(Dialog::info(e));
+                }
+            }
+        })
+        .button("Cancel", |s| { s.pop_layer(); })
+    );
+}
+
+fn build_selector([name, cmdline, user, ppid, tree, older_than, port]: [Option<String>; 7]) -> Result<Selector, String> {
+    let regex = |value: Option<String>| value.map(|v| regex::Regex::new(&v)).transpose().map_err(|e| e.to_string());
+    let pid = |value: Option<String>| value.map(|v| v.parse::<u32>().map_err(|_| format!("{} is not a PID", v))).transpose();
+    Ok(Selector {
+        name: regex(name)?,
+        cmdline: regex(cmdline)?,
+        user,
+        ppid: pid(ppid)?,
+        tree: pid(tree)?,
+        older_than: older_than.map(|v| selector::parse_age(&v)).transpose()?,
+        port: port.map(|v| v.parse::<u16>().map_err(|_| format!("{} is not a port", v))).transpose()?,
+    })
+}
+
+/// Asks once before running `action` on every marked process, then lists the result for each
+fn confirm_batch<F>(siv: &mut Cursive, action_name: String, action: F)
+where
+    F: Fn(u32) -> Result<String, String> + Send + Sync + 'static,
+{
+    confirm_targets(siv, marked_targets(), "marked process(es)", action_name, action);
+}
+
+/// Asks once, then runs `action` on every target and shows a per-PID summary.
+/// `what` completes "Kill 3 ...", e.g. "marked process(es)".
+fn confirm_targets<F>(siv: &mut Cursive, targets: Vec<(u32, String)>, what: &str, action_name: String, action: F)
+where
+    F: Fn(u32) -> Result<String, String> + Send + Sync + 'static,
+{
+    let listed: Vec<String> = targets.iter().take(10).map(|(pid, name)| format!("  {} ({})", pid, name)).collect();
+    let more = if targets.len() > listed.len() { format!("\n  ...and {} more", targets.len() - listed.len()) } else { String::new() };
+    let action = Arc::new(action);
+    siv.add_layer(
+        Dialog::text(format!("{} {} {}?\n{}{}", action_name, targets.len(), what, listed.join("\n"), more))
+            .title("Confirm Batch Action")
+            .button("Yes", move |s| {
+                s.pop_layer();
+                let sink = s.cb_sink().clone();
+                let targets = targets.clone();
+                let action = Arc::clone(&action);
+                let action_name = action_name.clone();
+                thread::spawn(move || {
+                    let results = run_batch(&targets, action.as_ref());
+                    sink.send(Box::new(move |s| show_batch_summary(s, &action_name, &results))).ok();
+                });
+            })
+            .button("No", |s| { s.pop_layer(); })
+    );
+}
+
+/// Runs `action` on every target using a few worker threads, so slow ones (e.g. a graceful stop)
+/// run side by side. Results keep the order of `targets`; an action that panics counts as failed.
+fn run_batch<F>(targets: &[(u32, String)], action: &F) -> Vec<(u32, String, Result<String, String>)>
+where
+    F: Fn(u32) -> Result<String, String> + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let slots: Vec<Mutex<Option<Result<String, String>>>> = targets.iter().map(|_| Mutex::new(None)).collect();
+    let work = || loop {
+        let index = next.fetch_add(1, AtomicOrdering::SeqCst);
+        let Some((pid, _)) = targets.get(index) else { break };
+        let result = panic::catch_unwind(AssertUnwindSafe(|| action(*pid)))
+            .unwrap_or_else(|_| Err("the action panicked".to_string()));
+        *slots[index].lock().unwrap() = Some(result);
+    };
+    thread::scope(|scope| {
+        for _ in 1..BATCH_WORKERS.min(targets.len()) {
+            if thread::Builder::new().spawn_scoped(scope, work).is_err() {
+                break;
+            }
+        }
+        // This thread is a worker too, so the batch finishes even if no thread could be started
+        work();
+    });
+    targets
+        .iter()
+        .zip(slots)
+        .map(|((pid, name), slot)| (*pid, name.clone(), slot.into_inner().unwrap().unwrap_or_else(|| Err("not run".to_string()))))
+        .collect()
+}
+
+fn show_batch_summary(siv: &mut Cursive, action_name: &str, results: &[(u32, String, Result<String, String>)]) {
+    let succeeded = results.iter().filter(|(_, _, result)| result.is_ok()).count();
+    let mut text = StyledString::plain(format!("{}: {} of {} succeeded\n\n", action_name, succeeded, results.len()));
+    for (pid, name, result) in results {
+        text.append_plain(format!("{:>7} {:<20} ", pid, name));
+        match result {
+            Ok(message) => text.append(StyledString::styled(message.clone(), Color::Light(BaseColor::Green))),
+            Err(error) => text.append(StyledString::styled(format!("failed: {}", error), Color::Light(BaseColor::Red))),
+        }
+        text.append_plain("\n");
+    }
+    siv.add_layer(
+        Dialog::around(ScrollView::new(TextView::new(text)).max_height(20))
+            .title("Batch Results")
+            .button("OK", |s| { s.pop_layer(); })
+    );
+    show_cached_processes(siv);
+}
+
+// Lists every signal, with the graceful TERM-then-KILL stop first
+fn show_signal_picker(siv: &mut Cursive) {
+    let selected = siv.find_name::<TableView<Process, BasicColumn>>("table").and_then(|table| {
+        let row = table.item()?;
+        table.borrow_item(row).map(|process| (process.pid, process.cmd.clone()))
+    });
+    let batch = has_marks();
+    let Some((pid, cmd)) = selected.filter(|_| !batch).or(batch.then(|| (0, String::new()))) else {
+        siv.add_layer(Dialog::info("No process selected. Please select a process first."));
+        return;
+    };
+
+    let mut signals = SelectView::<Option<Signal>>::new();
+    signals.add_item("   Graceful stop: SIGTERM, then SIGKILL after the timeout", None);
+    for (signal, description) in actions::all_signals() {
+        signals.add_item(format!("{:>2} {:<10} {}", signal as i32, signal.as_str(), description), Some(signal));
+    }
+    let title = if batch {
+        format!("Send signal to {} marked processes", MARKED.lock().unwrap().len())
+    } else {
+        format!("Send signal to {} ({})", pid, cmd)
+    };
+    signals.set_on_submit(move |s, signal: &Option<Signal>| {
+        let timeout = s
+            .call_on_name("signal_timeout", |view: &mut EditView| view.get_content().trim().parse::<u64>().ok())
+            .flatten()
+            .map_or(actions::DEFAULT_TERM_TIMEOUT, Duration::from_secs);
+        s.pop_layer();
+        if batch {
+            let signal = *signal;
+            let name = signal.map_or_else(|| "Gracefully stop".to_string(), |signal| format!("Send {} to", signal.as_str()));
+            confirm_batch(s, name, move |pid| send_signal(pid, signal, timeout));
+        } else {
+            confirm_signal(s, pid, cmd.clone(), *signal, timeout);
+        }
+    });
+
+    let timeout = LinearLayout::horizontal()
+        .child(TextView::new("SIGKILL timeout (s): "))
+        .child(EditView::new().content(actions::DEFAULT_TERM_TIMEOUT.as_secs().to_string()).with_name("signal_timeout").fixed_width(6));
+    siv.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(ScrollView::new(signals).max_height(15))
+                .child(DummyView)
+                .child(timeout)
+        )
+        .title(title)
+        .button("Cancel", |s| { s.pop_layer(); })
+    );
+}
+
+/// Sends `signal`, or stops the process gracefully when it is `None`, and describes the outcome
+fn send_signal(pid: u32, signal: Option<Signal>, timeout: Duration) -> Result<String, String> {
+    let result = match signal {
+        Some(signal) => audit::audited(Source::Tui, pid, "signal", &[signal.as_str().to_string()], false, || {
+            actions::send(pid, signal, Duration::from_millis(500))
+        }),
+        None => {
+            let args = ["SIGTERM".to_string(), format!("SIGKILL after {}s", timeout.as_secs())];
+            audit::audited(Source::Tui, pid, "signal", &args, false, || actions::terminate(pid, timeout))
         }
-        Err(_) => None
+    };
+    result
+        .map(|outcome| {
+            format!(
+                "sent {}, {}",
+                outcome.signals.join(" then "),
+                if outcome.exited { "exited" } else { "still running" }
+            )
+        })
+        .map_err(|e| e.to_string())
+}
+
+fn confirm_signal(siv: &mut Cursive, pid: u32, cmd: String, signal: Option<Signal>, timeout: Duration) {
+    let description = match signal {
+        Some(signal) => signal.as_str().to_string(),
+        None => format!("SIGTERM (then SIGKILL after {}s)", timeout.as_secs()),
+    };
+    siv.add_layer(
+        Dialog::text(format!("Send {} to process {} ({})?", description, pid, cmd))
+            .button("Yes", move |s| {
+                s.pop_layer();
+                let sink = s.cb_sink().clone();
+                let cmd = cmd.clone();
+                thread::spawn(move || {
+                    let msg = match send_signal(pid, signal, timeout) {
+                        Ok(outcome) => format!("PID {} ({}): {}", pid, cmd, outcome),
+                        Err(e) => format!("Failed to signal PID {} ({}): {}", pid, cmd, e),
+                    };
+                    sink.send(Box::new(move |s| {
+                        s.add_layer(Dialog::info(msg));
+                        show_cached_processes(s);
+                    })).ok();
+                });
+            })
+            .button("No", |s| { s.pop_layer(); })
+    );
+}
+
+// Most recent audit log entries, newest first
+fn show_history_dialog(siv: &mut Cursive) {
+    let log = AuditLog::default();
+    let entries = log.read_all();
+    let mut text = StyledString::new();
+    if entries.is_empty() {
+        text.append_plain(format!("No actions recorded in {}", log.path().display()));
     }
+    for entry in entries.iter().rev().take(200) {
+        let result = match &entry.error {
+            Some(error) => StyledString::styled(format!("failed: {}", error), Color::Light(BaseColor::Red)),
+            None => StyledString::styled("ok", Color::Light(BaseColor::Green)),
+        };
+        text.append_plain(format!(
+            "{}  {}{} via {}  {} {} ({}){}  ",
+            entry.timestamp,
+            entry.user,
+            if entry.sudo { " (sudo)" } else { "" },
+            entry.source,
+            entry.action,
+            entry.pid,
+            entry.name.as_deref().unwrap_or("?"),
+            if entry.args.is_empty() { String::new() } else { format!(" {}", entry.args.join(" ")) },
+        ));
+        text.append(result);
+        text.append_plain("\n");
+    }
+    siv.add_layer(
+        Dialog::around(ScrollView::new(TextView::new(text)).max_height(20))
+            .title("Action History")
+            .button("Close", |s| { s.pop_layer(); })
+    );
+}
+
+// Helper functions for nice value management
+fn get_process_nice(pid: u32) -> Option<i32> {
+    snapshot::read_nice(pid)
 }
 
 fn get_current_user() -> Option<String> {
-    std::process::Command::new("whoami")
-        .output()
-        .ok()
-        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    let uid = nix::unistd::getuid().as_raw();
+    users::get_user_by_uid(uid).map(|user| user.name().to_string_lossy().into_owned())
 }
 
-fn needs_sudo(nice_value: i32, process_user: &Option<String>, current_nice: Option<i32>) -> bool {
+/// Whether setpriority(2) will refuse the change unless we run as root: lowering the nice value,
+/// or renicing another user's process. Only used to explain failures; the TUI never runs sudo.
+fn needs_root(nice_value: i32, process_user: &Option<String>, current_nice: Option<i32>) -> bool {
     let current_user = get_current_user();
     nice_value < 0 || 
         process_user.as_ref().map(|u| Some(u) != current_user.as_ref()).unwrap_or(true) ||
         current_nice.map(|n| nice_value < n).unwrap_or(false)
 }
 
-fn execute_renice(pid: u32, nice_value: i32, needs_sudo: bool) -> std::io::Result<std::process::Output> {
-    let mut cmd = if needs_sudo {
-        let mut c = std::process::Command::new("sudo");
-        c.arg("renice");
-        c
-    } else {
-        std::process::Command::new("renice")
-    };
-    
-    cmd.arg(format!("{}", nice_value))
-        .arg("-p")
-        .arg(format!("{}", pid))
-        .output()
+fn execute_renice(pid: u32, nice_value: i32) -> Result<(), String> {
+    audit::audited(Source::Tui, pid, "renice", &[nice_value.to_string()], false, || actions::renice(pid, nice_value))
+        .map_err(|e| e.to_string())
 }
 
 fn create_nice_values_list() -> Vec<(String, i32)> {
@@ -252,146 +1018,154 @@ fn handle_priority_change(
     cmd: &str,
     nice_value: i32,
     current_nice: Option<i32>,
-    needs_sudo: bool
+    needs_root: bool
 ) {
-    match execute_renice(pid, nice_value, needs_sudo) {
-        Ok(output) if output.status.success() => {
+    match execute_renice(pid, nice_value) {
+        Ok(()) => {
             thread::sleep(Duration::from_millis(100));
             match get_process_nice(pid) {
                 Some(new_nice) if new_nice == nice_value => {
-                    show_success_dialog(s, current_nice.unwrap_or(0), new_nice, pid, cmd, needs_sudo);
+                    show_success_dialog(s, current_nice.unwrap_or(0), new_nice, pid, cmd);
                 }
                 Some(new_nice) => {
-                    show_verification_failed_dialog(s, current_nice.unwrap_or(0), nice_value, new_nice, needs_sudo);
+                    show_verification_failed_dialog(s, current_nice.unwrap_or(0), nice_value, new_nice, needs_root);
                 }
                 None => {
                     s.add_layer(Dialog::info("Failed to verify new nice value").button("OK", |s| { s.pop_layer(); }));
                 }
             }
         }
-        Ok(output) => {
-            show_error_dialog(s, &String::from_utf8_lossy(&output.stderr), needs_sudo);
-        }
         Err(e) => {
-            show_error_dialog(s, &e.to_string(), needs_sudo);
+            show_error_dialog(s, &e, needs_root);
         }
     }
 }
 
-fn show_success_dialog(s: &mut Cursive, old_nice: i32, new_nice: i32, pid: u32, cmd: &str, used_sudo: bool) {
+fn show_success_dialog(s: &mut Cursive, old_nice: i32, new_nice: i32, pid: u32, cmd: &str) {
     s.add_layer(Dialog::info(format!(
-        "Priority successfully changed from {} to {} for process {} ({})\nUsed {} renice",
-        old_nice, new_nice, pid, cmd,
-        if used_sudo { "sudo" } else { "regular" }
+        "Priority successfully changed from {} to {} for process {} ({})",
+        old_nice, new_nice, pid, cmd
     )).button("OK", |s| {
         s.pop_layer();
-        if let Some(mut table_view) = s.find_name::<TableView<Process, BasicColumn>>("table") {
-            let updated_processes = get_processes();
-            table_view.set_items(updated_processes);
-        }
+        show_cached_processes(s);
     }));
 }
 
-fn show_verification_failed_dialog(s: &mut Cursive, old_nice: i32, requested_nice: i32, current_nice: i32, needs_sudo: bool) {
+fn show_verification_failed_dialog(s: &mut Cursive, old_nice: i32, requested_nice: i32, current_nice: i32, needs_root: bool) {
     s.add_layer(Dialog::info(format!(
         "Priority change verification failed.\nPrevious: {}\nRequested: {}\nCurrent: {}\n{}",
         old_nice, requested_nice, current_nice,
-        if needs_sudo && current_nice != requested_nice {
-            "This might be due to insufficient permissions. Try with sudo."
+        if needs_root && current_nice != requested_nice {
+            "This might be due to insufficient permissions. Try running procmanager as root."
         } else {
             "The change was not applied as expected."
         }
     )).button("OK", |s| { s.pop_layer(); }));
 }
 
-fn show_error_dialog(s: &mut Cursive, error_msg: &str, needs_sudo: bool) {
+fn show_error_dialog(s: &mut Cursive, error_msg: &str, needs_root: bool) {
     s.add_layer(Dialog::info(format!(
         "Failed to change priority: {}\nNote: {} privileges are required for this operation.",
         error_msg,
-        if needs_sudo { "Root" } else { "Sufficient" }
+        if needs_root { "Root" } else { "Sufficient" }
     )).button("OK", |s| { s.pop_layer(); }));
 }
 
 fn renice_process(siv: &mut Cursive) {
-    if let Some(table) = siv.find_name::<TableView<Process, BasicColumn>>("table") {
-        if let Some(selected_row) = table.item() {
-            if let Some(process) = table.borrow_item(selected_row) {
-                let pid = process.pid;
-                let cmd = process.cmd.clone();
-                let process_user = process.user.clone();
-                let current_nice = get_process_nice(pid);
-                
-                let dialog = Dialog::around(
-                    LinearLayout::vertical()
-                        .child(TextView::new(format!(
-                            "Change priority for process {} ({}) owned by {}\nCurrent nice value: {}", 
-                            pid, cmd, process_user.as_ref().map(|s| s.as_str()).unwrap_or("unknown"),
-                            current_nice.unwrap_or(0))))
-                        .child(DummyView)
-                        .child(TextView::new("Select nice value:"))
-                        .child(ScrollView::new(
-                            SelectView::new()
-                                .with_all(create_nice_values_list())
-                                .on_submit({
-                                    let process_user = process_user.clone();
-                                    let cmd = cmd.clone();
-                                    move |s, &nice_value| {
-                                        let needs_sudo = needs_sudo(nice_value, &process_user, current_nice);
-                                        handle_priority_change(s, pid, &cmd, nice_value, current_nice, needs_sudo);
-                                    }
-                                })
-                        ).fixed_height(15))
-                )
-                .title("Change Process Priority")
-                .button("Cancel", |s| { s.pop_layer(); });
-                
-                siv.add_layer(dialog);
-            }
-        } else {
-            siv.add_layer(Dialog::info("No process selected. Please select a process first."));
-        }
+    if has_marks() {
+        renice_marked(siv);
+        return;
+    }
+    let selected = siv.find_name::<TableView<Process, BasicColumn>>("table").and_then(|table| {
+        let process = table.borrow_item(table.item()?)?;
+        Some((process.pid, process.cmd.clone(), process.user.clone()))
+    });
+    match selected {
+        Some((pid, cmd, process_user)) => renice_one(siv, pid, cmd, process_user),
+        None => siv.add_layer(Dialog::info("No process selected. Please select a process first.")),
     }
 }
 
-fn get_processes() -> Vec<Process> {
-    let mut system = SYSTEM.lock().unwrap();
-    system.refresh_all();
+fn renice_one(siv: &mut Cursive, pid: u32, cmd: String, process_user: Option<String>) {
+    let current_nice = get_process_nice(pid);
+    
+    let dialog = Dialog::around(
+        LinearLayout::vertical()
+            .child(TextView::new(format!(
+                "Change priority for process {} ({}) owned by {}\nCurrent nice value: {}", 
+                pid, cmd, process_user.as_ref().map(|s| s.as_str()).unwrap_or("unknown"),
+                current_nice.unwrap_or(0))))
+            .child(DummyView)
+            .child(TextView::new("Select nice value:"))
+            .child(ScrollView::new(
+                SelectView::new()
+                    .with_all(create_nice_values_list())
+                    .on_submit({
+                        let process_user = process_user.clone();
+                        let cmd = cmd.clone();
+                        move |s, &nice_value| {
+                            let needs_root = needs_root(nice_value, &process_user, current_nice);
+                            handle_priority_change(s, pid, &cmd, nice_value, current_nice, needs_root);
+                        }
+                    })
+            ).fixed_height(15))
+    )
+    .title("Change Process Priority")
+    .button("Cancel", |s| { s.pop_layer(); });
+    
+    siv.add_layer(dialog);
+}
 
-    let processes: Vec<Process> = system
-        .processes()
-        .iter()
-        .map(|(pid, process)| {
-            let ppid = process.parent().map(|p| p.as_u32());
-            
-            let user = match process.user_id() {
-                Some(uid) => {
-                    let uid_value = **uid;
-                    match users::get_user_by_uid(uid_value) {
-                        Some(user) => Some(user.name().to_string_lossy().into_owned()),
-                        None => Some(format!("uid:{}", uid_value))
-                    }
-                },
-                None => Some("unknown".to_string())
-            };
+fn process_owner(pid: u32) -> Option<String> {
+    let uid = ProcFs::new().read_status(pid)?.ruid?;
+    users::get_user_by_uid(uid).map(|user| user.name().to_string_lossy().into_owned())
+}
 
-            // Get the nice value directly from /proc/[pid]/stat
-            let priority = get_process_nice(pid.as_u32()).unwrap_or(0);
-            
-            Process {
-                pid: pid.as_u32(),
-                ppid,
-                user,
-                cpu: process.cpu_usage(),
-                mem: process.memory() as f32 / 1024.0,
-                cmd: process.name().to_string_lossy().into_owned(),
-                start_time: process.start_time(),
-                process_state: process.status(),
-                priority,
-            }
-        })
-        .collect();
+fn renice_marked(siv: &mut Cursive) {
+    let count = MARKED.lock().unwrap().len();
+    renice_targets(siv, marked_targets(), "marked process(es)".to_string(), format!("Change Priority of {} Marked Processes", count));
+}
+
+fn renice_targets(siv: &mut Cursive, targets: Vec<(u32, String)>, what: String, title: String) {
+    siv.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(TextView::new("Select nice value:"))
+                .child(ScrollView::new(
+                    SelectView::new()
+                        .with_all(create_nice_values_list())
+                        .on_submit(move |s, &nice_value: &i32| {
+                            s.pop_layer();
+                            confirm_targets(s, targets.clone(), &what, format!("Renice to {}", nice_value), move |pid| {
+                                execute_renice(pid, nice_value).map(|()| format!("nice {}", nice_value))
+                            });
+                        })
+                ).fixed_height(15))
+        )
+        .title(title)
+        .button("Cancel", |s| { s.pop_layer(); })
+    );
+}
 
-    processes
+fn get_snapshots() -> Vec<ProcessSnapshot> {
+    // Finding listening ports reads every process's descriptors and PSS/USS make the kernel walk every
+    // mapping, so each is only looked up while its column is shown or a filter uses it
+    let (ports_needed, proportional_needed) = {
+        let columns = COLUMN_WIDTHS.lock().unwrap();
+        let filter = CURRENT_FILTER.lock().unwrap();
+        let expression_uses = |field| filter.expression.as_ref().is_some_and(|expression| expression.uses(field));
+        (
+            columns.contains_key(&BasicColumn::PORTS) || filter.filter_type == Some(FilterType::PORT),
+            columns.contains_key(&BasicColumn::PSS)
+                || columns.contains_key(&BasicColumn::USS)
+                || expression_uses(Field::Pss)
+                || expression_uses(Field::Uss),
+        )
+    };
+    let mut snapshotter = SNAPSHOTTER.lock().unwrap();
+    snapshotter.set_listening_ports(ports_needed);
+    snapshotter.set_proportional_memory(proportional_needed);
+    snapshotter.refresh()
 }
 
 #[cfg(target_os = "linux")]
@@ -490,35 +1264,9 @@ fn get_cpu_name() -> String {
         .unwrap_or_else(|| "Unknown CPU".to_string())
 }
 
-// Add this new struct and function after get_cpu_name
-#[derive(Clone)]
-struct SystemInfo {
-    cpu_usage: f32,
-    total_memory: f32,
-    used_memory: f32,
-    available_memory: f32,
-    swap: f32,
-    uptime: u64,
-    process_count: usize,
-    physical_cores: usize,
-    logical_cores: usize,
-}
-
 fn get_system_metrics() -> SystemInfo {
     let mut system = SYSTEM.lock().unwrap();
-    system.refresh_all();
-
-    SystemInfo {
-        cpu_usage: system.cpus().iter().map(|cpu| cpu.cpu_usage()).sum::<f32>() / system.cpus().len() as f32,
-        total_memory: system.total_memory() as f32 / 1024.0 / 1024.0,
-        used_memory: system.used_memory() as f32 / 1024.0 / 1024.0,
-        available_memory: system.available_memory() as f32 / 1024.0 / 1024.0,
-        swap: system.total_swap() as f32 / 1024.0 / 1024.0,
-        uptime: System::uptime(),
-        process_count: system.processes().len(),
-        physical_cores: num_cpus::get_physical(),
-        logical_cores: num_cpus::get(),
-    }
+    system::get_system_metrics(&mut system)
 }
 
 fn format_system_info() -> String {
@@ -553,9 +1301,9 @@ fn format_system_info() -> String {
         sys_info.cpu_usage,
         sys_info.physical_cores,
         sys_info.logical_cores,
-        sys_info.total_memory,
-        sys_info.used_memory,
-        sys_info.available_memory,
+        sys_info.total_memory / 1024.0,
+        sys_info.used_memory / 1024.0,
+        sys_info.available_memory / 1024.0,
         (sys_info.used_memory / sys_info.total_memory) * 100.0,
         sys_info.process_count,
         sys_info.uptime / 86400,
@@ -623,16 +1371,26 @@ fn get_keybindings_bar() -> StyledString {
     bar.append_plain("   ");
     bar.append(key("Kill <k>"));
     bar.append_plain("   ");
-    bar.append(key("Pause <P>"));
+    bar.append(key("Pause <p>"));
+    bar.append_plain("   ");
+    bar.append(key("Resume <r>"));
     bar.append_plain("   ");
-    bar.append(key("Resume <R>"));
+    bar.append(key("Signal <x>"));
     bar.append_plain("   ");
-    bar.append(key("System Info <S>"));
+    bar.append(key("Mark <Space>"));
+    bar.append_plain("   ");
+    bar.append(key("System Info <s>"));
     bar.append_plain("   ");
     bar.append(key("Filter <f>"));
     bar.append_plain("   ");
+    bar.append(key("Search </>"));
+    bar.append_plain("   ");
     bar.append(key("Change Nice <n>"));
     bar.append_plain("   ");
+    bar.append(key("History <a>"));
+    bar.append_plain("   ");
+    bar.append(key("Setup <F2>"));
+    bar.append_plain("   ");
     bar.append(key("Help <h>"));
     bar.append_plain(" ┃");
 
@@ -652,93 +1410,294 @@ fn custom_theme() -> Theme {
     theme
 }
 
-#[derive(Clone)]
-struct ProcessNode {
-    process: Process,
-    children: Vec<ProcessNode>,
+fn configured_theme(theme: config::Theme) -> Theme {
+    let mut palette_theme = custom_theme();
+    let palette = &mut palette_theme.palette;
+    match theme {
+        config::Theme::Magenta => {}
+        config::Theme::Blue => {
+            palette[PaletteColor::TitlePrimary] = Color::Light(BaseColor::Cyan);
+            palette[PaletteColor::Highlight] = Color::Dark(BaseColor::Blue);
+            palette[PaletteColor::Secondary] = Color::Dark(BaseColor::Cyan);
+        }
+        config::Theme::Light => {
+            palette[PaletteColor::Background] = Color::Light(BaseColor::White);
+            palette[PaletteColor::View] = Color::Light(BaseColor::White);
+            palette[PaletteColor::Primary] = Color::Dark(BaseColor::Black);
+            palette[PaletteColor::TitlePrimary] = Color::Dark(BaseColor::Blue);
+            palette[PaletteColor::Highlight] = Color::Dark(BaseColor::Blue);
+            palette[PaletteColor::Secondary] = Color::Dark(BaseColor::Blue);
+        }
+        config::Theme::Monochrome => {
+            palette[PaletteColor::TitlePrimary] = Color::Light(BaseColor::White);
+            palette[PaletteColor::Highlight] = Color::Light(BaseColor::White);
+            palette[PaletteColor::HighlightText] = Color::Dark(BaseColor::Black);
+            palette[PaletteColor::Secondary] = Color::Light(BaseColor::White);
+        }
+    }
+    palette_theme
 }
 
-fn build_process_tree(processes: &[Process]) -> Vec<ProcessNode> {
-    // Create a map of pid to process
-    let mut pid_map: std::collections::HashMap<u32, Vec<Process>> = std::collections::HashMap::new();
-    
-    // Group processes by their parent pid
-    for process in processes {
-        let ppid = process.ppid.unwrap_or(0);
-        pid_map.entry(ppid).or_default().push(process.clone());
+impl TreeItem for Process {
+    fn pid(&self) -> u32 {
+        self.pid
     }
-    
-    // Build tree starting from root processes (those with ppid 0 or 1)
-    let root_processes = pid_map.remove(&0).unwrap_or_default();
-    let mut tree = Vec::new();
-    
-    for process in root_processes {
-        tree.push(build_process_subtree(process, &mut pid_map));
+
+    fn ppid(&self) -> Option<u32> {
+        self.ppid
     }
-    
-    // Handle any remaining processes (in case of orphaned processes)
-    for (_, remaining_processes) in pid_map.iter() {
-        for process in remaining_processes {
-            tree.push(ProcessNode {
-                process: process.clone(),
-                children: Vec::new(),
-            });
+}
+
+/// A column of the tree view: one of the table's, or a total over the subtree
+#[derive(Copy, Clone, PartialEq)]
+enum TreeColumn {
+    Basic(BasicColumn),
+    SubtreeCpu,
+    SubtreeMem,
+}
+
+/// A tree column with its header, alignment and width
+type TreeColumnSpec = (TreeColumn, &'static str, HAlign, usize);
+
+// The command column needs room for the branches as well as the name
+const MIN_TREE_COLUMN_WIDTH: usize = 40;
+
+/// The table's columns with the subtree totals appended, and the index of the column
+/// that holds the branches (the first of CMD and CMDLINE; CMD is added when neither is shown)
+fn tree_columns() -> (Vec<TreeColumnSpec>, usize) {
+    let mut columns: Vec<_> = CONFIG.lock().unwrap().columns.iter()
+        .filter_map(|column| {
+            let (basic, title, align) = column_spec(&column.name)?;
+            Some((TreeColumn::Basic(basic), title, align, column.width()))
+        })
+        .collect();
+    let is_command = |column: &TreeColumn| matches!(column, TreeColumn::Basic(BasicColumn::CMD | BasicColumn::CMDLINE));
+    let tree_index = match columns.iter().position(|(column, ..)| is_command(column)) {
+        Some(index) => index,
+        None => {
+            columns.push((TreeColumn::Basic(BasicColumn::CMD), "CMD", HAlign::Left, MIN_TREE_COLUMN_WIDTH));
+            columns.len() - 1
         }
+    };
+    columns[tree_index].2 = HAlign::Left;
+    columns[tree_index].3 = columns[tree_index].3.max(MIN_TREE_COLUMN_WIDTH);
+    columns.push((TreeColumn::SubtreeCpu, "TREE CPU %", HAlign::Right, 10));
+    columns.push((TreeColumn::SubtreeMem, "TREE MEM", HAlign::Right, 10));
+    (columns, tree_index)
+}
+
+fn pad(text: String, width: usize, align: HAlign) -> String {
+    match align {
+        HAlign::Right => format!("{:>width$}", fit(text, width), width = width),
+        _ => format!("{:<width$}", fit(text, width), width = width),
     }
-    
-    tree
 }
 
-fn build_process_subtree(process: Process, pid_map: &mut std::collections::HashMap<u32, Vec<Process>>) -> ProcessNode {
-    let mut node = ProcessNode {
-        process,
-        children: Vec::new(),
+/// CPU % and memory (MB) of a process and everything below it
+fn subtree_totals(node: &ProcessNode<Process>) -> (f32, f32) {
+    node.children.iter().map(subtree_totals).fold((node.process.cpu, node.process.mem), |(cpu, mem), (child_cpu, child_mem)| {
+        (cpu + child_cpu, mem + child_mem)
+    })
+}
+
+fn find_node(nodes: &[ProcessNode<Process>], pid: u32) -> Option<&ProcessNode<Process>> {
+    nodes.iter().find_map(|node| if node.process.pid == pid { Some(node) } else { find_node(&node.children, pid) })
+}
+
+/// One row per node that is not inside a folded subtree, labelled with its cells
+fn tree_rows(
+    nodes: &[ProcessNode<Process>],
+    prefix: &str,
+    columns: &[TreeColumnSpec],
+    tree_index: usize,
+    folded: &HashSet<u32>,
+    rows: &mut Vec<(String, u32)>,
+) {
+    for (i, node) in nodes.iter().enumerate() {
+        let is_last = i == nodes.len() - 1;
+        let is_folded = folded.contains(&node.process.pid);
+        let marker = if node.children.is_empty() { "── " } else if is_folded { "[+] " } else { "[-] " };
+        let branch = format!("{}{}{}", prefix, if is_last { "└─" } else { "├─" }, marker);
+        let (cpu, mem) = subtree_totals(node);
+        let cells: Vec<String> = columns.iter().enumerate().map(|(index, &(column, _, align, width))| {
+            let text = match column {
+                TreeColumn::Basic(basic) if index == tree_index => format!("{}{}", branch, node.process.cell(basic)),
+                TreeColumn::Basic(basic) => node.process.cell(basic),
+                TreeColumn::SubtreeCpu => format!("{:.2}", cpu),
+                TreeColumn::SubtreeMem => format!("{:.2}", mem),
+            };
+            pad(text, width, align)
+        }).collect();
+        rows.push((cells.join(" ┆ "), node.process.pid));
+        if !is_folded {
+            let child_prefix = format!("{}{}", prefix, if is_last { "  " } else { "│ " });
+            tree_rows(&node.children, &child_prefix, columns, tree_index, folded, rows);
+        }
+    }
+}
+
+/// Redraws the tree view from `TREE`, keeping the selected process selected
+fn render_tree(siv: &mut Cursive) {
+    let (columns, tree_index) = tree_columns();
+    let header: Vec<String> = columns.iter().map(|&(_, title, align, width)| pad(title.to_string(), width, align)).collect();
+    let mut rows = Vec::new();
+    tree_rows(&TREE.lock().unwrap(), "", &columns, tree_index, &FOLDED.lock().unwrap(), &mut rows);
+
+    siv.call_on_name("tree_header", |view: &mut TextView| view.set_content(header.join(" ┆ ")));
+    siv.call_on_name("tree_select", |select: &mut SelectView<u32>| {
+        let selected_pid = select.selection().map(|pid| *pid);
+        let selected_index = select.selected_id().unwrap_or(0);
+        select.clear();
+        let count = rows.len();
+        for (label, pid) in rows {
+            select.add_item(label, pid);
+        }
+        // Stay on the same process, or near where it was when it exited
+        let index = selected_pid
+            .and_then(|pid| select.iter().position(|(_, row_pid)| *row_pid == pid))
+            .unwrap_or_else(|| selected_index.min(count.saturating_sub(1)));
+        select.set_selection(index);
+    });
+}
+
+fn refresh_tree_view(siv: &mut Cursive, processes: &[Process]) {
+    *TREE.lock().unwrap() = build_process_tree(processes);
+    render_tree(siv);
+}
+
+fn selected_tree_pid(siv: &mut Cursive) -> Option<u32> {
+    siv.find_name::<SelectView<u32>>("tree_select")?.selection().map(|pid| *pid)
+}
+
+fn select_tree_pid(siv: &mut Cursive, pid: u32) {
+    siv.call_on_name("tree_select", |select: &mut SelectView<u32>| {
+        let index = select.iter().position(|(_, row_pid)| *row_pid == pid);
+        if let Some(index) = index {
+            select.set_selection(index);
+        }
+    });
+    siv.call_on_name("tree_scroll", |scroll: &mut ScrollView<NamedView<SelectView<u32>>>| scroll.scroll_to_important_area());
+}
+
+/// Folds (`Some(true)`), unfolds (`Some(false)`) or toggles (`None`) the selected subtree
+fn fold_selected(siv: &mut Cursive, fold: Option<bool>) {
+    let Some(pid) = selected_tree_pid(siv) else { return };
+    {
+        let mut folded = FOLDED.lock().unwrap();
+        let fold = fold.unwrap_or(!folded.contains(&pid));
+        if fold {
+            folded.insert(pid);
+        } else {
+            folded.remove(&pid);
+        }
+    }
+    render_tree(siv);
+}
+
+/// Left folds the selected subtree; on a folded subtree or a leaf it moves to the parent instead
+fn fold_or_select_parent(siv: &mut Cursive) {
+    let Some(pid) = selected_tree_pid(siv) else { return };
+    let parent = {
+        let tree = TREE.lock().unwrap();
+        let Some(node) = find_node(&tree, pid) else { return };
+        if !node.children.is_empty() && !FOLDED.lock().unwrap().contains(&pid) {
+            None
+        } else {
+            node.process.ppid.filter(|ppid| find_node(&tree, *ppid).is_some())
+        }
     };
-    
-    if let Some(children) = pid_map.remove(&node.process.pid) {
-        for child in children {
-            node.children.push(build_process_subtree(child, pid_map));
+    match parent {
+        Some(ppid) => select_tree_pid(siv, ppid),
+        None => fold_selected(siv, Some(true)),
+    }
+}
+
+fn fold_all(siv: &mut Cursive, fold: bool) {
+    {
+        let mut folded = FOLDED.lock().unwrap();
+        folded.clear();
+        if fold {
+            fn collect(nodes: &[ProcessNode<Process>], folded: &mut HashSet<u32>) {
+                for node in nodes.iter().filter(|node| !node.children.is_empty()) {
+                    folded.insert(node.process.pid);
+                    collect(&node.children, folded);
+                }
+            }
+            collect(&TREE.lock().unwrap(), &mut folded);
         }
-        // Sort children by PID
-        node.children.sort_by_key(|child| child.process.pid);
     }
-    
-    node
+    render_tree(siv);
 }
 
-fn format_process_tree(node: &ProcessNode, prefix: &str, is_last: bool, output: &mut String) {
-    let branch = if is_last { "└── " } else { "├── " };
-    let next_prefix = if is_last { "    " } else { "│   " };
-    
-    // Format current process - only show name and PID
-    output.push_str(&format!("{}{}{} ({})\n", 
-        prefix, 
-        branch, 
-        node.process.cmd,
-        node.process.pid
-    ));
-    
-    // Format children
-    for (i, child) in node.children.iter().enumerate() {
-        let is_last_child = i == node.children.len() - 1;
-        format_process_tree(child, &format!("{}{}", prefix, next_prefix), is_last_child, output);
+fn selected_tree_process(siv: &mut Cursive) -> Option<(u32, String)> {
+    let pid = selected_tree_pid(siv)?;
+    find_node(&TREE.lock().unwrap(), pid).map(|node| (pid, node.process.cmd.clone()))
+}
+
+/// `pid` and every process below it, leaving out procmanager itself
+fn subtree_targets(pid: u32) -> Vec<(u32, String)> {
+    let tree = TREE.lock().unwrap();
+    let Some(node) = find_node(&tree, pid) else { return Vec::new() };
+    let own_pid = std::process::id();
+    subtree_pids(node)
+        .into_iter()
+        .filter(|target| *target != own_pid)
+        .filter_map(|target| find_node(std::slice::from_ref(node), target).map(|n| (target, n.process.cmd.clone())))
+        .collect()
+}
+
+fn act_on_tree<F>(siv: &mut Cursive, action: F, action_name: &str, subtree: bool)
+where
+    F: Fn(u32) -> Result<(), ActionError> + Send + Sync + 'static + Clone,
+{
+    let Some((pid, cmd)) = selected_tree_process(siv) else { return };
+    if subtree {
+        let what = format!("process(es) in the subtree of {} ({})", pid, cmd);
+        confirm_targets(siv, subtree_targets(pid), &what, action_name.to_string(), audited_batch_action(action_name, action));
+    } else {
+        act_on_process(siv, pid, cmd, action, action_name);
+    }
+}
+
+fn renice_tree(siv: &mut Cursive, subtree: bool) {
+    let Some((pid, cmd)) = selected_tree_process(siv) else { return };
+    if subtree {
+        let what = format!("process(es) in the subtree of {} ({})", pid, cmd);
+        renice_targets(siv, subtree_targets(pid), what, format!("Change Priority of the Subtree of {} ({})", pid, cmd));
+    } else {
+        renice_one(siv, pid, cmd, process_owner(pid));
     }
 }
 
 fn show_process_tree_fullscreen(siv: &mut Cursive) {
-    let processes = get_processes();
-    let tree = build_process_tree(&processes);
-    let mut tree_text = String::new();
-    for (i, root) in tree.iter().enumerate() {
-        format_process_tree(root, "", i == tree.len() - 1, &mut tree_text);
-    }
-    let tree_view = ScrollView::new(
-        TextView::new(tree_text)
-            .with_name("tree_content")
-            .full_width()
-    ).full_screen();
-    siv.add_fullscreen_layer(tree_view.with_name("tree_layer"));
+    let select = SelectView::<u32>::new()
+        .on_submit(|s, _| fold_selected(s, None))
+        .with_name("tree_select");
+    let tree_view = OnEventView::new(ScrollView::new(select).with_name("tree_scroll").full_height())
+        .on_pre_event(Key::Left, fold_or_select_parent)
+        .on_pre_event(Key::Right, |s| fold_selected(s, Some(false)))
+        .on_event(' ', |s| fold_selected(s, None))
+        .on_event('+', |s| fold_all(s, false))
+        .on_event('-', |s| fold_all(s, true))
+        .on_event('k', |s| act_on_tree(s, actions::kill, "Kill", false))
+        .on_event('K', |s| act_on_tree(s, actions::kill, "Kill", true))
+        .on_event('p', |s| act_on_tree(s, actions::pause, "Pause", false))
+        .on_event('P', |s| act_on_tree(s, actions::pause, "Pause", true))
+        .on_event('r', |s| act_on_tree(s, actions::resume, "Resume", false))
+        .on_event('R', |s| act_on_tree(s, actions::resume, "Resume", true))
+        .on_event('n', |s| renice_tree(s, false))
+        .on_event('N', |s| renice_tree(s, true))
+        .on_event(Key::Esc, close_process_tree_fullscreen);
+    let layout = LinearLayout::vertical()
+        .child(TextView::new("").with_name("tree_header"))
+        .child(tree_view)
+        .child(TextView::new(
+            "Space/Enter/←/→ fold   +/- unfold/fold all   k/p/r/n kill/pause/resume/renice   K/P/R/N the whole subtree   Esc/t close",
+        ));
+    siv.add_fullscreen_layer(Dialog::around(layout).title("Process Tree").with_name("tree_layer").full_screen());
     TREE_VIEW_OPEN.store(true, AtomicOrdering::SeqCst);
+    let processes = PROCESSES.lock().unwrap().clone();
+    refresh_tree_view(siv, &processes);
 }
 
 fn close_process_tree_fullscreen(siv: &mut Cursive) {
@@ -747,9 +1706,10 @@ fn close_process_tree_fullscreen(siv: &mut Cursive) {
     TREE_VIEW_OPEN.store(false, AtomicOrdering::SeqCst);
 }
 
-pub fn display_tui(columns_to_display: Vec<String>, _initial_processes: Vec<Process>) {
+pub fn display_tui(config: Config, config_path: PathBuf, _initial_processes: Vec<Process>) {
     TUI_RUNNING.store(true, AtomicOrdering::SeqCst);
     UPDATES_PAUSED.store(false, AtomicOrdering::SeqCst);
+    REFRESH_INTERVAL_MS.store(config.refresh_interval().as_millis() as u64, AtomicOrdering::SeqCst);
     
     {
         let mut system = SYSTEM.lock().unwrap();
@@ -757,16 +1717,40 @@ pub fn display_tui(columns_to_display: Vec<String>, _initial_processes: Vec<Proc
         thread::sleep(Duration::from_millis(250));
     }
     
-    let initial_accurate_processes = get_processes();
-    let processes = Arc::new(Mutex::new(initial_accurate_processes));
+    let initial_snapshots = get_snapshots();
+    let initial_accurate_processes: Vec<Process> = initial_snapshots.iter().cloned().map(Process::from).collect();
+    *PROCESSES.lock().unwrap() = initial_accurate_processes;
+    let processes = Arc::clone(&PROCESSES);
     let mut siv = CursiveBackend::default();
-    let theme = custom_theme();
+    let theme = configured_theme(config.theme);
     siv.set_theme(theme);
 
     // Get terminal width
     let width = siv.screen_size().x.max(80);
     // Top bar
     let sysinfo_block = TextView::new(get_system_info_block(width)).with_name("sysinfo_block").fixed_height(4);
+    // `/` search bar, above the bottom bar while searching
+    let search_processes = Arc::clone(&processes);
+    let edit_processes = Arc::clone(&processes);
+    let submit_processes = Arc::clone(&processes);
+    let search_edit = OnEventView::new(
+        EditView::new()
+            .on_edit(move |s, query, _| apply_search(s, query, &edit_processes))
+            .on_submit(move |s, _| accept_search(s, &submit_processes))
+            .with_name("search_edit")
+            .full_width(),
+    )
+    .on_pre_event(Key::Down, |s| step_search(s, true))
+    .on_pre_event(Key::Up, |s| step_search(s, false))
+    .on_event(Key::Esc, move |s| close_search(s, &search_processes));
+    let search_bar = HideableView::new(
+        LinearLayout::horizontal()
+            .child(TextView::new("Search: "))
+            .child(search_edit)
+            .child(TextView::new("").with_name("search_status")),
+    )
+    .hidden()
+    .with_name("search_bar");
     // Bottom bar
     let bottom_bar = TextView::new(get_keybindings_bar()).with_name("bottom_bar").fixed_height(1);
 
@@ -794,43 +1778,21 @@ pub fn display_tui(columns_to_display: Vec<String>, _initial_processes: Vec<Proc
         .on_sort(|_siv, _column, _order| {
         });
 
-    for col_name in columns_to_display {
-        match col_name.as_str() {
-            "PID" => table = table.column(BasicColumn::PID, "PID", |c| c.align(HAlign::Right).width(10)),
-            "PPID" => table = table.column(BasicColumn::PPID, "PPID", |c| c.align(HAlign::Right).width(10)),
-            "USER" => table = table.column(BasicColumn::USER, "OWNER", |c| c.align(HAlign::Left).width(10)),
-            "CPU" => table = table.column(BasicColumn::CPU, "CPU %", |c| c.width(10).align(HAlign::Right)),
-            "MEM" => table = table.column(BasicColumn::MEM, "MEM %", |c| c.width(10).align(HAlign::Right)),
-            "NI" => table = table.column(BasicColumn::PRIORITY, "PRI", |c| c.align(HAlign::Right).width(10)),
-            "CMD" => table = table.column(BasicColumn::CMD, "CMD", |c| c.align(HAlign::Right).width(30)),
-            "START" => table = table.column(BasicColumn::START, "STARTED", |c| c.align(HAlign::Left).width(20)),
-            "STATUS" => table = table.column(BasicColumn::STATUS, "STATE", |c| c.align(HAlign::Left).width(15)),
-            _ => println!("Invalid column: {}", col_name),
-        }
-    }
+    configure_table(&mut table, &config, 0);
+    *CONFIG.lock().unwrap() = config;
 
     {
         let processes_guard = processes.lock().unwrap();
-        let mut sorted_processes = processes_guard.clone();
-        sorted_processes.sort_by(|a, b| b.cpu.partial_cmp(&a.cpu).unwrap_or(Ordering::Equal));
-        table.set_items(sorted_processes);
+        table.set_items(processes_guard.clone());
     }
     
-    table.sort_by(BasicColumn::CPU, Ordering::Greater);
-    
-    table.set_on_submit(|siv, row, _| {
-        if let Some(process) = siv.find_name::<TableView<Process, BasicColumn>>("table")
+    table.set_on_submit(|siv, _row, index| {
+        let process = siv.find_name::<TableView<Process, BasicColumn>>("table")
             .unwrap()
-            .borrow_item(row) 
-        {
-            siv.add_layer(
-                Dialog::around(TextView::new(format!(
-                    "PID: {}\nCommand: {}\nCPU Usage: {:.2}%\nMemory: {:.2} MB\nStatus: {:?}\nNice Value: {}",
-                    process.pid, process.cmd, process.cpu, process.mem, process.process_state, process.priority
-                )))
-                .title("Process Details")
-                .button("Close", |s| { s.pop_layer(); })
-            );
+            .borrow_item(index)
+            .cloned();
+        if let Some(process) = process {
+            show_process_details(siv, &process);
         }
     });
 
@@ -841,6 +1803,7 @@ pub fn display_tui(columns_to_display: Vec<String>, _initial_processes: Vec<Proc
     let main_layout = LinearLayout::vertical()
         .child(sysinfo_block)
         .child(scrollable_table)
+        .child(search_bar)
         .child(bottom_bar);
 
     siv.add_fullscreen_layer(main_layout);
@@ -853,12 +1816,20 @@ pub fn display_tui(columns_to_display: Vec<String>, _initial_processes: Vec<Proc
                  - 'u' to toggle updates (pause/resume)\n\
                  - 'q' to quit\n\
                  - 's' to show system information\n\
-                 - 'K' to kill the selected process\n\
-                 - 'P' to pause the selected process\n\
-                 - 'R' to resume the selected process\n\
-                 - 'N' to change process priority (nice value)\n\
+                 - 'k' to kill the selected process\n\
+                 - 'p' to pause the selected process\n\
+                 - 'r' to resume the selected process\n\
+                 - 'x' to send any signal, or stop gracefully (TERM, then KILL)\n\
+                 - Space to mark/unmark a process, 'm' to mark all shown,\n   'i' to invert marks, 'c' to clear them, 'M' to mark by pattern\n\
+                 - With processes marked, k/p/r/n/x act on all of them\n\
+                 - 'n' to change process priority (nice value)\n\
                  - 'f' to filter/clear filter processes\n\
-                 - 't' to show process tree\n\
+                 - 'l' to switch CMD between the name and the full command line\n\
+                 - Enter to show every detail of the selected process, and from there\n   its threads, which can be reniced (n) or signalled (x) one by one,\n   and its open files and sockets\n\
+                 - '/' to search names and command lines as you type; Down/Up\n   step through the matches, Enter selects one in the table, Esc cancels\n\
+                 - 't' to show the process tree: fold subtrees with Space or\n   the arrows, k/p/r/n act on a process, K/P/R/N on its subtree\n\
+                 - 'a' to browse the history of actions taken\n\
+                 - F2 to set up columns, sort order, refresh interval and theme\n\
                  - 'h' for help"
             ))
             .title("Help")
@@ -867,22 +1838,50 @@ pub fn display_tui(columns_to_display: Vec<String>, _initial_processes: Vec<Proc
     });
     // Kill process
 siv.add_global_callback('k', |s| {
-    act_on_selected_process(s, |proc| proc.kill(), "Kill");
+    act_on_selected_process(s, actions::kill, "Kill");
 });
 
 // Pause process (SIGSTOP)
 siv.add_global_callback('p', |s| {
-    act_on_selected_process(s, |proc| proc.kill_with(Signal::Stop).unwrap_or(false), "Pause");
+    act_on_selected_process(s, actions::pause, "Pause");
 });
 
 // Resume process (SIGCONT)
 siv.add_global_callback('r', |s| {
-    act_on_selected_process(s, |proc| proc.kill_with(Signal::Continue).unwrap_or(false), "Resume");
+    act_on_selected_process(s, actions::resume, "Resume");
 });
 
 // Add this near the other key bindings in display_tui
-siv.add_global_callback('n', |s| {
-    renice_process(s);
+siv.add_global_callback('n', renice_process);
+
+siv.add_global_callback('/', open_search);
+
+siv.add_global_callback(Key::F2, move |s| {
+    show_setup_dialog(s, config_path.clone());
+});
+
+// Switch CMD between the process name and the full command line
+siv.add_global_callback('l', |s| {
+    CMD_SHOWS_CMDLINE.fetch_xor(true, AtomicOrdering::Relaxed);
+    if let Some(mut table) = s.find_name::<TableView<Process, BasicColumn>>("table") {
+        if let Some((column, order)) = table.order() {
+            table.sort_by(column, order);
+        }
+    }
+});
+
+siv.add_global_callback(' ', toggle_mark);
+siv.add_global_callback('m', mark_all_shown);
+siv.add_global_callback('i', invert_marks);
+siv.add_global_callback('c', clear_marks);
+siv.add_global_callback('M', show_mark_matching_dialog);
+
+siv.add_global_callback('x', |s| {
+    show_signal_picker(s);
+});
+
+siv.add_global_callback('a', |s| {
+    show_history_dialog(s);
 });
 
     // Add the 't' key binding for process tree
@@ -898,7 +1897,7 @@ siv.add_global_callback('n', |s| {
     siv.add_global_callback('f', |s| {
         let has_filter = {
             let filter_state = CURRENT_FILTER.lock().unwrap();
-            filter_state.filter_type.is_some()
+            filter_state.filter_type.is_some() || filter_state.expression.is_some()
         };
         
         if has_filter {
@@ -912,30 +1911,43 @@ siv.add_global_callback('n', |s| {
     let sink = siv.cb_sink().clone();
     
     thread::spawn(move || {
+        let mut previous_snapshots = initial_snapshots;
         while TUI_RUNNING.load(AtomicOrdering::SeqCst) {
-            thread::sleep(Duration::from_secs(1));
+            thread::sleep(Duration::from_millis(REFRESH_INTERVAL_MS.load(AtomicOrdering::SeqCst)));
             if !UPDATES_PAUSED.load(AtomicOrdering::SeqCst) {
-                let updated_processes = get_processes();
-                {
+                // Only patch the processes that were added, removed or changed since the last refresh
+                let snapshots = get_snapshots();
+                let changes = delta::diff(&previous_snapshots, &snapshots);
+                previous_snapshots = snapshots;
+                let table_changed = !changes.is_empty();
+                if table_changed {
                     let mut processes_guard = processes_clone.lock().unwrap();
-                    *processes_guard = updated_processes;
+                    changes.apply(&mut processes_guard);
+                    // A mark belongs to the process, not to a later one reusing its PID
+                    let mut marked = MARKED.lock().unwrap();
+                    for pid in &changes.removed {
+                        marked.remove(pid);
+                    }
                 }
+                let threads = THREAD_SAMPLER.lock().unwrap().as_mut().map(|sampler| sampler.refresh());
                 let processes_for_closure = Arc::clone(&processes_clone);
                 sink.send(Box::new(move |s| {
-                    if let Some(mut table_view) = s.find_name::<TableView<Process, BasicColumn>>("table") {
+                    if let Some(threads) = threads {
+                        refresh_thread_view(s, threads);
+                    }
+                    let table = if table_changed { s.find_name::<TableView<Process, BasicColumn>>("table") } else { None };
+                    if let Some(mut table_view) = table {
                         let current_processes = processes_for_closure.lock().unwrap().clone();
                         
-                        // Apply current filter if one exists
-                        let filtered_processes = {
-                            let filter_state = CURRENT_FILTER.lock().unwrap();
-                            if let Some(filter_type) = filter_state.filter_type {
-                                filter_processes(&current_processes, filter_type, &filter_state.filter_value)
-                            } else {
-                                current_processes
-                            }
-                        };
-                        
-                        table_view.set_items(filtered_processes);
+                        // Apply the current filter and search, if any
+                        set_table_items(&mut table_view, visible_processes(current_processes));
+                    }
+                    if table_changed && TREE_VIEW_OPEN.load(AtomicOrdering::SeqCst) {
+                        let current_processes = processes_for_closure.lock().unwrap().clone();
+                        refresh_tree_view(s, &current_processes);
+                    }
+                    if !search_query().is_empty() {
+                        update_search_status(s);
                     }
                     // Update system info bar
                     let width = s.screen_size().x.max(80);
@@ -953,6 +1965,712 @@ siv.add_global_callback('n', |s| {
     thread::sleep(Duration::from_millis(100));
 }
 
+fn show_process_details(siv: &mut Cursive, process: &Process) {
+    let or_na = |value: &Option<String>| value.clone().unwrap_or_else(|| "N/A".to_string());
+    let started = chrono::DateTime::from_timestamp(process.start_time as i64, 0)
+        .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| "N/A".to_string());
+    let age = chrono::Utc::now().timestamp().saturating_sub(process.start_time as i64).max(0);
+    let megabytes = |value: Option<f32>| value.map_or("N/A".to_string(), |mb| format!("{:.2}", mb));
+    // The table only has PSS and USS while their columns are shown, so read them for the dialog
+    let proportional = match ProcFs::new().read_smaps_rollup(process.pid) {
+        Some(rollup) => format!("{:.2} / {:.2} MB", rollup.pss_kb as f64 / 1024.0, rollup.uss_kb() as f64 / 1024.0),
+        None => "N/A (needs the owner's or root's privileges)".to_string(),
+    };
+    let details = format!(
+        "PID: {}\n\
+         Parent PID: {}\n\
+         User: {} (uid {})\n\
+         Name: {}\n\
+         Command line: {}\n\
+         Executable: {}\n\
+         Working directory: {}\n\
+         State: {}\n\
+         Nice value: {}\n\
+         Threads: {}\n\
+         CPU usage: {:.2}%\n\
+         Memory (RSS): {:.2} MB ({:.2}% of RAM)\n\
+         Memory (PSS / USS): {}\n\
+         Shared / swap / virtual: {} / {} / {:.2} MB\n\
+         Started: {} ({}d {:02}h {:02}m ago)",
+        process.pid,
+        process.ppid.map_or("N/A".to_string(), |ppid| ppid.to_string()),
+        or_na(&process.user),
+        process.uid.map_or("N/A".to_string(), |uid| uid.to_string()),
+        process.cmd,
+        process.full_command(),
+        or_na(&process.exe),
+        or_na(&process.cwd),
+        process.process_state,
+        process.priority,
+        process.threads,
+        process.cpu,
+        process.mem,
+        process.mem_percent,
+        proportional,
+        megabytes(process.shared),
+        megabytes(process.swap),
+        process.vsz,
+        started,
+        age / 86400,
+        age % 86400 / 3600,
+        age % 3600 / 60,
+    );
+
+    let (pid, cmd) = (process.pid, process.cmd.clone());
+    siv.add_layer(
+        Dialog::around(ScrollView::new(TextView::new(details)).max_width(100).max_height(30))
+            .title("Process Details")
+            .button("Threads", {
+                let cmd = cmd.clone();
+                move |s| show_thread_view(s, pid, cmd.clone())
+            })
+            .button("Files", {
+                let cmd = cmd.clone();
+                move |s| show_files_view(s, pid, cmd.clone())
+            })
+            .button("Memory Map", move |s| show_maps_view(s, pid, cmd.clone(), false))
+            .button("Close", |s| { s.pop_layer(); })
+    );
+}
+
+/// A row of the thread view
+#[derive(Clone)]
+struct Thread(ThreadSnapshot);
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum ThreadColumn {
+    Tid,
+    Name,
+    Cpu,
+    State,
+    LastCpu,
+    Nice,
+    Voluntary,
+    Involuntary,
+}
+
+impl TableViewItem<ThreadColumn> for Thread {
+    fn to_column(&self, column: ThreadColumn) -> String {
+        let thread = &self.0;
+        let switches = |count: Option<u64>| count.map_or("N/A".to_string(), |count| count.to_string());
+        match column {
+            ThreadColumn::Tid => thread.tid.to_string(),
+            ThreadColumn::Name => fit(thread.name.clone(), 18),
+            ThreadColumn::Cpu => format!("{:.2}", thread.cpu),
+            ThreadColumn::State => thread.state.to_string(),
+            ThreadColumn::LastCpu => thread.last_cpu.to_string(),
+            ThreadColumn::Nice => thread.nice.to_string(),
+            ThreadColumn::Voluntary => switches(thread.voluntary_ctxt_switches),
+            ThreadColumn::Involuntary => switches(thread.nonvoluntary_ctxt_switches),
+        }
+    }
+
+    fn cmp(&self, other: &Self, column: ThreadColumn) -> Ordering {
+        let (a, b) = (&self.0, &other.0);
+        match column {
+            ThreadColumn::Tid => a.tid.cmp(&b.tid),
+            ThreadColumn::Name => a.name.cmp(&b.name),
+            ThreadColumn::Cpu => a.cpu.partial_cmp(&b.cpu).unwrap_or(Ordering::Equal),
+            ThreadColumn::State => a.state.to_string().cmp(&b.state.to_string()),
+            ThreadColumn::LastCpu => a.last_cpu.cmp(&b.last_cpu),
+            ThreadColumn::Nice => a.nice.cmp(&b.nice),
+            ThreadColumn::Voluntary => a.voluntary_ctxt_switches.cmp(&b.voluntary_ctxt_switches),
+            ThreadColumn::Involuntary => a.nonvoluntary_ctxt_switches.cmp(&b.nonvoluntary_ctxt_switches),
+        }
+    }
+}
+
+/// Lists the threads of a process; the update thread keeps it refreshed while it is open
+fn show_thread_view(siv: &mut Cursive, pid: u32, cmd: String) {
+    let mut sampler = ThreadSampler::new(pid, Duration::from_millis(250));
+    let Some(threads) = sampler.refresh() else {
+        siv.add_layer(Dialog::info(format!("Process {} ({}) not found.", pid, cmd)));
+        return;
+    };
+
+    let mut table = TableView::<Thread, ThreadColumn>::new()
+        .column(ThreadColumn::Tid, "TID", |c| c.align(HAlign::Right).width(8))
+        .column(ThreadColumn::Name, "NAME", |c| c.width(18))
+        .column(ThreadColumn::Cpu, "CPU %", |c| c.align(HAlign::Right).width(8))
+        .column(ThreadColumn::State, "STATE", |c| c.width(12))
+        .column(ThreadColumn::LastCpu, "LAST CPU", |c| c.align(HAlign::Right).width(9))
+        .column(ThreadColumn::Nice, "NI", |c| c.align(HAlign::Right).width(4))
+        .column(ThreadColumn::Voluntary, "VOL CS", |c| c.align(HAlign::Right).width(10))
+        .column(ThreadColumn::Involuntary, "INVOL CS", |c| c.align(HAlign::Right).width(10));
+    table.sort_by(ThreadColumn::Cpu, Ordering::Greater);
+    table.set_items(threads.into_iter().map(Thread).collect());
+    *THREAD_SAMPLER.lock().unwrap() = Some(sampler);
+
+    let view = OnEventView::new(table.with_name("thread_table").min_size((100, 15)))
+        .on_event('n', renice_selected_thread)
+        .on_event('x', show_thread_signal_picker)
+        .on_event(Key::Esc, close_thread_view);
+    siv.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(view)
+                .child(TextView::new("n renice the thread   x send it a signal   Esc close")),
+        )
+        .title(format!("Threads of {} ({})", pid, cmd))
+        .button("Close", close_thread_view)
+        .with_name("thread_dialog"),
+    );
+}
+
+fn close_thread_view(siv: &mut Cursive) {
+    *THREAD_SAMPLER.lock().unwrap() = None;
+    siv.pop_layer();
+}
+
+/// Shows a refresh from the update thread; `None` means the process has exited
+fn refresh_thread_view(siv: &mut Cursive, threads: Option<Vec<ThreadSnapshot>>) {
+    let Some(threads) = threads else {
+        *THREAD_SAMPLER.lock().unwrap() = None;
+        siv.call_on_name("thread_dialog", |dialog: &mut Dialog| {
+            let title = format!("{} (exited)", dialog.get_title());
+            dialog.set_title(title);
+        });
+        return;
+    };
+    if let Some(mut table) = siv.find_name::<TableView<Thread, ThreadColumn>>("thread_table") {
+        let selected_tid = table.item().and_then(|index| table.borrow_item(index)).map(|thread| thread.0.tid);
+        table.set_items(threads.into_iter().map(Thread).collect());
+        let selected = selected_tid.and_then(|tid| table.borrow_items().iter().position(|thread| thread.0.tid == tid));
+        if let Some(index) = selected {
+            table.set_selected_item(index);
+        }
+    }
+}
+
+fn selected_thread(siv: &mut Cursive) -> Option<ThreadSnapshot> {
+    let table = siv.find_name::<TableView<Thread, ThreadColumn>>("thread_table")?;
+    table.borrow_item(table.item()?).map(|thread| thread.0.clone())
+}
+
+// setpriority(2) on a TID changes only that thread, so the process renice dialog works as is
+fn renice_selected_thread(siv: &mut Cursive) {
+    if let Some(thread) = selected_thread(siv) {
+        let label = format!("thread {} of PID {}", thread.name, thread.pid);
+        renice_one(siv, thread.tid, label, process_owner(thread.pid));
+    }
+}
+
+fn show_thread_signal_picker(siv: &mut Cursive) {
+    let Some(thread) = selected_thread(siv) else { return };
+    let mut signals = SelectView::<Signal>::new();
+    for (signal, description) in actions::all_signals() {
+        signals.add_item(format!("{:>2} {:<10} {}", signal as i32, signal.as_str(), description), signal);
+    }
+    let title = format!("Send signal to thread {} ({})", thread.tid, thread.name);
+    signals.set_on_submit(move |s, signal: &Signal| {
+        s.pop_layer();
+        confirm_thread_signal(s, thread.clone(), *signal);
+    });
+    siv.add_layer(
+        Dialog::around(ScrollView::new(signals).max_height(15))
+            .title(title)
+            .button("Cancel", |s| { s.pop_layer(); })
+    );
+}
+
+fn confirm_thread_signal(siv: &mut Cursive, thread: ThreadSnapshot, signal: Signal) {
+    siv.add_layer(
+        Dialog::text(format!(
+            "Send {} to thread {} ({}) of process {}?\nStop, continue and fatal signals still act on the whole process.",
+            signal.as_str(), thread.tid, thread.name, thread.pid
+        ))
+        .button("Yes", move |s| {
+            s.pop_layer();
+            let args = [signal.as_str().to_string(), format!("thread of PID {}", thread.pid)];
+            let result = audit::audited(Source::Tui, thread.tid, "signal", &args, false, || {
+                actions::signal_thread(thread.pid, thread.tid, signal)
+            });
+            let msg = match result {
+                Ok(()) => format!("Sent {} to thread {} ({})", signal.as_str(), thread.tid, thread.name),
+                Err(e) => format!("Failed to signal thread {} ({}): {}", thread.tid, thread.name, e),
+            };
+            s.add_layer(Dialog::info(msg));
+        })
+        .button("No", |s| { s.pop_layer(); })
+    );
+}
+
+/// A row of the open files view
+#[derive(Clone)]
+struct FileRow(OpenFile);
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum FileColumn {
+    Fd,
+    Kind,
+    Target,
+    Protocol,
+    Local,
+    Remote,
+    State,
+}
+
+impl FileRow {
+    fn socket_field(&self, field: impl Fn(&Socket) -> String) -> String {
+        self.0.socket.as_ref().map_or(String::new(), field)
+    }
+}
+
+impl TableViewItem<FileColumn> for FileRow {
+    fn to_column(&self, column: FileColumn) -> String {
+        match column {
+            FileColumn::Fd => self.0.fd.to_string(),
+            FileColumn::Kind => self.0.kind.name().to_string(),
+            FileColumn::Target => fit(self.0.target.clone(), 40),
+            FileColumn::Protocol => self.socket_field(|socket| socket.protocol.to_string()),
+            FileColumn::Local => fit(self.socket_field(|socket| socket.local.clone()), 24),
+            FileColumn::Remote => fit(self.socket_field(|socket| socket.remote.clone()), 24),
+            FileColumn::State => self.socket_field(|socket| socket.state.clone()),
+        }
+    }
+
+    fn cmp(&self, other: &Self, column: FileColumn) -> Ordering {
+        match column {
+            FileColumn::Fd => self.0.fd.cmp(&other.0.fd),
+            FileColumn::Kind => self.0.kind.name().cmp(other.0.kind.name()),
+            FileColumn::Target => self.0.target.cmp(&other.0.target),
+            FileColumn::Protocol => self.socket_field(|s| s.protocol.to_string()).cmp(&other.socket_field(|s| s.protocol.to_string())),
+            FileColumn::Local => self.socket_field(|s| s.local.clone()).cmp(&other.socket_field(|s| s.local.clone())),
+            FileColumn::Remote => self.socket_field(|s| s.remote.clone()).cmp(&other.socket_field(|s| s.remote.clone())),
+            FileColumn::State => self.socket_field(|s| s.state.clone()).cmp(&other.socket_field(|s| s.state.clone())),
+        }
+    }
+}
+
+/// The open files of `pid` as table rows, or why they cannot be read
+fn file_rows(pid: u32) -> Result<Vec<FileRow>, String> {
+    match files::open_files(&ProcFs::new(), pid) {
+        Ok(files) => Ok(files.into_iter().map(FileRow).collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(format!("Process {} not found.", pid)),
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            Err(format!("Permission denied reading the open files of PID {}.", pid))
+        }
+        Err(e) => Err(format!("Failed to read the open files of PID {}: {}", pid, e)),
+    }
+}
+
+/// Lists the descriptors of a process, with the addresses and state of its sockets
+fn show_files_view(siv: &mut Cursive, pid: u32, cmd: String) {
+    let rows = match file_rows(pid) {
+        Ok(rows) => rows,
+        Err(e) => {
+            siv.add_layer(Dialog::info(e));
+            return;
+        }
+    };
+
+    let mut table = TableView::<FileRow, FileColumn>::new()
+        .column(FileColumn::Fd, "FD", |c| c.align(HAlign::Right).width(6))
+        .column(FileColumn::Kind, "TYPE", |c| c.width(10))
+        .column(FileColumn::Target, "TARGET", |c| c.width(40))
+        .column(FileColumn::Protocol, "PROTO", |c| c.width(5))
+        .column(FileColumn::Local, "LOCAL", |c| c.width(24))
+        .column(FileColumn::Remote, "REMOTE", |c| c.width(24))
+        .column(FileColumn::State, "STATE", |c| c.width(10));
+    table.sort_by(FileColumn::Fd, Ordering::Less);
+    table.set_items(rows);
+
+    siv.add_layer(
+        Dialog::around(table.with_name("files_table").min_size((140, 20)))
+            .title(format!("Open Files of {} ({})", pid, cmd))
+            .button("Refresh", move |s| match file_rows(pid) {
+                Ok(rows) => {
+                    if let Some(mut table) = s.find_name::<TableView<FileRow, FileColumn>>("files_table") {
+                        table.set_items(rows);
+                    }
+                }
+                Err(e) => s.add_layer(Dialog::info(e)),
+            })
+            .button("Close", |s| { s.pop_layer(); })
+    );
+}
+
+/// A row of the memory map view: one mapping, or the mappings of one file added up
+#[derive(Clone)]
+enum MapRow {
+    Mapping(Mapping),
+    Total(MappingTotal),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum MapColumn {
+    Address,
+    Perms,
+    Regions,
+    Size,
+    Rss,
+    Pss,
+    Swap,
+    Private,
+    Name,
+}
+
+impl MapRow {
+    /// Size, RSS, PSS, swap and private memory in kB
+    fn sizes(&self) -> [u64; 5] {
+        match self {
+            MapRow::Mapping(m) => [m.size_kb, m.rss_kb, m.pss_kb, m.swap_kb, m.private_kb],
+            MapRow::Total(t) => [t.size_kb, t.rss_kb, t.pss_kb, t.swap_kb, t.private_kb],
+        }
+    }
+
+    fn start(&self) -> u64 {
+        match self {
+            MapRow::Mapping(m) => m.start,
+            MapRow::Total(t) => t.start,
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            MapRow::Mapping(m) => m.name(),
+            MapRow::Total(t) => &t.name,
+        }
+    }
+
+    fn regions(&self) -> usize {
+        match self {
+            MapRow::Mapping(_) => 1,
+            MapRow::Total(t) => t.mappings,
+        }
+    }
+
+    fn size(&self, column: MapColumn) -> u64 {
+        let [size, rss, pss, swap, private] = self.sizes();
+        match column {
+            MapColumn::Size => size,
+            MapColumn::Rss => rss,
+            MapColumn::Pss => pss,
+            MapColumn::Swap => swap,
+            MapColumn::Private => private,
+            _ => 0,
+        }
+    }
+}
+
+impl TableViewItem<MapColumn> for MapRow {
+    fn to_column(&self, column: MapColumn) -> String {
+        match column {
+            MapColumn::Address => match self {
+                MapRow::Mapping(m) => m.address(),
+                MapRow::Total(_) => String::new(),
+            },
+            MapColumn::Perms => match self {
+                MapRow::Mapping(m) => m.perms.clone(),
+                MapRow::Total(_) => String::new(),
+            },
+            MapColumn::Regions => self.regions().to_string(),
+            MapColumn::Name => fit(self.name().to_string(), 50),
+            _ => self.size(column).to_string(),
+        }
+    }
+
+    fn cmp(&self, other: &Self, column: MapColumn) -> Ordering {
+        match column {
+            MapColumn::Address | MapColumn::Perms => self.start().cmp(&other.start()),
+            MapColumn::Regions => self.regions().cmp(&other.regions()),
+            MapColumn::Name => self.name().cmp(other.name()),
+            _ => self.size(column).cmp(&other.size(column)),
+        }
+    }
+}
+
+/// The mappings of `pid`, or their totals per file, as table rows with a summary line; or why they cannot be read
+fn map_rows(pid: u32, by_file: bool) -> Result<(Vec<MapRow>, String), String> {
+    let mappings = match maps::mappings(&ProcFs::new(), pid) {
+        Ok(mappings) => mappings,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(format!("Process {} not found.", pid)),
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            return Err(format!("Permission denied reading the memory map of PID {}.", pid));
+        }
+        Err(e) => return Err(format!("Failed to read the memory map of PID {}: {}", pid, e)),
+    };
+    let megabytes = |total: u64| total as f64 / 1024.0;
+    let sum = |field: fn(&Mapping) -> u64| megabytes(mappings.iter().map(field).sum());
+    let summary = format!(
+        "{} mappings | Size {:.1} MB | RSS {:.1} MB | PSS {:.1} MB | Swap {:.1} MB | Private {:.1} MB",
+        mappings.len(),
+        sum(|m| m.size_kb),
+        sum(|m| m.rss_kb),
+        sum(|m| m.pss_kb),
+        sum(|m| m.swap_kb),
+        sum(|m| m.private_kb),
+    );
+    let rows = if by_file {
+        maps::totals(&mappings).into_iter().map(MapRow::Total).collect()
+    } else {
+        mappings.into_iter().map(MapRow::Mapping).collect()
+    };
+    Ok((rows, summary))
+}
+
+/// Lists the memory mappings of a process from /proc/[pid]/smaps, or with `by_file` their totals
+/// per library, heap and anonymous memory, largest resident first
+fn show_maps_view(siv: &mut Cursive, pid: u32, cmd: String, by_file: bool) {
+    let (rows, summary) = match map_rows(pid, by_file) {
+        Ok(rows) => rows,
+        Err(e) => {
+            siv.add_layer(Dialog::info(e));
+            return;
+        }
+    };
+
+    let size = |c: TableColumn<MapColumn>| c.align(HAlign::Right).width(10);
+    let mut table = TableView::<MapRow, MapColumn>::new();
+    if by_file {
+        table = table.column(MapColumn::Regions, "REGIONS", |c| c.align(HAlign::Right).width(11));
+    } else {
+        table = table
+            .column(MapColumn::Address, "ADDRESS", |c| c.width(27))
+            .column(MapColumn::Perms, "PERMS", |c| c.width(9));
+    }
+    table = table
+        .column(MapColumn::Size, "SIZE KB", size)
+        .column(MapColumn::Rss, "RSS KB", size)
+        .column(MapColumn::Pss, "PSS KB", size)
+        .column(MapColumn::Swap, "SWAP KB", size)
+        .column(MapColumn::Private, "PRIV KB", size)
+        .column(MapColumn::Name, "MAPPING", |c| c.width(50));
+    if by_file {
+        table.sort_by(MapColumn::Rss, Ordering::Greater);
+    } else {
+        table.sort_by(MapColumn::Address, Ordering::Less);
+    }
+    table.set_items(rows);
+
+    let toggle = if by_file { "Per Mapping" } else { "Per File" };
+    siv.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(TextView::new(summary).with_name("maps_summary"))
+                .child(DummyView)
+                .child(table.with_name("maps_table").min_size((150, 20))),
+        )
+        .title(format!("Memory Map of {} ({}){}", pid, cmd, if by_file { ", per file" } else { "" }))
+        .button(toggle, {
+            let cmd = cmd.clone();
+            move |s| {
+                s.pop_layer();
+                show_maps_view(s, pid, cmd.clone(), !by_file);
+            }
+        })
+        .button("Refresh", move |s| match map_rows(pid, by_file) {
+            Ok((rows, summary)) => {
+                s.call_on_name("maps_summary", |view: &mut TextView| view.set_content(summary));
+                if let Some(mut table) = s.find_name::<TableView<MapRow, MapColumn>>("maps_table") {
+                    table.set_items(rows);
+                }
+            }
+            Err(e) => s.add_layer(Dialog::info(e)),
+        })
+        .button("Close", |s| { s.pop_layer(); })
+    );
+}
+
+/// One row of the Setup screen's column list
+#[derive(Clone)]
+struct SetupColumn {
+    name: &'static str,
+    shown: bool,
+    width: usize,
+}
+
+fn setup_column_label(column: &SetupColumn) -> String {
+    format!("[{}] {:<8} {:>3}", if column.shown { "x" } else { " " }, column.name, column.width)
+}
+
+fn render_setup_columns(siv: &mut Cursive, columns: &[SetupColumn], selected: usize) {
+    siv.call_on_name("setup_columns", |view: &mut SelectView<usize>| {
+        view.clear();
+        for (i, column) in columns.iter().enumerate() {
+            view.add_item(setup_column_label(column), i);
+        }
+        view.set_selection(selected);
+    });
+}
+
+// Applies a change to the selected column row and redraws the list
+fn edit_setup_column(siv: &mut Cursive, columns: &Mutex<Vec<SetupColumn>>, edit: impl FnOnce(&mut Vec<SetupColumn>, usize) -> usize) {
+    let Some(selected) = siv.call_on_name("setup_columns", |view: &mut SelectView<usize>| view.selected_id()).flatten() else {
+        return;
+    };
+    let mut columns = columns.lock().unwrap();
+    let selected = edit(&mut columns, selected);
+    render_setup_columns(siv, &columns, selected);
+}
+
+/// Applies settings to the running TUI
+fn apply_config(siv: &mut Cursive, config: Config) {
+    siv.set_theme(configured_theme(config.theme));
+    REFRESH_INTERVAL_MS.store(config.refresh_interval().as_millis() as u64, AtomicOrdering::SeqCst);
+    let previous_columns = CONFIG.lock().unwrap().columns.len();
+    siv.call_on_name("table", |table: &mut TableView<Process, BasicColumn>| {
+        configure_table(table, &config, previous_columns);
+    });
+    *CONFIG.lock().unwrap() = config;
+}
+
+// F2: edit the columns, sort order, refresh interval and theme, like htop's Setup screen
+fn show_setup_dialog(siv: &mut Cursive, config_path: PathBuf) {
+    let config = CONFIG.lock().unwrap().clone();
+
+    // Shown columns in their order, then the hidden ones
+    let mut rows: Vec<SetupColumn> = config
+        .columns
+        .iter()
+        .filter_map(|column| {
+            let name = config::COLUMNS.iter().find(|(name, _)| *name == column.name)?.0;
+            Some(SetupColumn { name, shown: true, width: column.width() })
+        })
+        .collect();
+    for (name, width) in config::COLUMNS {
+        if !rows.iter().any(|row| row.name == name) {
+            rows.push(SetupColumn { name, shown: false, width });
+        }
+    }
+    let columns = Arc::new(Mutex::new(rows));
+
+    let mut column_list = SelectView::<usize>::new();
+    for (i, column) in columns.lock().unwrap().iter().enumerate() {
+        column_list.add_item(setup_column_label(column), i);
+    }
+    let (toggle, wider, narrower, up, down) =
+        (Arc::clone(&columns), Arc::clone(&columns), Arc::clone(&columns), Arc::clone(&columns), Arc::clone(&columns));
+    let column_list = OnEventView::new(column_list.with_name("setup_columns"))
+        .on_event(' ', move |s| edit_setup_column(s, &toggle, |columns, i| {
+            columns[i].shown = !columns[i].shown;
+            i
+        }))
+        .on_event('+', move |s| edit_setup_column(s, &wider, |columns, i| {
+            columns[i].width = (columns[i].width + 1).min(config::MAX_COLUMN_WIDTH);
+            i
+        }))
+        .on_event('-', move |s| edit_setup_column(s, &narrower, |columns, i| {
+            columns[i].width = columns[i].width.saturating_sub(1).max(1);
+            i
+        }))
+        .on_event('[', move |s| edit_setup_column(s, &up, |columns, i| {
+            if i == 0 {
+                return i;
+            }
+            columns.swap(i, i - 1);
+            i - 1
+        }))
+        .on_event(']', move |s| edit_setup_column(s, &down, |columns, i| {
+            if i + 1 >= columns.len() {
+                return i;
+            }
+            columns.swap(i, i + 1);
+            i + 1
+        }));
+
+    let mut sort_column = SelectView::<&'static str>::new().popup();
+    for (name, _) in config::COLUMNS {
+        sort_column.add_item(name, name);
+    }
+    let sort_index = config::COLUMNS.iter().position(|(name, _)| *name == config.sort.column).unwrap_or(0);
+    sort_column.set_selection(sort_index);
+
+    let mut direction = SelectView::<SortDirection>::new()
+        .popup()
+        .item("Descending", SortDirection::Descending)
+        .item("Ascending", SortDirection::Ascending);
+    direction.set_selection(if config.sort.direction == SortDirection::Descending { 0 } else { 1 });
+
+    let mut theme = SelectView::<config::Theme>::new().popup();
+    for option in config::Theme::ALL {
+        theme.add_item(option.name(), option);
+    }
+    theme.set_selection(config::Theme::ALL.iter().position(|t| *t == config.theme).unwrap_or(0));
+
+    let settings = LinearLayout::vertical()
+        .child(TextView::new("Sort by:"))
+        .child(sort_column.with_name("setup_sort"))
+        .child(direction.with_name("setup_direction"))
+        .child(DummyView)
+        .child(TextView::new("Refresh interval (s):"))
+        .child(EditView::new().content(config.refresh_interval_secs.to_string()).with_name("setup_interval").fixed_width(8))
+        .child(DummyView)
+        .child(TextView::new("Theme:"))
+        .child(theme.with_name("setup_theme"));
+
+    let layout = LinearLayout::vertical()
+        .child(TextView::new("Space: show/hide   +/-: width   [/]: move up/down"))
+        .child(DummyView)
+        .child(
+            LinearLayout::horizontal()
+                .child(Dialog::around(ScrollView::new(column_list)).title("Columns"))
+                .child(DummyView.fixed_width(2))
+                .child(settings),
+        )
+        .child(DummyView)
+        .child(TextView::new(format!("Settings file: {}", config_path.display())));
+
+    let read_config = move |s: &mut Cursive| -> Result<Config, String> {
+        let interval = s
+            .call_on_name("setup_interval", |view: &mut EditView| view.get_content().trim().parse::<f64>())
+            .unwrap_or(Ok(1.0))
+            .map_err(|_| "The refresh interval must be a number of seconds".to_string())?;
+        let config = Config {
+            refresh_interval_secs: interval,
+            theme: s
+                .call_on_name("setup_theme", |view: &mut SelectView<config::Theme>| view.selection().map(|t| *t))
+                .flatten()
+                .unwrap_or_default(),
+            sort: config::SortConfig {
+                column: s
+                    .call_on_name("setup_sort", |view: &mut SelectView<&'static str>| view.selection().map(|n| n.to_string()))
+                    .flatten()
+                    .unwrap_or_else(|| "CPU".to_string()),
+                direction: s
+                    .call_on_name("setup_direction", |view: &mut SelectView<SortDirection>| view.selection().map(|d| *d))
+                    .flatten()
+                    .unwrap_or_default(),
+            },
+            columns: columns
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|column| column.shown)
+                .map(|column| config::ColumnConfig { name: column.name.to_string(), width: Some(column.width) })
+                .collect(),
+        };
+        config.validate()?;
+        Ok(config)
+    };
+    let read_for_apply = read_config.clone();
+
+    siv.add_layer(
+        Dialog::around(layout)
+            .title("Setup")
+            .button("Save", move |s| match read_config(s) {
+                Ok(config) => {
+                    let saved = config.save(&config_path);
+                    apply_config(s, config);
+                    s.pop_layer();
+                    if let Err(e) = saved {
+                        s.add_layer(Dialog::info(format!("Applied, but could not save the settings: {}", e)));
+                    }
+                }
+                Err(e) => s.add_layer(Dialog::info(e)),
+            })
+            .button("Apply", move |s| match read_for_apply(s) {
+                Ok(config) => {
+                    apply_config(s, config);
+                    s.pop_layer();
+                }
+                Err(e) => s.add_layer(Dialog::info(e)),
+            })
+            .button("Cancel", |s| { s.pop_layer(); })
+    );
+}
+
 // Add this function to create a real-time system info dialog
 fn show_system_info_dialog(siv: &mut Cursive) {
     let content = TextView::new(format_system_info()).with_name("sysinfo_content");
@@ -999,6 +2717,7 @@ fn filter_processes(processes: &[Process], filter_type: FilterType, filter_value
                 },
                 FilterType::USER => process.user.as_ref().map_or(false, |user| user == filter_value),
                 FilterType::STATUS => format!("{:?}", process.process_state) == filter_value,
+                FilterType::PORT => filter_value.parse::<u16>().is_ok_and(|port| process.ports.contains(&port)),
             }
         })
         .cloned()
@@ -1010,13 +2729,18 @@ fn show_filter_dialog(siv: &mut Cursive) {
         LinearLayout::vertical()
             .child(TextView::new("Select filter type:"))
             .child(SelectView::new()
-                .item("PID", FilterType::PID)
-                .item("PPID", FilterType::PPID)
-                .item("User", FilterType::USER)
-                .item("Status", FilterType::STATUS)
+                .item("Expression", None)
+                .item("PID", Some(FilterType::PID))
+                .item("PPID", Some(FilterType::PPID))
+                .item("User", Some(FilterType::USER))
+                .item("Status", Some(FilterType::STATUS))
+                .item("Listening port", Some(FilterType::PORT))
                 .on_submit(move |s, &filter_type| {
                     s.pop_layer();
-                    show_filter_value_dialog(s, filter_type);
+                    match filter_type {
+                        Some(filter_type) => show_filter_value_dialog(s, filter_type),
+                        None => show_expression_filter_dialog(s),
+                    }
                 }))
     )
     .title("Filter Processes")
@@ -1038,16 +2762,14 @@ fn show_filter_value_dialog(siv: &mut Cursive, filter_type: FilterType) {
     .button("Apply", move |s| {
         if let Some(view) = s.find_name::<EditView>("filter_value") {
             let filter_value = view.get_content().to_string();
-            if let Some(mut table_view) = s.find_name::<TableView<Process, BasicColumn>>("table") {
-                let current_processes = get_processes();
-                let filtered_processes = filter_processes(&current_processes, filter_type, &filter_value);
-                table_view.set_items(filtered_processes);
-                
-                // Update the current filter state
+            // A port filter also makes the next refresh look up ports, which fills the table in
+            {
                 let mut filter_state = CURRENT_FILTER.lock().unwrap();
                 filter_state.filter_type = Some(filter_type);
                 filter_state.filter_value = filter_value;
+                filter_state.expression = None;
             }
+            show_cached_processes(s);
             s.pop_layer();
         }
     })
@@ -1056,16 +2778,62 @@ fn show_filter_value_dialog(siv: &mut Cursive, filter_type: FilterType) {
     siv.add_layer(dialog);
 }
 
-fn clear_filter(siv: &mut Cursive) {
-    if let Some(mut table_view) = siv.find_name::<TableView<Process, BasicColumn>>("table") {
-        let current_processes = get_processes();
-        table_view.set_items(current_processes);
-        
-        // Clear the filter state
-        {
+fn show_expression_filter_dialog(siv: &mut Cursive) {
+    let current = CURRENT_FILTER.lock().unwrap().expression.as_ref().map(|e| e.to_string()).unwrap_or_default();
+    let dialog = Dialog::around(
+        LinearLayout::vertical()
+            .child(TextView::new(
+                "Fields: pid ppid uid user cpu cmd cmdline exe cwd state nice threads start age\n\
+                 read_bytes write_bytes, memory: rss (or mem) pss uss swap vsz shared mem_percent\n\
+                 e.g. cpu > 20 and user == \"ci\" and cmd ~ /python/\n     mem > 500MB or state == Zombie"
+            ))
+            .child(DummyView)
+            .child(EditView::new()
+                .content(current)
+                .on_submit(|s, _| apply_expression_filter(s))
+                .with_name("filter_expression")
+                .fixed_width(60))
+            .child(TextView::new("").with_name("filter_error"))
+    )
+    .title("Filter Expression")
+    .button("Apply", apply_expression_filter)
+    .button("Cancel", |s| { s.pop_layer(); });
+
+    siv.add_layer(dialog);
+}
+
+// Keeps the dialog open with the error pointed out when the expression does not parse
+fn apply_expression_filter(s: &mut Cursive) {
+    let Some(expression) = s.call_on_name("filter_expression", |view: &mut EditView| view.get_content().to_string()) else {
+        return;
+    };
+    match Filter::parse(&expression) {
+        Ok(filter) => {
             let mut filter_state = CURRENT_FILTER.lock().unwrap();
             filter_state.filter_type = None;
             filter_state.filter_value.clear();
+            filter_state.expression = Some(filter);
+            drop(filter_state);
+            show_cached_processes(s);
+            s.pop_layer();
         }
+        Err(e) => {
+            s.call_on_name("filter_error", |view: &mut TextView| {
+                view.set_content(StyledString::styled(
+                    format!("{}\n{}", e.pointer(&expression), e.message),
+                    Color::Light(BaseColor::Red),
+                ));
+            });
+        }
+    }
+}
+
+fn clear_filter(siv: &mut Cursive) {
+    {
+        let mut filter_state = CURRENT_FILTER.lock().unwrap();
+        filter_state.filter_type = None;
+        filter_state.filter_value.clear();
+        filter_state.expression = None;
     }
+    show_cached_processes(siv);
 }
\ No newline at end of file
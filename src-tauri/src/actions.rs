@@ -0,0 +1,223 @@
+// Process actions shared by the CLI, the TUI and the Tauri commands
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use nix::errno::Errno;
+use nix::libc;
+use nix::sys::signal::{kill as send_signal, Signal};
+use nix::unistd::Pid;
+use serde::Serialize;
+
+use crate::procfs::{self, ProcFs};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind")]
+pub enum ActionError {
+    NotFound { pid: u32 },
+    PermissionDenied { pid: u32 },
+    InvalidNice { nice: i32 },
+    Failed { pid: u32, message: String },
+}
+
+impl ActionError {
+    fn from_errno(pid: u32, errno: Errno) -> Self {
+        match errno {
+            Errno::ESRCH => ActionError::NotFound { pid },
+            Errno::EPERM | Errno::EACCES => ActionError::PermissionDenied { pid },
+            other => ActionError::Failed { pid, message: other.desc().to_string() },
+        }
+    }
+}
+
+impl fmt::Display for ActionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActionError::NotFound { pid } => write!(f, "process {} was not found", pid),
+            ActionError::PermissionDenied { pid } => write!(f, "permission denied for process {}", pid),
+            ActionError::InvalidNice { nice } => write!(f, "nice value {} is outside -20..=19", nice),
+            ActionError::Failed { pid, message } => write!(f, "process {}: {}", pid, message),
+        }
+    }
+}
+
+impl std::error::Error for ActionError {}
+
+pub fn signal(pid: u32, signal: Signal) -> Result<(), ActionError> {
+    if !procfs::is_valid_pid(pid) {
+        // kill(0, ...) would signal our whole process group, and a PID that wraps negative other groups or every process
+        return Err(ActionError::NotFound { pid });
+    }
+    send_signal(Pid::from_raw(pid as i32), signal).map_err(|e| ActionError::from_errno(pid, e))
+}
+
+/// Sends `signal` to one thread of `pid` with tgkill(2); kill(2) lets any thread of the process take it.
+/// Stop, continue and fatal signals still act on the whole process.
+#[cfg(target_os = "linux")]
+pub fn signal_thread(pid: u32, tid: u32, signal: Signal) -> Result<(), ActionError> {
+    if !procfs::is_valid_pid(pid) || !procfs::is_valid_pid(tid) {
+        return Err(ActionError::NotFound { pid: tid });
+    }
+    let res = unsafe { libc::syscall(libc::SYS_tgkill, pid as libc::pid_t, tid as libc::pid_t, signal as libc::c_int) };
+    Errno::result(res).map(drop).map_err(|e| ActionError::from_errno(tid, e))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn signal_thread(_pid: u32, tid: u32, _signal: Signal) -> Result<(), ActionError> {
+    Err(ActionError::Failed { pid: tid, message: "signalling a single thread is only supported on Linux".to_string() })
+}
+
+pub fn kill(pid: u32) -> Result<(), ActionError> {
+    signal(pid, Signal::SIGKILL)
+}
+
+pub fn pause(pid: u32) -> Result<(), ActionError> {
+    signal(pid, Signal::SIGSTOP)
+}
+
+pub fn resume(pid: u32) -> Result<(), ActionError> {
+    signal(pid, Signal::SIGCONT)
+}
+
+/// Given a TID instead of a PID, only that thread is reniced
+pub fn renice(pid: u32, nice: i32) -> Result<(), ActionError> {
+    if !(-20..=19).contains(&nice) {
+        return Err(ActionError::InvalidNice { nice });
+    }
+    if !procfs::is_valid_pid(pid) {
+        // setpriority treats 0 as the calling process
+        return Err(ActionError::NotFound { pid });
+    }
+    procfs::set_nice(pid, nice).map_err(|e| ActionError::from_errno(pid, e))
+}
+
+/// The process name, for messages about a PID
+pub fn process_name(pid: u32) -> Option<String> {
+    ProcFs::new().read_stat(pid).map(|stat| stat.comm)
+}
+
+/// How long `terminate` waits after SIGTERM before sending SIGKILL
+pub const DEFAULT_TERM_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What happened to a process after it was signalled
+#[derive(Clone, Debug, Serialize)]
+pub struct SignalOutcome {
+    pub pid: u32,
+    /// Every signal sent, in order
+    pub signals: Vec<String>,
+    /// Whether SIGTERM was not enough and SIGKILL had to be sent
+    pub escalated: bool,
+    /// Whether the process is gone (or a zombie) afterwards
+    pub exited: bool,
+}
+
+/// Whether the process that had `start_ticks` is gone, or only a zombie is left of it.
+/// A new process reusing the PID counts as gone.
+fn has_exited(procfs: &ProcFs, pid: u32, start_ticks: u64) -> bool {
+    procfs.read_stat(pid).is_none_or(|stat| stat.starttime != start_ticks || stat.state == 'Z')
+}
+
+fn wait_for_exit(procfs: &ProcFs, pid: u32, start_ticks: u64, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if has_exited(procfs, pid, start_ticks) {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Sends `signal` and reports whether the process exited within `wait`
+pub fn send(pid: u32, signal: Signal, wait: Duration) -> Result<SignalOutcome, ActionError> {
+    let procfs = ProcFs::new();
+    let start_ticks = procfs.read_stat(pid).ok_or(ActionError::NotFound { pid })?.starttime;
+    self::signal(pid, signal)?;
+    Ok(SignalOutcome {
+        pid,
+        signals: vec![signal.as_str().to_string()],
+        escalated: false,
+        exited: wait_for_exit(&procfs, pid, start_ticks, wait),
+    })
+}
+
+/// Asks the process to exit with SIGTERM and sends SIGKILL if it is still running after `timeout`
+pub fn terminate(pid: u32, timeout: Duration) -> Result<SignalOutcome, ActionError> {
+    let procfs = ProcFs::new();
+    let start_ticks = procfs.read_stat(pid).ok_or(ActionError::NotFound { pid })?.starttime;
+    let mut outcome = SignalOutcome { pid, signals: Vec::new(), escalated: false, exited: false };
+
+    signal(pid, Signal::SIGTERM)?;
+    outcome.signals.push(Signal::SIGTERM.as_str().to_string());
+    // A stopped process only handles SIGTERM once it is continued
+    let _ = signal(pid, Signal::SIGCONT);
+    if wait_for_exit(&procfs, pid, start_ticks, timeout) {
+        outcome.exited = true;
+        return Ok(outcome);
+    }
+
+    outcome.escalated = true;
+    match signal(pid, Signal::SIGKILL) {
+        Ok(()) | Err(ActionError::NotFound { .. }) => {}
+        Err(e) => return Err(e),
+    }
+    outcome.signals.push(Signal::SIGKILL.as_str().to_string());
+    // SIGKILL cannot be caught, but a process in uninterruptible sleep only dies once it wakes up
+    outcome.exited = wait_for_exit(&procfs, pid, start_ticks, Duration::from_secs(2));
+    Ok(outcome)
+}
+
+/// Every standard signal with a short description, in signal number order
+pub fn all_signals() -> Vec<(Signal, &'static str)> {
+    Signal::iterator().map(|signal| (signal, describe_signal(signal))).collect()
+}
+
+pub fn describe_signal(signal: Signal) -> &'static str {
+    match signal {
+        Signal::SIGHUP => "Hangup, often reloads configuration",
+        Signal::SIGINT => "Interrupt, as Ctrl-C",
+        Signal::SIGQUIT => "Quit with core dump, as Ctrl-\\",
+        Signal::SIGILL => "Illegal instruction",
+        Signal::SIGTRAP => "Trace/breakpoint trap",
+        Signal::SIGABRT => "Abort with core dump",
+        Signal::SIGBUS => "Bus error",
+        Signal::SIGFPE => "Floating point exception",
+        Signal::SIGKILL => "Kill, cannot be caught or ignored",
+        Signal::SIGUSR1 => "User defined signal 1",
+        Signal::SIGSEGV => "Segmentation fault",
+        Signal::SIGUSR2 => "User defined signal 2",
+        Signal::SIGPIPE => "Broken pipe",
+        Signal::SIGALRM => "Alarm clock",
+        Signal::SIGTERM => "Terminate gracefully",
+        Signal::SIGSTKFLT => "Stack fault",
+        Signal::SIGCHLD => "Child stopped or exited",
+        Signal::SIGCONT => "Continue if stopped",
+        Signal::SIGSTOP => "Stop, cannot be caught or ignored",
+        Signal::SIGTSTP => "Stop from the terminal, as Ctrl-Z",
+        Signal::SIGTTIN => "Background read from the terminal",
+        Signal::SIGTTOU => "Background write to the terminal",
+        Signal::SIGURG => "Urgent data on a socket",
+        Signal::SIGXCPU => "CPU time limit exceeded",
+        Signal::SIGXFSZ => "File size limit exceeded",
+        Signal::SIGVTALRM => "Virtual alarm clock",
+        Signal::SIGPROF => "Profiling timer expired",
+        Signal::SIGWINCH => "Window size changed",
+        Signal::SIGIO => "I/O is possible",
+        Signal::SIGPWR => "Power failure",
+        Signal::SIGSYS => "Bad system call",
+        _ => "",
+    }
+}
+
+/// Parses a signal given as a name (`TERM`, `SIGTERM`, `term`) or a number (`15`)
+pub fn parse_signal(name: &str) -> Option<Signal> {
+    let name = name.trim();
+    if let Ok(number) = name.parse::<i32>() {
+        return Signal::try_from(number).ok();
+    }
+    let upper = name.to_ascii_uppercase();
+    let full = if upper.starts_with("SIG") { upper } else { format!("SIG{}", upper) };
+    full.parse().ok()
+}
@@ -0,0 +1,264 @@
+// Append-only JSONL record of every action taken on a process, with size and age based rotation
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::{self as unix_fs, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use users::os::unix::UserExt;
+
+use crate::procfs::ProcFs;
+
+/// Where an action was issued from
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Cli,
+    Tui,
+    Gui,
+    Api,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Source::Cli => "cli",
+            Source::Tui => "tui",
+            Source::Gui => "gui",
+            Source::Api => "api",
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// RFC 3339, local time
+    pub timestamp: String,
+    /// The user behind the action; the invoking user when running under sudo
+    pub user: String,
+    /// Whether the action ran with sudo, either procmanager itself or an escalated helper
+    pub sudo: bool,
+    pub source: Source,
+    pub pid: u32,
+    pub name: Option<String>,
+    pub cmdline: Option<String>,
+    /// e.g. "kill", "pause", "resume", "renice", "signal"
+    pub action: String,
+    pub args: Vec<String>,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// The process an action is about to target, read before acting since a killed process
+/// can no longer be looked up
+pub struct AuditTarget {
+    pub pid: u32,
+    pub name: Option<String>,
+    pub cmdline: Option<String>,
+}
+
+impl AuditTarget {
+    pub fn capture(pid: u32) -> Self {
+        let procfs = ProcFs::new();
+        AuditTarget {
+            pid,
+            name: procfs.read_stat(pid).map(|stat| stat.comm),
+            cmdline: procfs.read_cmdline(pid).filter(|args| !args.is_empty()).map(|args| args.join(" ")),
+        }
+    }
+}
+
+/// The invoking user, seen through sudo. `SUDO_USER` is only believed when we run as root, since
+/// anyone can set it.
+fn acting_user() -> (String, bool) {
+    if nix::unistd::geteuid().is_root() {
+        if let Ok(user) = std::env::var("SUDO_USER") {
+            return (user, true);
+        }
+    }
+    let uid = nix::unistd::getuid().as_raw();
+    let name = users::get_user_by_uid(uid)
+        .map(|user| user.name().to_string_lossy().into_owned())
+        .unwrap_or_else(|| format!("uid:{}", uid));
+    (name, false)
+}
+
+/// The user who ran sudo, when procmanager runs as root under it
+fn sudo_invoker() -> Option<users::User> {
+    if !nix::unistd::geteuid().is_root() {
+        return None;
+    }
+    let uid = std::env::var("SUDO_UID").ok()?.parse().ok()?;
+    users::get_user_by_uid(uid)
+}
+
+pub struct AuditLog {
+    path: PathBuf,
+    /// Owner given to the files and directories we create, so a log written under sudo stays
+    /// writable by the invoking user
+    owner: Option<(u32, u32)>,
+    /// Rotate once the active file reaches this size
+    max_bytes: u64,
+    /// Rotate once the oldest entry of the active file is this old
+    max_age: Duration,
+    /// Rotated files kept next to the active one, as `<path>.1` (newest) to `<path>.<keep>`
+    keep: usize,
+}
+
+impl Default for AuditLog {
+    /// `$PROCMANAGER_AUDIT_LOG`, or `audit.jsonl` in `$XDG_STATE_HOME/procmanager` (`~/.local/state`).
+    /// Under sudo `~` is the invoking user's home, not root's, so both share one log.
+    fn default() -> Self {
+        let invoker = sudo_invoker();
+        let path = std::env::var_os("PROCMANAGER_AUDIT_LOG").map(PathBuf::from).unwrap_or_else(|| {
+            let home = match &invoker {
+                Some(user) => Some(user.home_dir().to_path_buf()),
+                None => std::env::var_os("HOME").map(PathBuf::from),
+            };
+            let state_home = std::env::var_os("XDG_STATE_HOME")
+                .map(PathBuf::from)
+                .or_else(|| home.map(|home| home.join(".local").join("state")))
+                .unwrap_or_else(std::env::temp_dir);
+            state_home.join("procmanager").join("audit.jsonl")
+        });
+        AuditLog { owner: invoker.map(|user| (user.uid(), user.primary_group_id())), ..AuditLog::new(path) }
+    }
+}
+
+impl AuditLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        AuditLog {
+            path: path.into(),
+            owner: None,
+            max_bytes: 5 * 1024 * 1024,
+            max_age: Duration::from_secs(7 * 24 * 60 * 60),
+            keep: 5,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn needs_rotation(&self) -> bool {
+        let Ok(metadata) = fs::metadata(&self.path) else { return false };
+        if metadata.len() >= self.max_bytes {
+            return true;
+        }
+        let oldest = File::open(&self.path)
+            .ok()
+            .and_then(|file| BufReader::new(file).lines().next()?.ok())
+            .and_then(|line| serde_json::from_str::<AuditEntry>(&line).ok())
+            .and_then(|entry| DateTime::parse_from_rfc3339(&entry.timestamp).ok());
+        match oldest {
+            Some(oldest) => (Local::now().fixed_offset() - oldest).to_std().is_ok_and(|age| age >= self.max_age),
+            None => false,
+        }
+    }
+
+    fn rotate(&self) -> io::Result<()> {
+        if self.keep == 0 {
+            return fs::remove_file(&self.path);
+        }
+        let _ = fs::remove_file(self.rotated(self.keep));
+        for index in (1..self.keep).rev() {
+            let from = self.rotated(index);
+            if from.exists() {
+                fs::rename(&from, self.rotated(index + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated(1))
+    }
+
+    /// Creates `dir` and its missing parents, owned by `owner` when set
+    fn create_dirs(&self, dir: &Path) -> io::Result<()> {
+        let missing: Vec<&Path> = dir.ancestors().take_while(|ancestor| !ancestor.exists()).collect();
+        fs::create_dir_all(dir)?;
+        if let Some((uid, gid)) = self.owner {
+            for created in missing {
+                unix_fs::chown(created, Some(uid), Some(gid))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn append(&self, entry: &AuditEntry) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            self.create_dirs(dir)?;
+        }
+        if self.needs_rotation() {
+            self.rotate()?;
+        }
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        let created = !self.path.exists();
+        // One write per entry so lines from concurrent writers do not interleave
+        let mut file = OpenOptions::new().create(true).append(true).mode(0o600).open(&self.path)?;
+        if let (true, Some((uid, gid))) = (created, self.owner) {
+            unix_fs::chown(&self.path, Some(uid), Some(gid))?;
+        }
+        file.write_all(&line)
+    }
+
+    /// Every entry still on disk, oldest first. Lines that do not parse are skipped.
+    pub fn read_all(&self) -> Vec<AuditEntry> {
+        let mut files: Vec<PathBuf> = (1..=self.keep).rev().map(|index| self.rotated(index)).collect();
+        files.push(self.path.clone());
+        files
+            .iter()
+            .filter_map(|path| File::open(path).ok())
+            .flat_map(|file| BufReader::new(file).lines().map_while(Result::ok).collect::<Vec<_>>())
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .collect()
+    }
+}
+
+/// Runs `action` against `pid` and records it in the default audit log. Failing to write the
+/// log does not fail the action.
+pub fn audited<T, E: fmt::Display>(
+    source: Source,
+    pid: u32,
+    action: &str,
+    args: &[String],
+    sudo: bool,
+    run: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    let target = AuditTarget::capture(pid);
+    let result = run();
+    let _ = record(source, target, action, args, sudo, result.as_ref().err().map(|e| e.to_string()));
+    result
+}
+
+/// Records an action already taken on `target`; `error` is `None` when it succeeded
+pub fn record(
+    source: Source,
+    target: AuditTarget,
+    action: &str,
+    args: &[String],
+    sudo: bool,
+    error: Option<String>,
+) -> io::Result<()> {
+    let (user, under_sudo) = acting_user();
+    AuditLog::default().append(&AuditEntry {
+        timestamp: Local::now().to_rfc3339(),
+        user,
+        sudo: sudo || under_sudo,
+        source,
+        pid: target.pid,
+        name: target.name,
+        cmdline: target.cmdline,
+        action: action.to_string(),
+        args: args.to_vec(),
+        success: error.is_none(),
+        error,
+    })
+}
@@ -0,0 +1,95 @@
+// Tauri commands exposing process management to the webview
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+use sysinfo::System;
+use tauri::State;
+
+use crate::actions::{self, ActionError};
+use crate::audit::{self, Source};
+use crate::snapshot::{self, ProcessSnapshot, Snapshotter};
+use crate::system::{self, SystemInfo};
+use crate::track::{self, TrackSample, TrackSummary};
+use crate::tree::{self, ProcessNode};
+
+pub struct AppState {
+    /// One per view, so the CPU usage of each is measured since that view's previous refresh
+    list_snapshotter: Arc<Mutex<Snapshotter>>,
+    tree_snapshotter: Arc<Mutex<Snapshotter>>,
+    system: Mutex<System>,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        AppState {
+            list_snapshotter: Arc::new(Mutex::new(Snapshotter::new(snapshot::DEFAULT_INTERVAL))),
+            tree_snapshotter: Arc::new(Mutex::new(Snapshotter::new(snapshot::DEFAULT_INTERVAL))),
+            system: Mutex::new(System::new()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct TrackReport {
+    pub samples: Vec<TrackSample>,
+    pub summary: TrackSummary,
+}
+
+/// A refresh reads all of /proc and may sleep for the first CPU sample, so it runs on the
+/// blocking pool rather than an async worker
+async fn refresh(snapshotter: &Arc<Mutex<Snapshotter>>) -> Result<Vec<ProcessSnapshot>, ActionError> {
+    let snapshotter = Arc::clone(snapshotter);
+    tauri::async_runtime::spawn_blocking(move || snapshotter.lock().unwrap().refresh())
+        .await
+        .map_err(|e| ActionError::Failed { pid: 0, message: e.to_string() })
+}
+
+#[tauri::command]
+pub async fn list_processes(state: State<'_, AppState>) -> Result<Vec<ProcessSnapshot>, ActionError> {
+    refresh(&state.list_snapshotter).await
+}
+
+#[tauri::command]
+pub fn kill(pid: u32) -> Result<(), ActionError> {
+    audit::audited(Source::Gui, pid, "kill", &[], false, || actions::kill(pid))
+}
+
+#[tauri::command]
+pub fn pause(pid: u32) -> Result<(), ActionError> {
+    audit::audited(Source::Gui, pid, "pause", &[], false, || actions::pause(pid))
+}
+
+#[tauri::command]
+pub fn resume(pid: u32) -> Result<(), ActionError> {
+    audit::audited(Source::Gui, pid, "resume", &[], false, || actions::resume(pid))
+}
+
+#[tauri::command]
+pub fn renice(pid: u32, nice: i32) -> Result<(), ActionError> {
+    audit::audited(Source::Gui, pid, "renice", &[nice.to_string()], false, || actions::renice(pid, nice))
+}
+
+#[tauri::command]
+pub async fn process_tree(state: State<'_, AppState>) -> Result<Vec<ProcessNode<ProcessSnapshot>>, ActionError> {
+    let processes = refresh(&state.tree_snapshotter).await?;
+    Ok(tree::build_process_tree(&processes))
+}
+
+#[tauri::command]
+pub fn system_metrics(state: State<'_, AppState>) -> SystemInfo {
+    system::get_system_metrics(&mut state.system.lock().unwrap())
+}
+
+#[tauri::command]
+pub async fn track_process(pid: u32, duration_secs: u64) -> Result<TrackReport, ActionError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut samples = Vec::new();
+        let summary = track::track(pid, Duration::from_secs(duration_secs), Duration::from_secs(1), |sample| {
+            samples.push(sample.clone())
+        })?;
+        Ok(TrackReport { samples, summary })
+    })
+    .await
+    .map_err(|e| ActionError::Failed { pid, message: e.to_string() })?
+}
@@ -0,0 +1,275 @@
+// TUI settings kept in ~/.config/procmanager/config.toml and edited from the TUI's Setup screen (F2)
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Every column the TUI can show, with its default width
+pub const COLUMNS: [(&str, usize); 20] = [
+    ("PID", 10),
+    ("PPID", 10),
+    ("USER", 10),
+    ("CPU", 10),
+    ("MEM", 10),
+    ("MEM%", 8),
+    ("PSS", 10),
+    ("USS", 10),
+    ("SHARED", 10),
+    ("SWAP", 10),
+    ("VSZ", 10),
+    ("NI", 10),
+    ("CMD", 30),
+    ("CMDLINE", 50),
+    ("EXE", 30),
+    ("CWD", 30),
+    ("THREADS", 6),
+    ("PORTS", 15),
+    ("START", 20),
+    ("STATUS", 15),
+];
+
+const DEFAULT_COLUMNS: [&str; 10] = ["PID", "PPID", "USER", "CPU", "MEM", "NI", "CMD", "START", "THREADS", "STATUS"];
+
+pub const MAX_COLUMN_WIDTH: usize = 200;
+pub const MIN_REFRESH_INTERVAL_SECS: f64 = 0.2;
+pub const MAX_REFRESH_INTERVAL_SECS: f64 = 60.0;
+
+/// The default width of a column, `None` for an unknown name
+pub fn default_width(name: &str) -> Option<usize> {
+    COLUMNS.iter().find(|(column, _)| *column == name).map(|(_, width)| *width)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColumnConfig {
+    /// One of `COLUMNS`
+    pub name: String,
+    /// Characters; the column's default width when omitted
+    pub width: Option<usize>,
+}
+
+impl ColumnConfig {
+    pub fn new(name: &str) -> Self {
+        ColumnConfig { name: name.to_string(), width: None }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width.or_else(|| default_width(&self.name)).unwrap_or(10)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortDirection {
+    Ascending,
+    #[default]
+    Descending,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SortConfig {
+    pub column: String,
+    pub direction: SortDirection,
+}
+
+impl Default for SortConfig {
+    fn default() -> Self {
+        SortConfig { column: "CPU".to_string(), direction: SortDirection::Descending }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    #[default]
+    Magenta,
+    Blue,
+    Light,
+    Monochrome,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 4] = [Theme::Magenta, Theme::Blue, Theme::Light, Theme::Monochrome];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Magenta => "magenta",
+            Theme::Blue => "blue",
+            Theme::Light => "light",
+            Theme::Monochrome => "monochrome",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Seconds between table refreshes
+    pub refresh_interval_secs: f64,
+    pub theme: Theme,
+    /// Initial sort order of the table
+    pub sort: SortConfig,
+    /// Visible columns, in display order
+    #[serde(rename = "column")]
+    pub columns: Vec<ColumnConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            refresh_interval_secs: 1.0,
+            theme: Theme::default(),
+            sort: SortConfig::default(),
+            columns: DEFAULT_COLUMNS.iter().map(|name| ColumnConfig::new(name)).collect(),
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME/procmanager/config.toml`, falling back to `~/.config`
+pub fn default_path() -> PathBuf {
+    config_home().join("procmanager").join("config.toml")
+}
+
+/// `$XDG_CONFIG_HOME`, falling back to `~/.config`
+pub(crate) fn config_home() -> PathBuf {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+fn column_names() -> String {
+    COLUMNS.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", ")
+}
+
+impl Config {
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut config: Config = toml::from_str(contents).map_err(|e| e.to_string())?;
+        for column in &mut config.columns {
+            column.name = column.name.to_uppercase();
+        }
+        config.sort.column = config.sort.column.to_uppercase();
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.columns.is_empty() {
+            return Err("at least one column must be shown".to_string());
+        }
+        for (i, column) in self.columns.iter().enumerate() {
+            if default_width(&column.name).is_none() {
+                return Err(format!("unknown column '{}'; columns are {}", column.name, column_names()));
+            }
+            if self.columns[..i].iter().any(|c| c.name == column.name) {
+                return Err(format!("column '{}' is listed twice", column.name));
+            }
+            if !(1..=MAX_COLUMN_WIDTH).contains(&column.width()) {
+                return Err(format!("column '{}' must be 1 to {} characters wide", column.name, MAX_COLUMN_WIDTH));
+            }
+        }
+        if default_width(&self.sort.column).is_none() {
+            return Err(format!("unknown sort column '{}'; columns are {}", self.sort.column, column_names()));
+        }
+        if !(MIN_REFRESH_INTERVAL_SECS..=MAX_REFRESH_INTERVAL_SECS).contains(&self.refresh_interval_secs) {
+            return Err(format!(
+                "refresh_interval_secs must be between {} and {}",
+                MIN_REFRESH_INTERVAL_SECS, MAX_REFRESH_INTERVAL_SECS
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Config::parse(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// The defaults when the file does not exist yet
+    pub fn load_or_default(path: &Path) -> Result<Self, String> {
+        if path.exists() {
+            Config::load(path)
+        } else {
+            Ok(Config::default())
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = toml::to_string(self).map_err(|e| e.to_string())?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        fs::write(path, format!("# Written by the procmanager TUI Setup screen (F2)\n{}", contents))
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn refresh_interval(&self) -> Duration {
+        Duration::from_secs_f64(self.refresh_interval_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(contents: &str) -> String {
+        Config::parse(contents).unwrap_err()
+    }
+
+    #[test]
+    fn empty_file_gives_defaults() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(config, Config::default());
+        assert_eq!(config.refresh_interval(), Duration::from_secs(1));
+        assert_eq!(config.sort, SortConfig { column: "CPU".to_string(), direction: SortDirection::Descending });
+        assert_eq!(config.columns.len(), DEFAULT_COLUMNS.len());
+    }
+
+    #[test]
+    fn reads_columns_case_insensitively() {
+        let contents = r#"
+theme = "blue"
+
+[sort]
+column = "mem"
+
+[[column]]
+name = "pid"
+
+[[column]]
+name = "Cmd"
+width = 40
+"#;
+        let config = Config::parse(contents).unwrap();
+        assert_eq!(config.theme, Theme::Blue);
+        assert_eq!(config.sort.column, "MEM");
+        assert_eq!(
+            config.columns,
+            [ColumnConfig::new("PID"), ColumnConfig { name: "CMD".to_string(), width: Some(40) }]
+        );
+        assert_eq!((config.columns[0].width(), config.columns[1].width()), (10, 40));
+    }
+
+    #[test]
+    fn rejects_unknown_columns() {
+        let unknown = error("[[column]]\nname = \"TEMPERATURE\"\n");
+        assert!(unknown.starts_with("unknown column 'TEMPERATURE'; columns are PID, PPID"), "{}", unknown);
+        assert!(error("[sort]\ncolumn = \"TEMPERATURE\"\n").starts_with("unknown sort column 'TEMPERATURE'"));
+        assert_eq!(error("[[column]]\nname = \"pid\"\n[[column]]\nname = \"PID\"\n"), "column 'PID' is listed twice");
+        assert!(error("[[column]]\nname = \"CMD\"\nwidth = 0\n").contains("must be 1 to 200 characters wide"));
+        assert!(error("colour = \"red\"\n").contains("unknown field `colour`"));
+    }
+
+    #[test]
+    fn rejects_an_invalid_interval() {
+        for interval in ["0.0", "0.1", "-1.0", "61.0", "nan", "inf"] {
+            let contents = format!("refresh_interval_secs = {}\n", interval);
+            assert_eq!(error(&contents), "refresh_interval_secs must be between 0.2 and 60", "{}", interval);
+        }
+        assert!(error("refresh_interval_secs = \"fast\"\n").contains("invalid type"));
+        let config = Config::parse("refresh_interval_secs = 0.5\n").unwrap();
+        assert_eq!(config.refresh_interval(), Duration::from_millis(500));
+    }
+}
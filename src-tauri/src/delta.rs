@@ -0,0 +1,140 @@
+// Differences between two snapshots, so subscribers can patch their lists instead of replacing them
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::snapshot::ProcessSnapshot;
+use crate::tree::TreeItem;
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SnapshotDelta {
+    pub added: Vec<ProcessSnapshot>,
+    /// PIDs that are gone
+    pub removed: Vec<u32>,
+    /// Processes whose fields changed, with their new values
+    pub changed: Vec<ProcessSnapshot>,
+}
+
+impl SnapshotDelta {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Brings a list built from the previous snapshot up to date
+    pub fn apply<P>(&self, items: &mut Vec<P>)
+    where
+        P: TreeItem + From<ProcessSnapshot>,
+    {
+        let removed: HashSet<u32> = self.removed.iter().copied().collect();
+        items.retain(|item| !removed.contains(&item.pid()));
+
+        let mut changed: HashMap<u32, &ProcessSnapshot> = self.changed.iter().map(|p| (p.pid, p)).collect();
+        for item in items.iter_mut() {
+            if let Some(snapshot) = changed.remove(&item.pid()) {
+                *item = P::from(snapshot.clone());
+            }
+        }
+
+        items.extend(self.added.iter().cloned().map(P::from));
+    }
+}
+
+pub fn diff(previous: &[ProcessSnapshot], current: &[ProcessSnapshot]) -> SnapshotDelta {
+    let before: HashMap<u32, &ProcessSnapshot> = previous.iter().map(|p| (p.pid, p)).collect();
+    let now: HashSet<u32> = current.iter().map(|p| p.pid).collect();
+
+    let mut delta = SnapshotDelta::default();
+    for process in current {
+        match before.get(&process.pid) {
+            // Same PID but a different start time means the PID was reused
+            Some(old) if old.start_time != process.start_time => {
+                delta.removed.push(process.pid);
+                delta.added.push(process.clone());
+            }
+            Some(old) if *old != process => delta.changed.push(process.clone()),
+            Some(_) => {}
+            None => delta.added.push(process.clone()),
+        }
+    }
+    delta.removed.extend(previous.iter().map(|p| p.pid).filter(|pid| !now.contains(pid)));
+    delta
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::ProcessState;
+
+    fn process(pid: u32, start_time: u64) -> ProcessSnapshot {
+        ProcessSnapshot {
+            pid,
+            ppid: Some(1),
+            uid: Some(1000),
+            user: Some("user".to_string()),
+            cpu: 0.0,
+            mem: 10.0,
+            mem_percent: 0.1,
+            vsz: 100.0,
+            shared: None,
+            swap: None,
+            pss: None,
+            uss: None,
+            cmd: format!("worker{}", pid),
+            cmdline: format!("worker{} --serve", pid),
+            exe: None,
+            cwd: None,
+            start_time,
+            state: ProcessState::Sleeping,
+            nice: 0,
+            threads: 1,
+            read_bytes: None,
+            write_bytes: None,
+            ports: Vec::new(),
+        }
+    }
+
+    fn pids(processes: &[ProcessSnapshot]) -> Vec<u32> {
+        processes.iter().map(|process| process.pid).collect()
+    }
+
+    /// `previous` with `delta` applied, in PID order
+    fn patched(previous: &[ProcessSnapshot], delta: &SnapshotDelta) -> Vec<ProcessSnapshot> {
+        let mut items = previous.to_vec();
+        delta.apply(&mut items);
+        items.sort_by_key(|process| process.pid);
+        items
+    }
+
+    #[test]
+    fn unchanged_lists_give_an_empty_delta() {
+        let processes = vec![process(1, 100), process(2, 200)];
+        assert!(diff(&processes, &processes).is_empty());
+    }
+
+    #[test]
+    fn reports_added_removed_and_changed() {
+        let previous = vec![process(1, 100), process(2, 200), process(3, 300)];
+        let mut busy = process(2, 200);
+        busy.cpu = 75.0;
+        let current = vec![process(1, 100), busy.clone(), process(4, 400)];
+
+        let delta = diff(&previous, &current);
+        assert_eq!(pids(&delta.added), [4]);
+        assert_eq!(delta.removed, [3]);
+        assert_eq!(delta.changed, [busy]);
+        assert_eq!(patched(&previous, &delta), current);
+    }
+
+    #[test]
+    fn reused_pid_is_removed_and_added() {
+        let previous = vec![process(1, 100), process(7, 200)];
+        let current = vec![process(1, 100), process(7, 900)];
+
+        let delta = diff(&previous, &current);
+        assert_eq!(delta.removed, [7]);
+        assert_eq!(pids(&delta.added), [7]);
+        assert_eq!(delta.added[0].start_time, 900);
+        assert!(delta.changed.is_empty());
+        assert_eq!(patched(&previous, &delta), current);
+    }
+}
@@ -0,0 +1,78 @@
+// Open file descriptors of a process, like `lsof -p`, read from /proc/[pid]/fd
+use std::io;
+
+use serde::Serialize;
+
+use crate::net::{self, Socket};
+use crate::procfs::ProcFs;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    /// A path: regular files, directories and devices
+    File,
+    Pipe,
+    Socket,
+    /// eventfd, epoll, inotify, timerfd and the like
+    AnonInode,
+    Other,
+}
+
+impl FileKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            FileKind::File => "file",
+            FileKind::Pipe => "pipe",
+            FileKind::Socket => "socket",
+            FileKind::AnonInode => "anon_inode",
+            FileKind::Other => "other",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct OpenFile {
+    pub fd: u32,
+    pub kind: FileKind,
+    /// The path, or the link text for everything else, e.g. `pipe:[4242]` or `anon_inode:[eventfd]`
+    pub target: String,
+    /// The socket's entry in /proc/[pid]/net; `None` for other families such as netlink
+    pub socket: Option<Socket>,
+}
+
+/// What an fd link points to, with the inode of pipes and sockets
+pub fn classify(target: &str) -> (FileKind, Option<u64>) {
+    let inode = |prefix: &str| target.strip_prefix(prefix)?.strip_suffix(']')?.parse().ok();
+    if target.starts_with('/') {
+        (FileKind::File, None)
+    } else if target.starts_with("socket:[") {
+        (FileKind::Socket, inode("socket:["))
+    } else if target.starts_with("pipe:[") {
+        (FileKind::Pipe, inode("pipe:["))
+    } else if target.starts_with("anon_inode:") {
+        (FileKind::AnonInode, None)
+    } else {
+        (FileKind::Other, None)
+    }
+}
+
+/// Every open descriptor of `pid` in fd order, with sockets joined to the socket tables
+pub fn open_files(procfs: &ProcFs, pid: u32) -> io::Result<Vec<OpenFile>> {
+    let fds = procfs.read_fds(pid)?;
+    // Only read the socket tables when there is something to look up in them
+    let mut sockets = None;
+    Ok(fds
+        .into_iter()
+        .map(|(fd, target)| {
+            let target = target.to_string_lossy().into_owned();
+            let (kind, inode) = classify(&target);
+            let socket = match (kind, inode) {
+                (FileKind::Socket, Some(inode)) => {
+                    sockets.get_or_insert_with(|| net::sockets(procfs, pid)).get(&inode).cloned()
+                }
+                _ => None,
+            };
+            OpenFile { fd, kind, target, socket }
+        })
+        .collect())
+}
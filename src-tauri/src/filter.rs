@@ -0,0 +1,842 @@
+// A small query language for picking processes, e.g. `cpu > 20 and user == "ci" and cmd ~ /python/`
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use regex::{Regex, RegexBuilder};
+
+use crate::snapshot::{ProcessSnapshot, ProcessState};
+
+const KB: f64 = 1024.0;
+const MB: f64 = 1024.0 * 1024.0;
+/// Deepest nesting of parentheses and `not` the parser recurses into
+const MAX_DEPTH: usize = 64;
+
+/// A field a filter can test
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Field {
+    Pid,
+    Ppid,
+    Uid,
+    User,
+    Cpu,
+    /// Resident memory, in bytes
+    Mem,
+    /// Resident memory, in percent of physical memory
+    MemPercent,
+    /// Virtual, shared, swapped out, proportional and unique memory, in bytes
+    Vsz,
+    Shared,
+    Swap,
+    Pss,
+    Uss,
+    Cmd,
+    Cmdline,
+    Exe,
+    Cwd,
+    State,
+    Nice,
+    Threads,
+    /// Start time, in Unix seconds
+    Start,
+    /// Seconds since the process started
+    Age,
+    ReadBytes,
+    WriteBytes,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    Number,
+    /// Bytes; bare numbers use `unit` bytes
+    Size { unit: u64 },
+    /// Seconds; bare numbers are seconds
+    Duration,
+    Text,
+    State,
+}
+
+const FIELDS: [(&str, Field); 25] = [
+    ("pid", Field::Pid),
+    ("ppid", Field::Ppid),
+    ("uid", Field::Uid),
+    ("user", Field::User),
+    ("cpu", Field::Cpu),
+    ("mem", Field::Mem),
+    ("rss", Field::Mem),
+    ("mem_percent", Field::MemPercent),
+    ("vsz", Field::Vsz),
+    ("shared", Field::Shared),
+    ("swap", Field::Swap),
+    ("pss", Field::Pss),
+    ("uss", Field::Uss),
+    ("cmd", Field::Cmd),
+    ("name", Field::Cmd),
+    ("cmdline", Field::Cmdline),
+    ("exe", Field::Exe),
+    ("cwd", Field::Cwd),
+    ("state", Field::State),
+    ("nice", Field::Nice),
+    ("threads", Field::Threads),
+    ("start", Field::Start),
+    ("age", Field::Age),
+    ("read_bytes", Field::ReadBytes),
+    ("write_bytes", Field::WriteBytes),
+];
+
+impl Field {
+    fn kind(self) -> Kind {
+        match self {
+            Field::Pid
+            | Field::Ppid
+            | Field::Uid
+            | Field::Cpu
+            | Field::MemPercent
+            | Field::Nice
+            | Field::Threads
+            | Field::Start => Kind::Number,
+            // Memory is shown in MB everywhere, so a bare `mem > 500` means 500 MB
+            Field::Mem | Field::Vsz | Field::Shared | Field::Swap | Field::Pss | Field::Uss => Kind::Size { unit: MB as u64 },
+            Field::ReadBytes | Field::WriteBytes => Kind::Size { unit: 1 },
+            Field::Age => Kind::Duration,
+            Field::User | Field::Cmd | Field::Cmdline | Field::Exe | Field::Cwd => Kind::Text,
+            Field::State => Kind::State,
+        }
+    }
+}
+
+/// A field's value for one process
+pub enum Value<'a> {
+    Number(f64),
+    Text(&'a str),
+    State(ProcessState),
+    /// The field is not known for this process, e.g. the IO counters of another user's process
+    Missing,
+}
+
+/// Anything a filter can be evaluated on
+pub trait Filterable {
+    fn value(&self, field: Field) -> Value<'_>;
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+impl Filterable for ProcessSnapshot {
+    fn value(&self, field: Field) -> Value<'_> {
+        let optional = |value: Option<u64>| value.map_or(Value::Missing, |v| Value::Number(v as f64));
+        let megabytes = |value: Option<f32>| value.map_or(Value::Missing, |mb| Value::Number(mb as f64 * MB));
+        match field {
+            Field::Pid => Value::Number(self.pid as f64),
+            Field::Ppid => optional(self.ppid.map(u64::from)),
+            Field::Uid => optional(self.uid.map(u64::from)),
+            Field::User => self.user.as_deref().map_or(Value::Missing, Value::Text),
+            Field::Cpu => Value::Number(self.cpu as f64),
+            Field::Mem => Value::Number(self.mem as f64 * MB),
+            Field::MemPercent => Value::Number(self.mem_percent as f64),
+            Field::Vsz => Value::Number(self.vsz as f64 * MB),
+            Field::Shared => megabytes(self.shared),
+            Field::Swap => megabytes(self.swap),
+            Field::Pss => megabytes(self.pss),
+            Field::Uss => megabytes(self.uss),
+            Field::Cmd => Value::Text(&self.cmd),
+            Field::Cmdline => Value::Text(&self.cmdline),
+            Field::Exe => self.exe.as_deref().map_or(Value::Missing, Value::Text),
+            Field::Cwd => self.cwd.as_deref().map_or(Value::Missing, Value::Text),
+            Field::State => Value::State(self.state),
+            Field::Nice => Value::Number(self.nice as f64),
+            Field::Threads => Value::Number(self.threads as f64),
+            Field::Start => Value::Number(self.start_time as f64),
+            Field::Age => Value::Number(now_secs().saturating_sub(self.start_time) as f64),
+            Field::ReadBytes => optional(self.read_bytes),
+            Field::WriteBytes => optional(self.write_bytes),
+        }
+    }
+}
+
+/// A parse error and where in the expression it happened
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FilterError {
+    pub message: String,
+    /// Character offset into the expression
+    pub position: usize,
+}
+
+impl FilterError {
+    fn new(position: usize, message: impl Into<String>) -> Self {
+        FilterError { message: message.into(), position }
+    }
+
+    /// The expression with a caret under the error, for showing below an input field
+    pub fn pointer(&self, expression: &str) -> String {
+        format!("{}\n{}^", expression, " ".repeat(self.position))
+    }
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at column {})", self.message, self.position + 1)
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(f64, String),
+    Str(String),
+    Regex(String, bool),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Match,
+    NotMatch,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Op::Eq => "==",
+            Op::Ne => "!=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Match => "~",
+            Op::NotMatch => "!~",
+        })
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Ident(name) => format!("`{}`", name),
+        Token::Number(number, unit) => format!("`{}{}`", number, unit),
+        Token::Str(text) => format!("\"{}\"", text),
+        Token::Regex(pattern, _) => format!("/{}/", pattern),
+        Token::Op(op) => format!("`{}`", op),
+        Token::And => "`and`".to_string(),
+        Token::Or => "`or`".to_string(),
+        Token::Not => "`not`".to_string(),
+        Token::Open => "`(`".to_string(),
+        Token::Close => "`)`".to_string(),
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, FilterError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let token = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => {
+                i += 1;
+                Token::Open
+            }
+            ')' => {
+                i += 1;
+                Token::Close
+            }
+            '=' | '!' | '>' | '<' | '~' => {
+                let (op, len) = match (c, next) {
+                    ('=', Some('=')) => (Some(Op::Eq), 2),
+                    ('=', Some('~')) => (Some(Op::Match), 2),
+                    ('=', _) => (Some(Op::Eq), 1),
+                    ('!', Some('=')) => (Some(Op::Ne), 2),
+                    ('!', Some('~')) => (Some(Op::NotMatch), 2),
+                    ('!', _) => (None, 1),
+                    ('>', Some('=')) => (Some(Op::Ge), 2),
+                    ('>', _) => (Some(Op::Gt), 1),
+                    ('<', Some('=')) => (Some(Op::Le), 2),
+                    ('<', _) => (Some(Op::Lt), 1),
+                    _ => (Some(Op::Match), 1),
+                };
+                i += len;
+                op.map_or(Token::Not, Token::Op)
+            }
+            '&' | '|' => {
+                if next != Some(c) {
+                    return Err(FilterError::new(start, format!("expected `{0}{0}`", c)));
+                }
+                i += 2;
+                if c == '&' { Token::And } else { Token::Or }
+            }
+            '"' | '\'' => {
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(FilterError::new(start, "unterminated string")),
+                        Some('\\') if chars.get(i + 1).is_some() => {
+                            text.push(chars[i + 1]);
+                            i += 2;
+                        }
+                        Some(&q) if q == c => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&other) => {
+                            text.push(other);
+                            i += 1;
+                        }
+                    }
+                }
+                Token::Str(text)
+            }
+            '/' => {
+                let mut pattern = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(FilterError::new(start, "unterminated regex, expected a closing `/`")),
+                        Some('\\') if chars.get(i + 1) == Some(&'/') => {
+                            pattern.push('/');
+                            i += 2;
+                        }
+                        Some('/') => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&other) => {
+                            pattern.push(other);
+                            i += 1;
+                        }
+                    }
+                }
+                let case_insensitive = chars.get(i) == Some(&'i');
+                if case_insensitive {
+                    i += 1;
+                }
+                Token::Regex(pattern, case_insensitive)
+            }
+            c if c.is_ascii_digit() || (matches!(c, '.' | '-') && next.is_some_and(|n| n.is_ascii_digit() || n == '.')) => {
+                // A leading `-` for negative values such as `nice < -5`
+                if c == '-' {
+                    i += 1;
+                }
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let number: String = chars[start..i].iter().collect();
+                let unit_start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphabetic() || chars[i] == '%') {
+                    i += 1;
+                }
+                let unit: String = chars[unit_start..i].iter().collect();
+                let number = number.parse().map_err(|_| FilterError::new(start, format!("invalid number `{}`", number)))?;
+                Token::Number(number, unit)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '-') {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                match word.to_ascii_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Ident(word),
+                }
+            }
+            other => return Err(FilterError::new(start, format!("unexpected character `{}`", other))),
+        };
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Debug)]
+enum Literal {
+    Number(f64),
+    Text(String),
+    Regex(Regex),
+    State(ProcessState),
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Or(Vec<Expr>),
+    And(Vec<Expr>),
+    Not(Box<Expr>),
+    Compare { field: Field, op: Op, value: Literal },
+}
+
+/// A parsed filter expression
+#[derive(Clone, Debug)]
+pub struct Filter {
+    expr: Expr,
+    source: String,
+}
+
+impl Filter {
+    pub fn parse(input: &str) -> Result<Self, FilterError> {
+        let tokens = tokenize(input)?;
+        if tokens.is_empty() {
+            return Err(FilterError::new(0, "empty filter"));
+        }
+        let mut parser = Parser { tokens, index: 0, depth: 0, end: input.chars().count() };
+        let expr = parser.or()?;
+        if let Some((position, token)) = parser.tokens.get(parser.index) {
+            let hint = if *token == Token::Close { "unmatched `)`".to_string() } else { format!("expected `and` or `or` before {}", describe(token)) };
+            return Err(FilterError::new(*position, hint));
+        }
+        Ok(Filter { expr, source: input.trim().to_string() })
+    }
+
+    pub fn matches<P: Filterable>(&self, process: &P) -> bool {
+        evaluate(&self.expr, process)
+    }
+
+    /// Whether any comparison tests `field`, e.g. to read expensive fields only when needed
+    pub fn uses(&self, field: Field) -> bool {
+        mentions(&self.expr, field)
+    }
+
+    /// The expression as it was written
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+impl std::str::FromStr for Filter {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Filter::parse(s)
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    index: usize,
+    /// Parentheses and `not` entered so far
+    depth: usize,
+    /// Position reported for errors at the end of the input
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(_, token)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.index).map_or(self.end, |(position, _)| *position)
+    }
+
+    fn next(&mut self) -> Option<(usize, Token)> {
+        let token = self.tokens.get(self.index).cloned();
+        self.index += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<Expr, FilterError> {
+        let mut terms = vec![self.and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.index += 1;
+            terms.push(self.and()?);
+        }
+        Ok(if terms.len() == 1 { terms.remove(0) } else { Expr::Or(terms) })
+    }
+
+    fn and(&mut self) -> Result<Expr, FilterError> {
+        let mut terms = vec![self.unary()?];
+        while self.peek() == Some(&Token::And) {
+            self.index += 1;
+            terms.push(self.unary()?);
+        }
+        Ok(if terms.len() == 1 { terms.remove(0) } else { Expr::And(terms) })
+    }
+
+    fn unary(&mut self) -> Result<Expr, FilterError> {
+        match self.peek() {
+            Some(Token::Not | Token::Open) if self.depth >= MAX_DEPTH => {
+                Err(FilterError::new(self.position(), format!("nested more than {} levels deep", MAX_DEPTH)))
+            }
+            Some(Token::Not) => {
+                self.index += 1;
+                self.depth += 1;
+                let inner = self.unary();
+                self.depth -= 1;
+                Ok(Expr::Not(Box::new(inner?)))
+            }
+            Some(Token::Open) => {
+                let open = self.position();
+                self.index += 1;
+                self.depth += 1;
+                let expr = self.or();
+                self.depth -= 1;
+                let expr = expr?;
+                match self.next() {
+                    Some((_, Token::Close)) => Ok(expr),
+                    _ => Err(FilterError::new(open, "unclosed `(`")),
+                }
+            }
+            _ => self.comparison(),
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr, FilterError> {
+        let position = self.position();
+        let field = match self.next() {
+            Some((_, Token::Ident(name))) => lookup_field(&name).ok_or_else(|| unknown_field(position, &name))?,
+            Some((_, token)) => return Err(FilterError::new(position, format!("expected a field name, found {}", describe(&token)))),
+            None => return Err(FilterError::new(position, "expected a field name")),
+        };
+
+        let op_position = self.position();
+        let op = match self.next() {
+            Some((_, Token::Op(op))) => op,
+            Some((_, token)) => {
+                return Err(FilterError::new(op_position, format!("expected a comparison (==, !=, >, >=, <, <=, ~, !~), found {}", describe(&token))))
+            }
+            None => return Err(FilterError::new(op_position, "expected a comparison (==, !=, >, >=, <, <=, ~, !~)")),
+        };
+
+        let value_position = self.position();
+        let value = self.next().ok_or_else(|| FilterError::new(value_position, "expected a value"))?.1;
+        let value = typed_value(field, op, value, op_position, value_position)?;
+        Ok(Expr::Compare { field, op, value })
+    }
+}
+
+fn lookup_field(name: &str) -> Option<Field> {
+    let name = name.to_ascii_lowercase();
+    FIELDS.iter().find(|(field, _)| *field == name).map(|(_, field)| *field)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = (previous + usize::from(ca != *cb)).min(row[j] + 1).min(current + 1);
+            previous = current;
+        }
+    }
+    row[b.len()]
+}
+
+fn unknown_field(position: usize, name: &str) -> FilterError {
+    let lower = name.to_ascii_lowercase();
+    let closest = FIELDS.iter().map(|(field, _)| *field).min_by_key(|field| edit_distance(&lower, field));
+    let mut message = format!("unknown field `{}`", name);
+    match closest {
+        Some(field) if edit_distance(&lower, field) <= 2 => message.push_str(&format!(", did you mean `{}`?", field)),
+        _ => {
+            let names: Vec<&str> = FIELDS.iter().map(|(field, _)| *field).collect();
+            message.push_str(&format!("; fields are {}", names.join(", ")));
+        }
+    }
+    FilterError::new(position, message)
+}
+
+/// Regex errors span several lines with their own pointer; keep the last line, which says what is wrong
+fn regex_error(position: usize, error: regex::Error) -> FilterError {
+    let error = error.to_string();
+    let reason = error.lines().last().unwrap_or_default().trim_start_matches("error: ");
+    FilterError::new(position, format!("invalid regex: {}", reason))
+}
+
+fn size_unit(unit: &str) -> Option<f64> {
+    Some(match unit.to_ascii_uppercase().as_str() {
+        "B" => 1.0,
+        "K" | "KB" | "KIB" => KB,
+        "M" | "MB" | "MIB" => MB,
+        "G" | "GB" | "GIB" => MB * KB,
+        "T" | "TB" | "TIB" => MB * MB,
+        _ => return None,
+    })
+}
+
+fn duration_unit(unit: &str) -> Option<f64> {
+    Some(match unit {
+        "s" => 1.0,
+        "m" | "min" => 60.0,
+        "h" => 3600.0,
+        "d" => 86400.0,
+        _ => return None,
+    })
+}
+
+fn state_named(name: &str) -> Option<ProcessState> {
+    const STATES: [ProcessState; 9] = [
+        ProcessState::Running,
+        ProcessState::Sleeping,
+        ProcessState::DiskSleep,
+        ProcessState::Stopped,
+        ProcessState::Tracing,
+        ProcessState::Zombie,
+        ProcessState::Dead,
+        ProcessState::Idle,
+        ProcessState::Unknown,
+    ];
+    // The one-letter codes of ps and /proc/[pid]/stat
+    let mut letters = name.chars();
+    if let (Some(code), None) = (letters.next(), letters.next()) {
+        return Some(ProcessState::from_code(code)).filter(|state| *state != ProcessState::Unknown);
+    }
+    let wanted: String = name.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_ascii_lowercase();
+    STATES.into_iter().find(|state| {
+        format!("{:?}", state).to_ascii_lowercase() == wanted
+            || state.to_string().replace(' ', "").to_ascii_lowercase() == wanted
+    })
+}
+
+/// Checks that `value` fits `field` and `op`, and converts it to the field's unit
+fn typed_value(field: Field, op: Op, value: Token, op_position: usize, position: usize) -> Result<Literal, FilterError> {
+    let kind = field.kind();
+    let field_name = FIELDS.iter().find(|(_, f)| *f == field).map_or("", |(name, _)| *name);
+    let ordering = matches!(op, Op::Gt | Op::Ge | Op::Lt | Op::Le);
+    let matching = matches!(op, Op::Match | Op::NotMatch);
+
+    match kind {
+        Kind::Number | Kind::Size { .. } | Kind::Duration => {
+            if matching {
+                return Err(FilterError::new(op_position, format!("`{}` only works on text fields; `{}` is a number", op, field_name)));
+            }
+            let (number, unit) = match value {
+                Token::Number(number, unit) => (number, unit),
+                other => return Err(FilterError::new(position, format!("`{}` is a number, found {}", field_name, describe(&other)))),
+            };
+            let scale = match kind {
+                Kind::Size { unit: base } if unit.is_empty() => Some(base as f64),
+                Kind::Size { .. } => size_unit(&unit),
+                Kind::Duration if unit.is_empty() => Some(1.0),
+                Kind::Duration => duration_unit(&unit),
+                _ if unit.is_empty() || (matches!(field, Field::Cpu | Field::MemPercent) && unit == "%") => Some(1.0),
+                _ => None,
+            };
+            let scale = scale.ok_or_else(|| {
+                let expected = match kind {
+                    Kind::Size { .. } => "B, KB, MB, GB or TB",
+                    Kind::Duration => "s, m, h or d",
+                    _ => "no unit",
+                };
+                FilterError::new(position, format!("unknown unit `{}` for `{}`, expected {}", unit, field_name, expected))
+            })?;
+            Ok(Literal::Number(number * scale))
+        }
+        Kind::Text => {
+            if ordering {
+                return Err(FilterError::new(op_position, format!("`{}` is text; use ==, != or ~ /regex/", field_name)));
+            }
+            match (value, matching) {
+                (Token::Regex(pattern, case_insensitive), true) => RegexBuilder::new(&pattern)
+                    .case_insensitive(case_insensitive)
+                    .build()
+                    .map(Literal::Regex)
+                    .map_err(|e| regex_error(position, e)),
+                (Token::Str(pattern), true) => Regex::new(&pattern)
+                    .map(Literal::Regex)
+                    .map_err(|e| regex_error(position, e)),
+                (Token::Regex(..), false) => Err(FilterError::new(op_position, format!("use `~` to match a regex, not `{}`", op))),
+                (Token::Str(text), false) | (Token::Ident(text), false) => Ok(Literal::Text(text)),
+                (Token::Number(number, unit), false) => Ok(Literal::Text(format!("{}{}", number, unit))),
+                (other, _) => Err(FilterError::new(position, format!("`{}` is text, found {}", field_name, describe(&other)))),
+            }
+        }
+        Kind::State => {
+            if ordering || matching {
+                return Err(FilterError::new(op_position, "`state` can only be compared with == or !="));
+            }
+            let name = match value {
+                Token::Ident(name) | Token::Str(name) => name,
+                other => return Err(FilterError::new(position, format!("`state` is a process state, found {}", describe(&other)))),
+            };
+            state_named(&name).map(Literal::State).ok_or_else(|| {
+                FilterError::new(
+                    position,
+                    format!("unknown state `{}`; states are Running, Sleeping, DiskSleep, Stopped, Tracing, Zombie, Dead, Idle or their letters R, S, D, T, t, Z, X, I", name),
+                )
+            })
+        }
+    }
+}
+
+fn evaluate<P: Filterable>(expr: &Expr, process: &P) -> bool {
+    match expr {
+        Expr::Or(terms) => terms.iter().any(|term| evaluate(term, process)),
+        Expr::And(terms) => terms.iter().all(|term| evaluate(term, process)),
+        Expr::Not(inner) => !evaluate(inner, process),
+        Expr::Compare { field, op, value } => compare(process.value(*field), *op, value),
+    }
+}
+
+fn mentions(expr: &Expr, wanted: Field) -> bool {
+    match expr {
+        Expr::Or(terms) | Expr::And(terms) => terms.iter().any(|term| mentions(term, wanted)),
+        Expr::Not(inner) => mentions(inner, wanted),
+        Expr::Compare { field, .. } => *field == wanted,
+    }
+}
+
+fn compare(actual: Value<'_>, op: Op, expected: &Literal) -> bool {
+    match (actual, expected) {
+        (Value::Number(actual), Literal::Number(expected)) => match op {
+            Op::Eq => actual == *expected,
+            Op::Ne => actual != *expected,
+            Op::Gt => actual > *expected,
+            Op::Ge => actual >= *expected,
+            Op::Lt => actual < *expected,
+            Op::Le => actual <= *expected,
+            Op::Match | Op::NotMatch => false,
+        },
+        (Value::Text(actual), Literal::Text(expected)) => match op {
+            Op::Eq => actual == expected,
+            Op::Ne => actual != expected,
+            _ => false,
+        },
+        (Value::Text(actual), Literal::Regex(regex)) => match op {
+            Op::Match => regex.is_match(actual),
+            Op::NotMatch => !regex.is_match(actual),
+            _ => false,
+        },
+        (Value::State(actual), Literal::State(expected)) => match op {
+            Op::Eq => actual == *expected,
+            Op::Ne => actual != *expected,
+            _ => false,
+        },
+        // An unknown value only satisfies "is not"
+        (Value::Missing, _) => matches!(op, Op::Ne | Op::NotMatch),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Just the fields the tests compare
+    #[derive(Default)]
+    struct Process {
+        pid: u32,
+        cpu: f64,
+        nice: i32,
+        mem_bytes: f64,
+        age_secs: f64,
+        cmd: &'static str,
+    }
+
+    impl Filterable for Process {
+        fn value(&self, field: Field) -> Value<'_> {
+            match field {
+                Field::Pid => Value::Number(self.pid as f64),
+                Field::Cpu => Value::Number(self.cpu),
+                Field::Nice => Value::Number(self.nice as f64),
+                Field::Mem => Value::Number(self.mem_bytes),
+                Field::Age => Value::Number(self.age_secs),
+                Field::Cmd => Value::Text(self.cmd),
+                _ => Value::Missing,
+            }
+        }
+    }
+
+    fn matches(expression: &str, process: &Process) -> bool {
+        Filter::parse(expression).unwrap().matches(process)
+    }
+
+    fn error(expression: &str) -> FilterError {
+        Filter::parse(expression).unwrap_err()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let process = Process { pid: 1, cpu: 0.0, ..Default::default() };
+        // pid == 1 or (cpu > 50 and nice > 0)
+        assert!(matches("pid == 1 or cpu > 50 and nice > 0", &process));
+        assert!(!matches("(pid == 1 or cpu > 50) and nice > 0", &process));
+        assert!(matches("not cpu > 50 and pid == 1", &process));
+    }
+
+    #[test]
+    fn scales_units() {
+        let process = Process { mem_bytes: 2.0 * MB * KB, age_secs: 7200.0, cpu: 30.0, ..Default::default() };
+        assert!(matches("mem > 1GB", &process));
+        assert!(matches("mem == 2048", &process));
+        assert!(matches("mem >= 2048MiB and mem <= 2g", &process));
+        assert!(!matches("mem > 3G", &process));
+        assert!(matches("age >= 2h and age < 121m", &process));
+        assert!(matches("cpu > 25%", &process));
+        assert_eq!(error("mem > 5 parsecs").position, 8);
+        assert!(error("mem > 5XB").message.contains("unknown unit `XB`"));
+        assert!(error("age > 5GB").message.contains("expected s, m, h or d"));
+    }
+
+    #[test]
+    fn parses_negative_numbers() {
+        let process = Process { nice: -10, ..Default::default() };
+        assert!(matches("nice < -5", &process));
+        assert!(matches("nice==-10", &process));
+        assert!(!matches("nice > -.5", &process));
+        assert!(error("nice < - 5").message.contains("unexpected character `-`"));
+    }
+
+    #[test]
+    fn points_at_the_error() {
+        let expression = "cpu > 5 and cpux == 1";
+        let err = error(expression);
+        assert_eq!(err.position, 12);
+        assert!(err.message.contains("did you mean `cpu`?"));
+        assert_eq!(err.pointer(expression), format!("{}\n{}^", expression, " ".repeat(12)));
+
+        assert_eq!(error("(cpu > 5").position, 0);
+        assert_eq!(error("cpu > 5)").position, 7);
+        assert_eq!(error("cpu >").position, 5);
+        assert_eq!(error("cmd > \"x\"").position, 4);
+        assert_eq!(error("cmd ~ /(/").position, 6);
+        assert_eq!(error("cmd == \"open").position, 7);
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let nested = |depth: usize| format!("{}pid == 1{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(Filter::parse(&nested(MAX_DEPTH)).is_ok());
+        let err = error(&nested(MAX_DEPTH + 1));
+        assert_eq!(err.position, MAX_DEPTH);
+        assert!(err.message.contains("nested"));
+        assert!(Filter::parse(&format!("{}pid == 1", "not ".repeat(100_000))).is_err());
+    }
+
+    #[test]
+    fn round_trips_through_to_string() {
+        let process = Process { pid: 7, cmd: "python3", ..Default::default() };
+        for expression in ["pid == 7 and cmd ~ /PY/i", "not (nice < -5 or mem > 1GB)", "cmd != 'bash'"] {
+            let filter = Filter::parse(&format!("  {} ", expression)).unwrap();
+            assert_eq!(filter.to_string(), expression);
+            let reparsed: Filter = filter.to_string().parse().unwrap();
+            assert_eq!(reparsed.matches(&process), filter.matches(&process));
+            assert!(reparsed.matches(&process));
+        }
+    }
+}
@@ -0,0 +1,33 @@
+// Turns SIGINT and SIGTERM into a flag, so long running commands can stop cleanly
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use nix::libc;
+use nix::sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_stop(_: libc::c_int) {
+    REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// After this, Ctrl-C no longer kills the process; poll `requested` instead
+pub fn install() -> nix::Result<()> {
+    REQUESTED.store(false, Ordering::SeqCst);
+    let action = SigAction::new(SigHandler::Handler(request_stop), SaFlags::empty(), SigSet::empty());
+    for sig in [Signal::SIGINT, Signal::SIGTERM] {
+        unsafe { signal::sigaction(sig, &action) }?;
+    }
+    Ok(())
+}
+
+/// Restores the default behaviour of SIGINT and SIGTERM
+pub fn uninstall() {
+    let action = SigAction::new(SigHandler::SigDfl, SaFlags::empty(), SigSet::empty());
+    for sig in [Signal::SIGINT, Signal::SIGTERM] {
+        let _ = unsafe { signal::sigaction(sig, &action) };
+    }
+}
+
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
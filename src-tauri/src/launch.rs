@@ -0,0 +1,223 @@
+// Everything needed to start a process again the way it was started, captured from /proc
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use serde::{Deserialize, Serialize};
+
+use crate::procfs::ProcFs;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProcessLaunchSpec {
+    pub pid: u32,
+    pub ppid: u32,
+    pub name: String,
+    /// Arguments exactly as passed to exec, including argv[0]
+    #[serde(with = "os_text::list")]
+    pub argv: Vec<OsString>,
+    /// Resolved executable, used instead of looking argv[0] up again
+    pub exe: Option<PathBuf>,
+    pub cwd: Option<PathBuf>,
+    #[serde(with = "os_text::map")]
+    pub env: BTreeMap<OsString, OsString>,
+    /// Real user and group ids
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    /// Start time in clock ticks after boot, to tell the process apart from a later one with the same PID
+    pub start_ticks: u64,
+}
+
+impl ProcessLaunchSpec {
+    /// `None` for kernel threads, which have no command line, and for processes that are gone.
+    /// `cwd` and `env` are left empty when the process belongs to another user and we are not root.
+    pub fn capture(procfs: &ProcFs, pid: u32) -> Option<Self> {
+        let stat = procfs.read_stat(pid)?;
+        let argv = procfs.read_argv(pid)?;
+        if argv.is_empty() {
+            return None;
+        }
+        let status = procfs.read_status(pid);
+        Some(ProcessLaunchSpec {
+            pid,
+            ppid: stat.ppid,
+            name: stat.comm,
+            argv,
+            exe: procfs.read_exe(pid),
+            cwd: procfs.read_cwd(pid),
+            env: procfs.read_environ(pid).unwrap_or_default().into_iter().collect(),
+            uid: status.as_ref().and_then(|s| s.ruid),
+            gid: status.as_ref().and_then(|s| s.rgid),
+            start_ticks: stat.starttime,
+        })
+    }
+
+    /// Every process that can be relaunched
+    pub fn capture_all(procfs: &ProcFs) -> Vec<Self> {
+        procfs.pids().into_iter().filter_map(|pid| ProcessLaunchSpec::capture(procfs, pid)).collect()
+    }
+
+    /// The arguments joined for display; bytes that are not UTF-8 are replaced
+    pub fn command_line(&self) -> String {
+        self.argv.iter().map(|arg| arg.to_string_lossy()).collect::<Vec<_>>().join(" ")
+    }
+
+    /// Whether the captured process is still alive, as opposed to a new one reusing its PID
+    pub fn is_running(&self, procfs: &ProcFs) -> bool {
+        procfs
+            .read_stat(self.pid)
+            .is_some_and(|stat| stat.starttime == self.start_ticks && stat.state != 'Z')
+    }
+
+    /// A command that starts the process again with the same argv, cwd, environment and, when
+    /// running as root, the same user. It is detached from our terminal.
+    pub fn command(&self) -> Command {
+        let program = self.exe.clone().unwrap_or_else(|| PathBuf::from(&self.argv[0]));
+        let mut command = Command::new(program);
+        command.arg0(&self.argv[0]).args(&self.argv[1..]);
+        if !self.env.is_empty() {
+            command.env_clear().envs(&self.env);
+        }
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+        if nix::unistd::geteuid().is_root() {
+            if let Some(gid) = self.gid {
+                command.gid(gid);
+            }
+            if let Some(uid) = self.uid {
+                command.uid(uid);
+            }
+        }
+        command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+        command.process_group(0);
+        command
+    }
+
+    /// Starts the process again and returns its new PID
+    pub fn relaunch(&self) -> io::Result<u32> {
+        Ok(self.command().spawn()?.id())
+    }
+}
+
+/// Launch specs saved on disk, one JSON file per PID, so a process can be relaunched
+/// after procmanager itself restarts
+pub struct LaunchStore {
+    dir: PathBuf,
+}
+
+impl Default for LaunchStore {
+    fn default() -> Self {
+        let state_home = std::env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state")))
+            .unwrap_or_else(std::env::temp_dir);
+        LaunchStore::new(state_home.join("procmanager").join("launch"))
+    }
+}
+
+impl LaunchStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        LaunchStore { dir: dir.into() }
+    }
+
+    fn path(&self, pid: u32) -> PathBuf {
+        self.dir.join(format!("{}.json", pid))
+    }
+
+    /// Readable by the owner only, since the environment may hold secrets
+    pub fn save(&self, spec: &ProcessLaunchSpec) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let mut file = OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(self.path(spec.pid))?;
+        serde_json::to_writer_pretty(&mut file, spec)?;
+        file.write_all(b"\n")
+    }
+
+    pub fn load(&self, pid: u32) -> Option<ProcessLaunchSpec> {
+        serde_json::from_slice(&fs::read(self.path(pid)).ok()?).ok()
+    }
+
+    pub fn remove(&self, pid: u32) -> io::Result<()> {
+        fs::remove_file(self.path(pid))
+    }
+}
+
+/// Arguments and environment are bytes, not necessarily UTF-8. They are saved as JSON strings
+/// when they decode and as byte arrays otherwise, so launch files stay readable.
+mod os_text {
+    use std::collections::BTreeMap;
+    use std::ffi::{OsStr, OsString};
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum Encoded {
+        Text(String),
+        Bytes(Vec<u8>),
+    }
+
+    impl From<&OsStr> for Encoded {
+        fn from(value: &OsStr) -> Self {
+            match value.to_str() {
+                Some(text) => Encoded::Text(text.to_string()),
+                None => Encoded::Bytes(value.as_bytes().to_vec()),
+            }
+        }
+    }
+
+    impl From<Encoded> for OsString {
+        fn from(value: Encoded) -> Self {
+            match value {
+                Encoded::Text(text) => text.into(),
+                Encoded::Bytes(bytes) => OsString::from_vec(bytes),
+            }
+        }
+    }
+
+    pub mod list {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(values: &[OsString], serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(values.iter().map(|value| Encoded::from(value.as_os_str())))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<OsString>, D::Error> {
+            Ok(Vec::<Encoded>::deserialize(deserializer)?.into_iter().map(OsString::from).collect())
+        }
+    }
+
+    /// A `{"KEY": "value"}` object while every name is UTF-8, `[[key, value], ...]` pairs otherwise
+    pub mod map {
+        use super::*;
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Variables {
+            Object(BTreeMap<String, Encoded>),
+            Pairs(Vec<(Encoded, Encoded)>),
+        }
+
+        pub fn serialize<S: Serializer>(values: &BTreeMap<OsString, OsString>, serializer: S) -> Result<S::Ok, S::Error> {
+            if values.keys().all(|key| key.to_str().is_some()) {
+                serializer.collect_map(values.iter().map(|(key, value)| (key.to_string_lossy(), Encoded::from(value.as_os_str()))))
+            } else {
+                serializer.collect_seq(
+                    values.iter().map(|(key, value)| (Encoded::from(key.as_os_str()), Encoded::from(value.as_os_str()))),
+                )
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeMap<OsString, OsString>, D::Error> {
+            Ok(match Variables::deserialize(deserializer)? {
+                Variables::Object(object) => object.into_iter().map(|(key, value)| (key.into(), value.into())).collect(),
+                Variables::Pairs(pairs) => pairs.into_iter().map(|(key, value)| (key.into(), value.into())).collect(),
+            })
+        }
+    }
+}
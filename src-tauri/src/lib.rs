@@ -1,14 +1,48 @@
-// Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
-#[tauri::command]
-fn greet(name: &str) -> String {
-    format!("Hello, {}! You've been greeted from Rust!", name)
-}
+pub mod actions;
+pub mod audit;
+pub mod config;
+pub mod delta;
+pub mod files;
+pub mod filter;
+pub mod interrupt;
+pub mod launch;
+pub mod lifecycle;
+pub mod maps;
+pub mod metrics;
+pub mod net;
+pub mod procfs;
+pub mod selector;
+pub mod snapshot;
+pub mod supervisor;
+pub mod system;
+pub mod threads;
+pub mod track;
+pub mod tree;
+
+mod commands;
+mod stream;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![greet])
+        .manage(commands::AppState::default())
+        .manage(stream::StreamState::default())
+        .setup(|app| {
+            stream::start(app.handle().clone());
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            commands::list_processes,
+            commands::kill,
+            commands::pause,
+            commands::resume,
+            commands::renice,
+            commands::process_tree,
+            commands::system_metrics,
+            commands::track_process,
+            stream::configure_stream,
+        ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
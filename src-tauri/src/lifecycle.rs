@@ -0,0 +1,581 @@
+// Process lifecycle events (fork, exec, exit, uid change, state change) from the kernel's
+// netlink process connector, or from polling /proc when the connector is not available
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::{Duration, Instant};
+
+use chrono::Local;
+use nix::libc;
+use serde::Serialize;
+
+use crate::procfs::{ProcFs, Stat};
+use crate::snapshot::ProcessState;
+
+/// Which processes to report on
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Scope {
+    Pid(u32),
+    /// The process and every process it forks, recursively
+    Descendants(u32),
+    All,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// Seen when monitoring starts
+    Running,
+    Fork,
+    Exec,
+    Exit,
+    UidChange,
+    StateChange,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LifecycleEvent {
+    pub timestamp: String,
+    pub pid: u32,
+    pub event: EventKind,
+    /// Parent, for fork events
+    pub ppid: Option<u32>,
+    /// Command line after an exec, or of a process already running
+    pub cmdline: Option<String>,
+    /// Exit status; `None` when killed by a signal or when the exit was found by polling
+    pub exit_code: Option<i32>,
+    /// Signal that killed the process
+    pub signal: Option<String>,
+    pub ruid: Option<u32>,
+    pub euid: Option<u32>,
+    pub previous_state: Option<ProcessState>,
+    pub state: Option<ProcessState>,
+}
+
+impl LifecycleEvent {
+    fn new(pid: u32, event: EventKind) -> Self {
+        LifecycleEvent {
+            timestamp: Local::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+            pid,
+            event,
+            ppid: None,
+            cmdline: None,
+            exit_code: None,
+            signal: None,
+            ruid: None,
+            euid: None,
+            previous_state: None,
+            state: None,
+        }
+    }
+
+    /// One line description, e.g. "exited with code 0"
+    pub fn describe(&self) -> String {
+        match self.event {
+            EventKind::Running => format!("running ({})", self.cmdline.as_deref().unwrap_or("")),
+            EventKind::Fork => match &self.cmdline {
+                Some(cmdline) => format!("forked from {} ({})", self.ppid.unwrap_or(0), cmdline),
+                None => format!("forked from {}", self.ppid.unwrap_or(0)),
+            },
+            EventKind::Exec => format!("exec {}", self.cmdline.as_deref().unwrap_or("")),
+            EventKind::Exit => match (&self.signal, self.exit_code) {
+                (Some(signal), _) => format!("killed by {}", signal),
+                (None, Some(code)) => format!("exited with code {}", code),
+                (None, None) => "exited".to_string(),
+            },
+            EventKind::UidChange => format!("uid changed to {} (effective {})", self.ruid.unwrap_or(0), self.euid.unwrap_or(0)),
+            EventKind::StateChange => format!(
+                "state changed from {} to {}",
+                self.previous_state.map(|s| s.to_string()).unwrap_or_default(),
+                self.state.map(|s| s.to_string()).unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/// What we remember about a watched process between polls
+struct Tracked {
+    starttime: u64,
+    state: char,
+    ruid: Option<u32>,
+    euid: Option<u32>,
+    cmdline: Vec<String>,
+    /// Gone from /proc while the connector has not reported its exit yet
+    missing: bool,
+}
+
+// Constants of linux/connector.h and linux/cn_proc.h
+const NETLINK_CONNECTOR: libc::c_int = 11;
+const CN_IDX_PROC: u32 = 1;
+const CN_VAL_PROC: u32 = 1;
+const PROC_CN_MCAST_LISTEN: u32 = 1;
+const PROC_EVENT_FORK: u32 = 0x1;
+const PROC_EVENT_EXEC: u32 = 0x2;
+const PROC_EVENT_UID: u32 = 0x4;
+const PROC_EVENT_EXIT: u32 = 0x8000_0000;
+const NLMSG_HDRLEN: usize = 16;
+const CN_MSG_LEN: usize = 20;
+
+/// A socket subscribed to the process connector. Needs CAP_NET_ADMIN.
+struct ProcConnector {
+    fd: OwnedFd,
+}
+
+fn check(res: libc::c_int) -> io::Result<libc::c_int> {
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(res)
+    }
+}
+
+impl ProcConnector {
+    fn open(timeout: Duration) -> io::Result<Self> {
+        let raw = check(unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, NETLINK_CONNECTOR) })?;
+        let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_groups = CN_IDX_PROC;
+        check(unsafe {
+            libc::bind(raw, &addr as *const _ as *const libc::sockaddr, mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t)
+        })?;
+
+        let tv = libc::timeval { tv_sec: timeout.as_secs() as _, tv_usec: timeout.subsec_micros() as _ };
+        check(unsafe {
+            libc::setsockopt(
+                raw,
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &tv as *const _ as *const libc::c_void,
+                mem::size_of::<libc::timeval>() as libc::socklen_t,
+            )
+        })?;
+
+        // nlmsghdr, then cn_msg, then the operation
+        let payload_len = CN_MSG_LEN + 4;
+        let mut message = Vec::with_capacity(NLMSG_HDRLEN + payload_len);
+        message.extend_from_slice(&((NLMSG_HDRLEN + payload_len) as u32).to_ne_bytes());
+        message.extend_from_slice(&(libc::NLMSG_DONE as u16).to_ne_bytes());
+        message.extend_from_slice(&0u16.to_ne_bytes());
+        message.extend_from_slice(&0u32.to_ne_bytes());
+        message.extend_from_slice(&std::process::id().to_ne_bytes());
+        message.extend_from_slice(&CN_IDX_PROC.to_ne_bytes());
+        message.extend_from_slice(&CN_VAL_PROC.to_ne_bytes());
+        message.extend_from_slice(&0u32.to_ne_bytes());
+        message.extend_from_slice(&0u32.to_ne_bytes());
+        message.extend_from_slice(&4u16.to_ne_bytes());
+        message.extend_from_slice(&0u16.to_ne_bytes());
+        message.extend_from_slice(&PROC_CN_MCAST_LISTEN.to_ne_bytes());
+        check(unsafe { libc::send(raw, message.as_ptr() as *const libc::c_void, message.len(), 0) } as libc::c_int)?;
+
+        Ok(ProcConnector { fd })
+    }
+
+    /// Waits up to the receive timeout; returns the raw events received, if any
+    fn receive(&self) -> io::Result<Vec<RawEvent>> {
+        let mut buffer = [0u8; 8192];
+        let len = unsafe { libc::recv(self.fd.as_raw_fd(), buffer.as_mut_ptr() as *mut libc::c_void, buffer.len(), 0) };
+        if len < 0 {
+            let error = io::Error::last_os_error();
+            return match error.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted => Ok(Vec::new()),
+                // ENOBUFS: the kernel dropped events because we fell behind; keep going
+                _ if error.raw_os_error() == Some(libc::ENOBUFS) => Ok(Vec::new()),
+                _ => Err(error),
+            };
+        }
+        Ok(parse_messages(&buffer[..len as usize]))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum RawEvent {
+    Fork { parent_tgid: u32, child_pid: u32, child_tgid: u32 },
+    Exec { tgid: u32 },
+    Uid { tgid: u32, ruid: u32, euid: u32 },
+    Exit { pid: u32, tgid: u32, exit_code: u32 },
+}
+
+fn parse_messages(mut data: &[u8]) -> Vec<RawEvent> {
+    let u32_at = |bytes: &[u8], offset: usize| -> Option<u32> {
+        bytes.get(offset..offset + 4).map(|b| u32::from_ne_bytes(b.try_into().unwrap()))
+    };
+    let mut events = Vec::new();
+    while let Some(len) = u32_at(data, 0) {
+        let len = len as usize;
+        if len < NLMSG_HDRLEN || len > data.len() {
+            break;
+        }
+        // proc_event: what, cpu, timestamp_ns, then the event data
+        let event = &data[NLMSG_HDRLEN + CN_MSG_LEN.min(len - NLMSG_HDRLEN)..len];
+        let field = |index: usize| u32_at(event, 16 + index * 4);
+        let parsed = match u32_at(event, 0) {
+            Some(PROC_EVENT_FORK) => field(1).zip(field(2)).zip(field(3)).map(|((parent_tgid, child_pid), child_tgid)| {
+                RawEvent::Fork { parent_tgid, child_pid, child_tgid }
+            }),
+            Some(PROC_EVENT_EXEC) => field(1).map(|tgid| RawEvent::Exec { tgid }),
+            Some(PROC_EVENT_UID) => field(1).zip(field(2)).zip(field(3)).map(|((tgid, ruid), euid)| RawEvent::Uid { tgid, ruid, euid }),
+            Some(PROC_EVENT_EXIT) => {
+                field(0).zip(field(1)).zip(field(2)).map(|((pid, tgid), exit_code)| RawEvent::Exit { pid, tgid, exit_code })
+            }
+            _ => None,
+        };
+        events.extend(parsed);
+        // Messages are padded to 4 bytes
+        data = &data[((len + 3) & !3).min(data.len())..];
+    }
+    events
+}
+
+/// Follows the processes in `scope`, reporting every lifecycle event
+pub struct Monitor {
+    scope: Scope,
+    procfs: ProcFs,
+    interval: Duration,
+    connector: Option<ProcConnector>,
+    watched: HashMap<u32, Tracked>,
+    last_scan: Option<Instant>,
+    /// Set once the process (or, for descendants, the whole subtree) is gone
+    finished: bool,
+}
+
+impl Monitor {
+    /// Subscribes to the process connector unless `poll` is set or the connector cannot be
+    /// opened (it needs CAP_NET_ADMIN); then /proc is scanned every `interval` instead.
+    /// State changes are always found by reading /proc every `interval`.
+    pub fn new(scope: Scope, interval: Duration, poll: bool) -> Self {
+        let connector = if poll { None } else { ProcConnector::open(interval).ok() };
+        Monitor {
+            scope,
+            procfs: ProcFs::new(),
+            interval,
+            connector,
+            watched: HashMap::new(),
+            last_scan: None,
+            finished: false,
+        }
+    }
+
+    /// "netlink" or "polling"
+    pub fn source(&self) -> &'static str {
+        if self.connector.is_some() {
+            "netlink"
+        } else {
+            "polling"
+        }
+    }
+
+    fn in_scope_root(&self, pid: u32) -> bool {
+        match self.scope {
+            Scope::Pid(root) | Scope::Descendants(root) => pid == root,
+            Scope::All => true,
+        }
+    }
+
+    /// Whether a child of a watched process should be watched too
+    fn follows_children(&self) -> bool {
+        !matches!(self.scope, Scope::Pid(_))
+    }
+
+    fn track(&self, stat: &Stat) -> Tracked {
+        let status = self.procfs.read_status(stat.pid);
+        Tracked {
+            starttime: stat.starttime,
+            state: stat.state,
+            ruid: status.as_ref().and_then(|s| s.ruid),
+            euid: status.as_ref().and_then(|s| s.euid),
+            cmdline: self.procfs.read_cmdline(stat.pid).unwrap_or_default(),
+            missing: false,
+        }
+    }
+
+    /// Reports the processes already in scope; fails when the monitored PID does not exist
+    pub fn start(&mut self, emit: &mut impl FnMut(LifecycleEvent)) -> Result<(), String> {
+        let stats: Vec<Stat> = self.procfs.pids().into_iter().filter_map(|pid| self.procfs.read_stat(pid)).collect();
+        let mut added: HashSet<u32> = stats.iter().filter(|s| self.in_scope_root(s.pid)).map(|s| s.pid).collect();
+        if self.follows_children() {
+            // Parents may be listed after their children, so repeat until nothing new is found
+            loop {
+                let before = added.len();
+                for stat in &stats {
+                    if added.contains(&stat.ppid) {
+                        added.insert(stat.pid);
+                    }
+                }
+                if added.len() == before {
+                    break;
+                }
+            }
+        }
+        if added.is_empty() {
+            return Err("The Process was not found, recheck the PID".to_string());
+        }
+
+        for stat in stats.iter().filter(|s| added.contains(&s.pid)) {
+            let tracked = self.track(stat);
+            let mut event = LifecycleEvent::new(stat.pid, EventKind::Running);
+            event.ppid = Some(stat.ppid);
+            event.cmdline = Some(display_cmdline(&tracked.cmdline, &stat.comm));
+            event.state = Some(ProcessState::from_code(stat.state));
+            event.ruid = tracked.ruid;
+            event.euid = tracked.euid;
+            emit(event);
+            self.watched.insert(stat.pid, tracked);
+        }
+        self.last_scan = Some(Instant::now());
+        Ok(())
+    }
+
+    /// No process left to follow
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Waits for at most about one interval and reports what happened
+    pub fn step(&mut self, emit: &mut impl FnMut(LifecycleEvent)) -> io::Result<()> {
+        match &self.connector {
+            Some(connector) => {
+                let events = connector.receive()?;
+                for event in events {
+                    self.handle(event, emit);
+                }
+                if self.last_scan.is_none_or(|last| last.elapsed() >= self.interval) {
+                    self.scan(false, emit);
+                }
+            }
+            None => {
+                std::thread::sleep(self.interval);
+                self.scan(true, emit);
+            }
+        }
+        self.finished = self.scope != Scope::All && self.watched.is_empty();
+        Ok(())
+    }
+
+    fn handle(&mut self, event: RawEvent, emit: &mut impl FnMut(LifecycleEvent)) {
+        match event {
+            RawEvent::Fork { parent_tgid, child_pid, child_tgid } => {
+                // New threads are reported as forks too
+                if child_pid != child_tgid || (self.scope != Scope::All && !self.watched.contains_key(&parent_tgid)) {
+                    return;
+                }
+                let mut lifecycle = LifecycleEvent::new(child_tgid, EventKind::Fork);
+                lifecycle.ppid = Some(parent_tgid);
+                emit(lifecycle);
+                if self.follows_children() {
+                    if let Some(stat) = self.procfs.read_stat(child_tgid) {
+                        let tracked = self.track(&stat);
+                        self.watched.insert(child_tgid, tracked);
+                    }
+                }
+            }
+            RawEvent::Exec { tgid } => {
+                if !self.watched.contains_key(&tgid) {
+                    return;
+                }
+                let cmdline = self.procfs.read_cmdline(tgid).unwrap_or_default();
+                let comm = self.procfs.read_stat(tgid).map(|s| s.comm).unwrap_or_default();
+                let mut lifecycle = LifecycleEvent::new(tgid, EventKind::Exec);
+                lifecycle.cmdline = Some(display_cmdline(&cmdline, &comm));
+                emit(lifecycle);
+                if let Some(tracked) = self.watched.get_mut(&tgid) {
+                    tracked.cmdline = cmdline;
+                }
+            }
+            RawEvent::Uid { tgid, ruid, euid } => {
+                let Some(tracked) = self.watched.get_mut(&tgid) else { return };
+                tracked.ruid = Some(ruid);
+                tracked.euid = Some(euid);
+                let mut lifecycle = LifecycleEvent::new(tgid, EventKind::UidChange);
+                lifecycle.ruid = Some(ruid);
+                lifecycle.euid = Some(euid);
+                emit(lifecycle);
+            }
+            RawEvent::Exit { pid, tgid, exit_code } => {
+                // Only the exit of the thread group leader ends the process
+                if pid != tgid || self.watched.remove(&tgid).is_none() {
+                    return;
+                }
+                let mut lifecycle = LifecycleEvent::new(tgid, EventKind::Exit);
+                // exit_code is a wait status
+                let signal = exit_code & 0x7f;
+                if signal == 0 {
+                    lifecycle.exit_code = Some(((exit_code >> 8) & 0xff) as i32);
+                } else {
+                    lifecycle.signal = Some(
+                        nix::sys::signal::Signal::try_from(signal as i32)
+                            .map(|s| s.as_str().to_string())
+                            .unwrap_or_else(|_| format!("signal {}", signal)),
+                    );
+                }
+                emit(lifecycle);
+            }
+        }
+    }
+
+    /// Compares /proc with what we saw last time. `full` also looks for new processes,
+    /// exits, execs and uid changes, which the connector reports otherwise.
+    fn scan(&mut self, full: bool, emit: &mut impl FnMut(LifecycleEvent)) {
+        self.last_scan = Some(Instant::now());
+
+        let mut gone = Vec::new();
+        for (&pid, tracked) in self.watched.iter_mut() {
+            let stat = match self.procfs.read_stat(pid) {
+                Some(stat) if stat.starttime == tracked.starttime => stat,
+                // Give the connector until the next scan to deliver the exit, which carries the exit code
+                _ if self.connector.is_some() && !tracked.missing => {
+                    tracked.missing = true;
+                    continue;
+                }
+                _ => {
+                    gone.push(pid);
+                    continue;
+                }
+            };
+            if stat.state != tracked.state {
+                let mut lifecycle = LifecycleEvent::new(pid, EventKind::StateChange);
+                lifecycle.previous_state = Some(ProcessState::from_code(tracked.state));
+                lifecycle.state = Some(ProcessState::from_code(stat.state));
+                emit(lifecycle);
+                tracked.state = stat.state;
+            }
+            if !full {
+                continue;
+            }
+
+            let status = self.procfs.read_status(pid);
+            let (ruid, euid) = (status.as_ref().and_then(|s| s.ruid), status.as_ref().and_then(|s| s.euid));
+            if (ruid, euid) != (tracked.ruid, tracked.euid) {
+                let mut lifecycle = LifecycleEvent::new(pid, EventKind::UidChange);
+                lifecycle.ruid = ruid;
+                lifecycle.euid = euid;
+                emit(lifecycle);
+                tracked.ruid = ruid;
+                tracked.euid = euid;
+            }
+            let cmdline = self.procfs.read_cmdline(pid).unwrap_or_default();
+            if cmdline != tracked.cmdline {
+                let mut lifecycle = LifecycleEvent::new(pid, EventKind::Exec);
+                lifecycle.cmdline = Some(display_cmdline(&cmdline, &stat.comm));
+                emit(lifecycle);
+                tracked.cmdline = cmdline;
+            }
+        }
+
+        // Exits seen here were missed by the connector or found by polling, so there is no exit code
+        for pid in gone {
+            self.watched.remove(&pid);
+            emit(LifecycleEvent::new(pid, EventKind::Exit));
+        }
+
+        if full {
+            self.find_new(emit);
+        }
+    }
+
+    fn find_new(&mut self, emit: &mut impl FnMut(LifecycleEvent)) {
+        if !self.follows_children() {
+            return;
+        }
+        let mut stats: Vec<Stat> = self
+            .procfs
+            .pids()
+            .into_iter()
+            .filter(|pid| !self.watched.contains_key(pid))
+            .filter_map(|pid| self.procfs.read_stat(pid))
+            .collect();
+        // Oldest first, so a parent is added before its children
+        stats.sort_by_key(|s| s.starttime);
+        for stat in stats {
+            if self.scope != Scope::All && !self.watched.contains_key(&stat.ppid) {
+                continue;
+            }
+            let tracked = self.track(&stat);
+            let mut lifecycle = LifecycleEvent::new(stat.pid, EventKind::Fork);
+            lifecycle.ppid = Some(stat.ppid);
+            // By the time a scan finds the process it has usually exec'd already
+            lifecycle.cmdline = Some(display_cmdline(&tracked.cmdline, &stat.comm));
+            emit(lifecycle);
+            self.watched.insert(stat.pid, tracked);
+        }
+    }
+}
+
+fn display_cmdline(cmdline: &[String], comm: &str) -> String {
+    if cmdline.is_empty() {
+        format!("[{}]", comm)
+    } else {
+        cmdline.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A netlink message as the process connector sends it: nlmsghdr, cn_msg, then a proc_event
+    /// of `what` with `data` as its event fields
+    fn message(what: u32, data: &[u32]) -> Vec<u8> {
+        let event_len = 16 + data.len() * 4;
+        let len = NLMSG_HDRLEN + CN_MSG_LEN + event_len;
+        let mut bytes = Vec::with_capacity(len);
+        bytes.extend_from_slice(&(len as u32).to_ne_bytes());
+        bytes.extend_from_slice(&(libc::NLMSG_DONE as u16).to_ne_bytes());
+        bytes.extend_from_slice(&0u16.to_ne_bytes());
+        bytes.extend_from_slice(&0u32.to_ne_bytes());
+        bytes.extend_from_slice(&0u32.to_ne_bytes());
+        for value in [CN_IDX_PROC, CN_VAL_PROC, 0, 0] {
+            bytes.extend_from_slice(&value.to_ne_bytes());
+        }
+        bytes.extend_from_slice(&(event_len as u16).to_ne_bytes());
+        bytes.extend_from_slice(&0u16.to_ne_bytes());
+        // what, cpu, timestamp_ns
+        bytes.extend_from_slice(&what.to_ne_bytes());
+        bytes.extend_from_slice(&0u32.to_ne_bytes());
+        bytes.extend_from_slice(&123_456_789u64.to_ne_bytes());
+        for value in data {
+            bytes.extend_from_slice(&value.to_ne_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn parses_fork_exec_and_exit() {
+        let mut data = message(PROC_EVENT_FORK, &[100, 100, 200, 200]);
+        data.extend(message(PROC_EVENT_EXEC, &[200, 200]));
+        // exit_code, exit_signal, parent_pid, parent_tgid
+        data.extend(message(PROC_EVENT_EXIT, &[201, 200, 256, 17, 100, 100]));
+        assert_eq!(
+            parse_messages(&data),
+            [
+                RawEvent::Fork { parent_tgid: 100, child_pid: 200, child_tgid: 200 },
+                RawEvent::Exec { tgid: 200 },
+                RawEvent::Exit { pid: 201, tgid: 200, exit_code: 256 },
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_other_events() {
+        // PROC_EVENT_NONE is the reply to our subscription, 0x40 is PROC_EVENT_GID
+        let mut data = message(0, &[0]);
+        data.extend(message(0x40, &[300, 300, 1000, 1000]));
+        data.extend(message(PROC_EVENT_EXEC, &[300, 300]));
+        assert_eq!(parse_messages(&data), [RawEvent::Exec { tgid: 300 }]);
+    }
+
+    #[test]
+    fn stops_at_a_truncated_message() {
+        let mut data = message(PROC_EVENT_EXEC, &[400, 400]);
+        let fork = message(PROC_EVENT_FORK, &[400, 400, 401, 401]);
+        data.extend_from_slice(&fork[..fork.len() - 8]);
+        assert_eq!(parse_messages(&data), [RawEvent::Exec { tgid: 400 }]);
+        // A fork event cut short by its own header is dropped rather than misread
+        let mut short = message(PROC_EVENT_FORK, &[500, 500]);
+        short.extend(message(PROC_EVENT_EXEC, &[501, 501]));
+        assert_eq!(parse_messages(&short), [RawEvent::Exec { tgid: 501 }]);
+        assert!(parse_messages(&[]).is_empty());
+    }
+}
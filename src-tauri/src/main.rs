@@ -1,563 +1,1058 @@
 use std::{env, fs::File, io::Write};
+use std::path::PathBuf;
 use std::{thread, time};
-use std::process::Command;
-use sysinfo::System;
+use std::process::{Command, ExitCode};
 mod TUI;
 use std::io;
-use users::get_user_by_uid;
-use nix::sys::signal::{kill, Signal};
-use chrono::{Local}; 
-use sysinfo::{Pid};
+use procmanager_lib::actions::{self, ActionError};
+use procmanager_lib::audit::{self, AuditLog, Source};
+use procmanager_lib::config::{self, ColumnConfig, Config};
+use procmanager_lib::files;
+use procmanager_lib::filter::{Field, Filter};
+use procmanager_lib::interrupt;
+use procmanager_lib::launch::{LaunchStore, ProcessLaunchSpec};
+use procmanager_lib::lifecycle::{LifecycleEvent, Monitor, Scope};
+use procmanager_lib::maps::{self, Mapping, MappingTotal};
+use procmanager_lib::metrics::MetricsFilter;
+use procmanager_lib::net;
+use procmanager_lib::procfs::ProcFs;
+use procmanager_lib::selector::{self, Selector};
+use procmanager_lib::snapshot::{self, take_snapshot, ProcessSnapshot, Snapshotter};
+use procmanager_lib::supervisor::{self, ControlCommand, ControlRequest, SupervisorConfig};
+use procmanager_lib::threads::take_thread_snapshot;
+use procmanager_lib::track::{self, TrackSample};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use regex::Regex;
+use nix::sys::signal::Signal;
+mod output;
+use output::{
+    print_records, report_action, write_records, ActionRecord, CommandRecord, OsRecord, OutputFormat, RecordWriter,
+};
+mod serve;
 
-fn log_by_pid(pid_str: String) {
-    let mut system = System::new_all();
-    system.refresh_all();
-    thread::sleep(time::Duration::from_secs(1));
-    system.refresh_all();
-
-    let pid = match pid_str.parse::<u32>() {
-        Ok(pid) => Pid::from(pid as usize),
-        Err(_) => {
-            println!("Invalid PID string: {}", pid_str);
+fn log_lifecycle(scope: Scope, output: Option<&str>, poll: bool, interval: time::Duration, format: OutputFormat) -> bool {
+    let out: Box<dyn Write> = match output {
+        Some(path) => match std::fs::OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => Box::new(file),
+            Err(e) => {
+                eprintln!("Failed to open {}: {}", path, e);
+                return false;
+            }
+        },
+        None => Box::new(io::stdout()),
+    };
+    // Table output is written line by line so the log streams like the other formats
+    let mut lines = None;
+    let mut writer = None;
+    if format == OutputFormat::Table {
+        lines = Some(out);
+    } else {
+        match RecordWriter::new(out, format) {
+            Ok(w) => writer = Some(w),
+            Err(e) => {
+                eprintln!("Failed to write output: {}", e);
+                return false;
+            }
+        }
+    }
+    let write_failed = std::cell::Cell::new(false);
+    let mut emit = |event: LifecycleEvent| {
+        if write_failed.get() {
             return;
         }
+        let result = match (&mut lines, &mut writer) {
+            (Some(out), _) => writeln!(out, "[{}] Process {} {}", event.timestamp, event.pid, event.describe()).and_then(|_| out.flush()),
+            (_, Some(writer)) => writer.write(&event),
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            // e.g. the reading end of a pipe went away
+            if e.kind() != io::ErrorKind::BrokenPipe {
+                eprintln!("Failed to write output: {}", e);
+            }
+            write_failed.set(true);
+        }
     };
 
-    let processes: Vec<_> = system
-        .processes()
-        .iter()
-        .map(|(id, process)| (id.to_string(), process))
-        .collect();
+    let mut monitor = Monitor::new(scope, interval, poll);
+    if let Err(e) = monitor.start(&mut emit) {
+        eprintln!("{}", e);
+        return false;
+    }
+    eprintln!("Watching process events using {} (Ctrl-C to stop)", monitor.source());
 
-    let mut found = false;
-    let mut log = vec![];
-
-    for (id, process) in processes {
-        if id == pid_str {
-            found = true;
-            let creation_time = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-            log.push(format!("[{}] Process {} created", creation_time, pid));
-
-            let mut last_state = process.status().to_string();
-
-            loop {
-                thread::sleep(time::Duration::from_secs(1));
-                system.refresh_all(); 
-                if let Some(process) = system.process(pid) {
-                    let current_state = process.status().to_string();
-
-                    if current_state != last_state {
-                        let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-                        log.push(format!("[{}] Status of Process {} changed to: {}", timestamp, pid, current_state));
-                        last_state = current_state;
-                        break;
-                    }
-                } else {
-                    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-                    log.push(format!("[{}] Process {} terminated", timestamp, pid));
-                    break;
-                }
-            }
+    let _ = interrupt::install();
+    let mut ok = true;
+    while !interrupt::requested() && !monitor.finished() && !write_failed.get() {
+        if let Err(e) = monitor.step(&mut emit) {
+            eprintln!("Process event monitor failed: {}", e);
+            ok = false;
             break;
         }
     }
+    interrupt::uninstall();
 
-    if !found {
-        println!("The Process was not found, recheck the PID");
-    } else {
-        for entry in log {
-            println!("{}", entry);
+    if let Some(writer) = writer {
+        let _ = writer.finish();
+    }
+    ok && !write_failed.get()
+}
+fn kill_by_pid(pid: u32, source: Source) -> ActionRecord {
+    let name = actions::process_name(pid);
+
+    let result = audit::audited(source, pid, "kill", &[], false, || actions::kill(pid));
+    ActionRecord {
+        pid,
+        action: "kill".to_string(),
+        success: result.is_ok(),
+        message: match &result {
+            Ok(()) => format!(" {} was killed, PID: {}", name.unwrap_or_default(), pid),
+            Err(ActionError::NotFound { .. }) => "The Process was not found, recheck the PID".to_string(),
+            Err(e) => format!("Failed to kill PID {}: {}", pid, e),
+        },
+    }
+}
+fn parse_signal_arg(value: &str) -> Result<Signal, String> {
+    actions::parse_signal(value).ok_or_else(|| format!("unknown signal {}", value))
+}
+
+/// How long an explicitly chosen signal gets to make the process exit before we report on it
+const SIGNAL_EXIT_WAIT: time::Duration = time::Duration::from_millis(500);
+
+fn signal_process(pid: u32, signal: Option<Signal>, timeout: time::Duration, format: OutputFormat, source: Source) -> bool {
+    let name = actions::process_name(pid).unwrap_or_default();
+    let result = match signal {
+        Some(signal) => {
+            audit::audited(source, pid, "signal", &[signal.as_str().to_string()], false, || {
+                actions::send(pid, signal, SIGNAL_EXIT_WAIT)
+            })
+        }
+        None => {
+            let args = ["SIGTERM".to_string(), format!("SIGKILL after {}s", timeout.as_secs())];
+            audit::audited(source, pid, "signal", &args, false, || actions::terminate(pid, timeout))
+        }
+    };
+
+    let outcome = match result {
+        Ok(outcome) => outcome,
+        Err(ActionError::NotFound { .. }) => {
+            eprintln!("The Process was not found, recheck the PID");
+            return false;
+        }
+        Err(e) => {
+            eprintln!("Failed to signal PID {}: {}", pid, e);
+            return false;
         }
+    };
+    if format == OutputFormat::Table {
+        let sent = outcome.signals.join(" then ");
+        if outcome.exited {
+            println!("Sent {} to {} (PID {}); it exited", sent, name, pid);
+        } else if signal.is_some() {
+            println!("Sent {} to {} (PID {}); it is still running", sent, name, pid);
+        } else {
+            println!("Sent {} to {} (PID {}) but it is still running", sent, name, pid);
+        }
+    } else {
+        print_records(format, std::slice::from_ref(&outcome));
     }
+    // An explicit signal need not end the process; the graceful stop has failed if it did not
+    signal.is_some() || outcome.exited
 }
-fn kill_by_pid(pid: String) {
-    let mut system = System::new_all();
-    system.refresh_all();
-    thread::sleep(time::Duration::from_secs(1));
-    system.refresh_all();
 
-    let processes: Vec<_> = system
-        .processes()
-        .iter()
-        .map(|(id, process)| (id.to_string(), process))
-        .collect();
+/// Runs `action` on `pid`, or on every process `select` matches, and reports the results.
+/// With --dry-run the processes are only listed.
+fn for_each_target(pid: Option<u32>, select: &SelectorArgs, format: OutputFormat, mut action: impl FnMut(u32) -> ActionRecord) -> bool {
+    if let (Some(pid), false) = (pid, select.dry_run) {
+        let record = action(pid);
+        let success = record.success;
+        report_action(format, record);
+        return success;
+    }
 
-    let mut found = false;
-    for (id, process) in processes {
-        if id == pid {
-            process.kill();
-            found = true;
-            println!(
-                " {} was killed, PID: {}",
-                process.name().to_string_lossy(),
-                id
-            );
-            break;
+    let processes = take_snapshot(snapshot::DEFAULT_INTERVAL);
+    let selector = select.selector();
+    let targets: Vec<ProcessSnapshot> = match pid {
+        Some(pid) => processes.iter().filter(|p| p.pid == pid).cloned().collect(),
+        None => selector.select(&processes).into_iter().cloned().collect(),
+    };
+    if targets.is_empty() {
+        eprintln!("No process matches.");
+        return false;
+    }
+    if select.dry_run {
+        print_records(format, &targets);
+        if format == OutputFormat::Table {
+            println!("{} process(es) would be affected (dry run).", targets.len());
         }
+        return true;
     }
-    if !found {
-        println!("The Process was not found, recheck the PID");
+
+    // Table output reports each process as it is done; the other formats print one list
+    let mut records = Vec::new();
+    for process in &targets {
+        let record = action(process.pid);
+        if format == OutputFormat::Table {
+            report_action(format, record.clone());
+        }
+        records.push(record);
     }
+    let succeeded = records.iter().filter(|record| record.success).count();
+    if format == OutputFormat::Table {
+        println!("{} of {} process(es) succeeded.", succeeded, targets.len());
+    } else {
+        print_records(format, &records);
+    }
+    succeeded == targets.len()
 }
-fn ptable(file_path: Option<&str>) {
-    let mut system = System::new_all();
-
-    system.refresh_all();
-    thread::sleep(time::Duration::from_secs(1));
-    system.refresh_all();
 
-    let mut processes: Vec<_> = system
-        .processes()
-        .iter()
-        .map(|(id, process)| (id.to_string(), process))
-        .collect();
+fn ptable(file_path: Option<&str>, sort: SortKey, limit: Option<usize>, filter: Option<&Filter>, pss: bool, format: OutputFormat) -> bool {
+    let mut snapshotter = Snapshotter::new(time::Duration::from_secs(1));
+    // Like the TUI, PSS and USS are only read when something shows or uses them
+    let proportional_needed = pss
+        || matches!(sort, SortKey::Pss | SortKey::Uss)
+        || filter.is_some_and(|filter| filter.uses(Field::Pss) || filter.uses(Field::Uss));
+    snapshotter.set_proportional_memory(proportional_needed);
+    let mut processes = snapshotter.refresh();
+    if let Some(filter) = filter {
+        processes.retain(|process| filter.matches(process));
+    }
 
-    processes.sort_by(|a, b| b.1.cpu_usage().partial_cmp(&a.1.cpu_usage()).unwrap());
+    match sort {
+        SortKey::Cpu => processes.sort_by(|a, b| b.cpu.partial_cmp(&a.cpu).unwrap_or(std::cmp::Ordering::Equal)),
+        SortKey::Mem => processes.sort_by(|a, b| b.mem.partial_cmp(&a.mem).unwrap_or(std::cmp::Ordering::Equal)),
+        // Processes whose memory could not be read sort last
+        SortKey::Pss => processes.sort_by(|a, b| b.pss.partial_cmp(&a.pss).unwrap_or(std::cmp::Ordering::Equal)),
+        SortKey::Uss => processes.sort_by(|a, b| b.uss.partial_cmp(&a.uss).unwrap_or(std::cmp::Ordering::Equal)),
+        SortKey::Swap => processes.sort_by(|a, b| b.swap.partial_cmp(&a.swap).unwrap_or(std::cmp::Ordering::Equal)),
+        SortKey::Pid => processes.sort_by_key(|process| process.pid),
+        SortKey::Name => processes.sort_by(|a, b| a.cmd.cmp(&b.cmd)),
+    }
+    if let Some(limit) = limit {
+        processes.truncate(limit);
+    }
 
     if let Some(path) = file_path {
-        if path.ends_with(".csv") {
-            match File::create(path) {
-                Ok(mut file) => {
-                    writeln!(
-                        file,
-                        "{},{},{},{},{}",
-                        "PID", "Process Name", "CPU (%)", "Memory (MB)", "Status"
-                    )
-                    .unwrap();
-
-                    for (id, process) in &processes {
-                        writeln!(
-                            file,
-                            "{},{},{:.2},{},{}",
-                            id,
-                            process.name().to_string_lossy(),
-                            process.cpu_usage(),
-                            process.memory(),
-                            format!("{:?}", process.status())
-                        )
-                        .unwrap();
-                    }
-
-                    println!("Exported process table to: {}", path);
-                }
-                Err(e) => {
-                    eprintln!("Failed to create file {}: {}", path, e);
-                }
-            }
-        } else {
+        // Exported files have always been CSV unless another format is asked for
+        let format = if format == OutputFormat::Table { OutputFormat::Csv } else { format };
+        if format == OutputFormat::Csv && !path.ends_with(".csv") {
             eprintln!("Error: Please provide a .csv file path.");
+            return false;
+        }
+        match File::create(path).and_then(|file| write_records(file, format, &processes)) {
+            Ok(()) => {
+                println!("Exported process table to: {}", path);
+                true
+            }
+            Err(e) => {
+                eprintln!("Failed to write file {}: {}", path, e);
+                false
+            }
         }
     } else {
-        println!(
-            "{:<10} {:<45} {:<10} {:<15} {:<10}",
-            "PID", "Process Name", "CPU (%)", "Memory (MB)", "Status"
-        );
-        println!("{}", "-".repeat(75));
-        for (id, process) in &processes {
-            println!(
-                "{:<10} {:<45} {:<10.2} {:<15} {:<10}",
-                id,
-                process.name().to_string_lossy(),
-                process.cpu_usage(),
-                process.memory()/1024,
-                format!("{:?}", process.status())
-            );
-        }
-    }
-}
-fn track_process(pid: String, path: String, duration_secs: u64) {
-    let mut system = System::new_all();
-    let mut file = match File::create(&path) {
+        print_records(format, &processes);
+        true
+    }
+}
+fn track_process(pid: u32, path: String, duration_secs: u64, format: OutputFormat) -> bool {
+    let file = match File::create(&path) {
         Ok(f) => f,
         Err(e) => {
             eprintln!("Failed to create file {}: {}", path, e);
-            return;
+            return false;
         }
     };
 
-    writeln!(file, "Timestamp,CPU (%),Memory (KB)").unwrap();
-
-    let start_time = time::Instant::now();
-    let mut total_cpu: f32 = 0.0;
-    let mut total_memory: u64 = 0;
-    let mut count: u64 = 0;
-
-    while start_time.elapsed().as_secs() < duration_secs {
-        system.refresh_all();
-        if let Some(process) = system.processes().get(&pid.parse().unwrap()) {
-            total_cpu += process.cpu_usage();
-            total_memory += process.memory();
-            count += 1;
-
-            writeln!(
-                file,
-                "{}, {:.2}, {}",
-                chrono::Utc::now().format("%Y-%m-%d %H:%M:%S"),
-                process.cpu_usage(),
-                process.memory()
-            )
-            .unwrap();
-        } else {
-            println!("Process {} not found. Stopping monitoring.", pid);
-            break;
+    // Samples are streamed to the file, which stays CSV unless another format is asked for
+    let file_format = if format == OutputFormat::Table { OutputFormat::Csv } else { format };
+    let mut writer = match RecordWriter::<_, TrackSample>::new(file, file_format) {
+        Ok(writer) => writer,
+        Err(e) => {
+            eprintln!("Failed to write file {}: {}", path, e);
+            return false;
+        }
+    };
+
+    let mut write_error = None;
+    let result = track::track(pid, time::Duration::from_secs(duration_secs), time::Duration::from_secs(1), |sample| {
+        if write_error.is_none() {
+            write_error = writer.write(sample).err();
         }
-        thread::sleep(time::Duration::from_secs(1));
+    });
+    if let Some(e) = write_error.or_else(|| writer.finish().err()) {
+        eprintln!("Failed to write file {}: {}", path, e);
+        return false;
     }
-    if count > 0 {
-        let avg_cpu = total_cpu / count as f32;
-        let avg_memory = total_memory / count;
 
-        println!(
-            "Tracking complete. Data saved to {}",
-            path
-        );
-        println!("Average CPU Usage: {:.2}%", avg_cpu);
-        println!("Average Memory Usage: {} KB", avg_memory);
-    } else {
-        println!("No data collected. The process may not have been available.");
+    match result {
+        Ok(summary) if summary.samples > 0 => {
+            if summary.exited {
+                eprintln!("Process {} not found. Stopping monitoring.", pid);
+            }
+            if format == OutputFormat::Table {
+                println!(
+                    "Tracking complete. Data saved to {}",
+                    path
+                );
+                println!("Average CPU Usage: {:.2}%", summary.avg_cpu);
+                println!("Average Memory Usage: {} KB", summary.avg_memory_kb);
+            } else {
+                print_records(format, &[summary]);
+            }
+            true
+        }
+        _ => {
+            eprintln!("No data collected. The process may not have been available.");
+            false
+        }
     }
 }
 fn get_process_command(pid: u32) -> String {
-  
-    let output = Command::new("ps")
-        .arg("-p")
-        .arg(pid.to_string())
-        .arg("-o")
-        .arg("command=")  
-        .output()
-        .expect("Failed to fetch process command");
-
-    if output.status.success() {
-        
-        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    let procfs = ProcFs::new();
+    match procfs.read_cmdline(pid) {
+        Some(args) if !args.is_empty() => args.join(" "),
+        // Kernel threads have no command line; show their name the way ps does
+        Some(_) => procfs.read_stat(pid).map(|stat| format!("[{}]", stat.comm)).unwrap_or_default(),
+        None => String::new(),
+    }
+}
+fn threads(pid: u32, format: OutputFormat) -> bool {
+    let Some(mut threads) = take_thread_snapshot(pid, snapshot::DEFAULT_INTERVAL) else {
+        eprintln!("The Process was not found, recheck the PID");
+        return false;
+    };
+    threads.sort_by(|a, b| b.cpu.partial_cmp(&a.cpu).unwrap_or(std::cmp::Ordering::Equal).then(a.tid.cmp(&b.tid)));
+    print_records(format, &threads);
+    true
+}
+fn files(pid: u32, format: OutputFormat) -> bool {
+    match files::open_files(&ProcFs::new(), pid) {
+        Ok(files) => {
+            print_records(format, &files);
+            true
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            eprintln!("The Process was not found, recheck the PID");
+            false
+        }
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            eprintln!("Permission denied reading the open files of PID {}; run as its owner or as root", pid);
+            false
+        }
+        Err(e) => {
+            eprintln!("Failed to read the open files of PID {}: {}", pid, e);
+            false
+        }
+    }
+}
+fn memory_maps(pid: u32, sort: MapSortKey, totals: bool, format: OutputFormat) -> bool {
+    let mut mappings = match maps::mappings(&ProcFs::new(), pid) {
+        Ok(mappings) => mappings,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            eprintln!("The Process was not found, recheck the PID");
+            return false;
+        }
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            eprintln!("Permission denied reading the memory maps of PID {}; run as its owner or as root", pid);
+            return false;
+        }
+        Err(e) => {
+            eprintln!("Failed to read the memory maps of PID {}: {}", pid, e);
+            return false;
+        }
+    };
+    // Sizes sort largest first; both lists are already in address order
+    let key = |size_kb: u64, rss_kb: u64, pss_kb: u64, swap_kb: u64| match sort {
+        MapSortKey::Address => 0,
+        MapSortKey::Size => size_kb,
+        MapSortKey::Rss => rss_kb,
+        MapSortKey::Pss => pss_kb,
+        MapSortKey::Swap => swap_kb,
+    };
+    if totals {
+        let mut totals = maps::totals(&mappings);
+        totals.sort_by_key(|t: &MappingTotal| std::cmp::Reverse(key(t.size_kb, t.rss_kb, t.pss_kb, t.swap_kb)));
+        print_records(format, &totals);
     } else {
-        String::new()
-    }
-}
-fn get_pid_and_command() -> Vec<(u32, String)> {
-    let mut system = System::new_all();
-    system.refresh_all();
-
-    let mut pid_and_commands = Vec::new();
-
-    for (pid, process) in system.processes() {
-        let cmd = process.cmd().iter()
-            .map(|arg| arg.to_string_lossy().into_owned()) 
-            .collect::<Vec<String>>()
-            .join(" ");
-        pid_and_commands.push((
-            pid.as_u32(), 
-            if cmd.is_empty() { "[no command]".to_string() } else { cmd }
-        ));
-    }
-
-    pid_and_commands
-}
-fn restart_if_failed(pid: u32, initial_pids: &Vec<(u32, String)>, current_pids: &Vec<(u32, String)>) {
-    if initial_pids.iter().any(|(initial_pid, _)| *initial_pid == pid) {
-        if !current_pids.iter().any(|(current_pid, _)| *current_pid == pid) {
-            println!("Process {} has stopped. Restarting...", pid);
-
-            let command = initial_pids.iter()
-                .find(|(initial_pid, _)| *initial_pid == pid)
-                .map(|(_, cmd)| cmd.clone())
-                .unwrap_or_else(|| String::new());
-
-            if !command.is_empty() {
-                let child = Command::new("xterm")
-                    .arg("-e")
-                    .arg(command.clone())
-                    .spawn();
-
-                match child {
-                    Ok(child_proc) => {
-                        println!("Restarted process with new PID: {}", child_proc.id());
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to restart process: {}", e);
-                    }
-                }
-            } else {
-                eprintln!("Could not retrieve command for PID {}", pid);
-            }
-        } else {
-            println!("Process {} is already running.", pid);
+        mappings.sort_by_key(|m: &Mapping| std::cmp::Reverse(key(m.size_kb, m.rss_kb, m.pss_kb, m.swap_kb)));
+        print_records(format, &mappings);
+    }
+    true
+}
+fn port(port: u16, format: OutputFormat) -> bool {
+    let owners = net::port_owners(&ProcFs::new(), port);
+    if owners.is_empty() {
+        eprintln!("No process has a socket on port {}", port);
+        if !nix::unistd::geteuid().is_root() {
+            eprintln!("Only your own processes were searched; run as root to see every process");
         }
+        return false;
+    }
+    print_records(format, &owners);
+    true
+}
+fn capture_launch(pid: u32, format: OutputFormat) -> bool {
+    let Some(spec) = ProcessLaunchSpec::capture(&ProcFs::new(), pid) else {
+        eprintln!("Cannot capture PID {}: it is not running or is a kernel thread", pid);
+        return false;
+    };
+    let store = LaunchStore::default();
+    if let Err(e) = store.save(&spec) {
+        eprintln!("Failed to save launch context for PID {}: {}", pid, e);
+        return false;
+    }
+    if format == OutputFormat::Table {
+        println!("Saved launch context for PID {} ({}); restart_if_failed {} can now relaunch it", pid, spec.command_line(), pid);
     } else {
-        println!("PID {} not found in the initial table.", pid);
+        print_records(format, &[spec]);
     }
+    true
+}
 
-    thread::sleep(time::Duration::from_secs(10));
+fn history(limit: usize, pid: Option<u32>, action: Option<String>, format: OutputFormat) -> bool {
+    let log = AuditLog::default();
+    let mut entries: Vec<_> = log
+        .read_all()
+        .into_iter()
+        .filter(|entry| pid.is_none_or(|pid| entry.pid == pid))
+        .filter(|entry| action.as_deref().is_none_or(|action| entry.action == action))
+        .collect();
+    if entries.is_empty() && format == OutputFormat::Table {
+        println!("No matching actions recorded in {}", log.path().display());
+        return true;
+    }
+    let skip = entries.len().saturating_sub(limit);
+    entries.drain(..skip);
+    print_records(format, &entries);
+    true
 }
-fn pause_process(pid: u32) -> bool {
-use nix::unistd::Pid;
 
-    kill(Pid::from_raw(pid as i32), Signal::SIGSTOP).is_ok()
+fn restart_if_failed(pid: u32, initial_specs: &[ProcessLaunchSpec]) -> bool {
+    let procfs = ProcFs::new();
+    let store = LaunchStore::default();
+    let saved = store.load(pid);
+    let Some(spec) = initial_specs.iter().find(|spec| spec.pid == pid).cloned().or_else(|| saved.clone()) else {
+        println!("No launch context for PID {}; it was not running when the session started and was never captured.", pid);
+        return false;
+    };
+
+    if spec.is_running(&procfs) {
+        println!("Process {} is already running.", pid);
+        return true;
+    }
+
+    println!("Process {} has stopped. Restarting...", pid);
+    match spec.relaunch() {
+        Ok(new_pid) => {
+            println!("Restarted process with new PID: {}", new_pid);
+            if saved.is_some() {
+                // Keep the saved context under the new PID so the next crash can be handled too
+                let start_ticks = procfs.read_stat(new_pid).map_or(0, |stat| stat.starttime);
+                let relaunched = ProcessLaunchSpec { pid: new_pid, ppid: std::process::id(), start_ticks, ..spec };
+                let _ = store.remove(pid);
+                let _ = store.save(&relaunched);
+            }
+            true
+        }
+        Err(e) => {
+            eprintln!("Failed to restart process: {}", e);
+            false
+        }
+    }
 }
-fn resume_process(pid: u32) -> bool {
-    use nix::unistd::Pid;
+fn supervise(config_path: Option<PathBuf>, action: SuperviseAction, format: OutputFormat) -> bool {
+    let config_path = config_path.unwrap_or_else(supervisor::default_config_path);
+    let config = match SupervisorConfig::load(&config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Failed to load supervisor config {}", e);
+            return false;
+        }
+    };
 
-    kill(Pid::from_raw(pid as i32), Signal::SIGCONT).is_ok()
+    let (command, name) = match action {
+        SuperviseAction::Run => {
+            return match supervisor::run(config) {
+                Ok(()) => true,
+                Err(e) => {
+                    eprintln!("Supervisor failed: {}", e);
+                    false
+                }
+            };
+        }
+        SuperviseAction::Status { name } => (ControlCommand::Status, name),
+        SuperviseAction::Start { name } => (ControlCommand::Start, name),
+        SuperviseAction::Stop { name } => (ControlCommand::Stop, name),
+        SuperviseAction::Restart { name } => (ControlCommand::Restart, name),
+    };
+
+    match supervisor::send(&config.socket_path(), &ControlRequest { command, name }) {
+        Ok(programs) => {
+            print_records(format, &programs);
+            true
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            false
+        }
+    }
+}
+fn pause_process(pid: u32, source: Source) -> Result<(), ActionError> {
+    audit::audited(source, pid, "pause", &[], false, || actions::pause(pid))
 }
-fn get_os() {
+fn resume_process(pid: u32, source: Source) -> Result<(), ActionError> {
+    audit::audited(source, pid, "resume", &[], false, || actions::resume(pid))
+}
+fn get_os(format: OutputFormat) {
     let os = env::consts::OS;
-    println!("Your OS is: {}", os);
-}
-fn tui() {
-    let mut system = sysinfo::System::new_all();
-    system.refresh_all();
-
-    thread::sleep(std::time::Duration::from_millis(500));
-    system.refresh_all();
-
-    let processes: Vec<TUI::Process> = system
-        .processes()
-        .iter()
-        .map(|(pid, process)| {
-            let ppid = process.parent().map(|p| p.as_u32());
-            let user = match process.user_id() {
-                Some(uid) => {
-                    let uid_value = **uid;
-                    
-                    match get_user_by_uid(uid_value) {
-                        Some(user) => Some(user.name().to_string_lossy().into_owned()),
-                        None => Some(format!("uid:{}", uid_value))
-                    }
-                },
-                None => Some("unknown".to_string())
-            };
+    if format == OutputFormat::Table {
+        println!("Your OS is: {}", os);
+    } else {
+        print_records(format, &[OsRecord { os: os.to_string() }]);
+    }
+}
+fn tui(config_path: Option<PathBuf>, columns: Option<Vec<String>>) -> bool {
+    let config_path = config_path.unwrap_or_else(config::default_path);
+    let mut config = match Config::load_or_default(&config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid TUI config {}", e);
+            return false;
+        }
+    };
 
-            // Get the nice value for the process using ps command
-            let priority = std::process::Command::new("ps")
-                .arg("-o")
-                .arg("nice")
-                .arg("-p")
-                .arg(format!("{}", pid.as_u32()))
-                .output()
-                .ok()
-                .and_then(|output| {
-                    if output.status.success() {
-                        String::from_utf8_lossy(&output.stdout)
-                            .lines()
-                            .nth(1)
-                            .and_then(|line| line.trim().parse::<i32>().ok())
-                    } else {
-                        None
-                    }
-                })
-                .unwrap_or(0);
-            
-            TUI::Process {
-                pid: pid.as_u32(),
-                ppid,
-                user,
-                cpu: process.cpu_usage(),
-                mem: process.memory() as f32 / 1024.0, // Convert memory to MB
-                cmd: process.name().to_string_lossy().into_owned(),
-                start_time: process.start_time(),
-                process_state: process.status(),
-                priority,
-            }
-        })
-        .collect();
+    // --columns replaces the configured columns for this run, keeping their configured widths
+    if let Some(columns) = columns {
+        config.columns = columns
+            .iter()
+            .map(|name| {
+                let name = name.trim().to_uppercase();
+                config.columns.iter().find(|c| c.name == name).cloned().unwrap_or_else(|| ColumnConfig::new(&name))
+            })
+            .collect();
+        if let Err(e) = config.validate() {
+            eprintln!("Invalid --columns: {}", e);
+            return false;
+        }
+    }
 
-    // Define which columns to display in the TUI
-    let columns_to_display = vec![
-        "PID".into(),
-        "PPID".into(),
-        "USER".into(),
-        "CPU".into(),
-        "MEM".into(),
-        "NI".into(),  // Changed from "PRIORITY" to "NI" to match the column definition
-        "CMD".into(),
-        "START".into(),
-        "STATUS".into(),
-    ];
+    let processes: Vec<TUI::Process> = take_snapshot(snapshot::DEFAULT_INTERVAL)
+        .into_iter()
+        .map(TUI::Process::from)
+        .collect();
 
     // Display the TUI
-    TUI::display_tui(columns_to_display, processes);
-}
-fn change_niceness(pid: u32, niceness: i32) {
-    let output = Command::new("renice")
-        .arg(niceness.to_string())
-        .arg("-p")
-        .arg(pid.to_string())
-        .output()
-        .expect("Failed to change niceness");
-
-    if output.status.success() {
-        println!("Changed niceness for PID {} to {}", pid, niceness);
-    } else {
-        eprintln!(
-            "Failed to change niceness for PID {}: {}",
-            pid,
-            String::from_utf8_lossy(&output.stderr)
-        );
+    TUI::display_tui(config, config_path, processes);
+    true
+}
+fn change_niceness(pid: u32, niceness: i32, source: Source) -> ActionRecord {
+    let result = audit::audited(source, pid, "renice", &[niceness.to_string()], false, || actions::renice(pid, niceness));
+
+    ActionRecord {
+        pid,
+        action: "change_nice".to_string(),
+        success: result.is_ok(),
+        message: match &result {
+            Ok(()) => format!("Changed niceness for PID {} to {}", pid, niceness),
+            Err(e) => format!("Failed to change niceness for PID {}: {}", pid, e),
+        },
+    }
+}
+fn launch_gui(running: Arc<AtomicBool>) -> thread::JoinHandle<()> {
+    let current_dir = std::env::current_dir().expect("Failed to get current directory");
+    let electron_dir = current_dir.join("../electron-gui");
+
+    thread::spawn(move || {
+        let mut child = Command::new("npm")
+            .arg("start")
+            .current_dir(&electron_dir)
+            // The GUI reads its process list from this binary
+            .env("PROCMANAGER_BIN", env::current_exe().unwrap_or_default())
+            .spawn()
+            .expect("Failed to start Electron");
+
+        println!("GUI running successfully!");
+
+        while running.load(Ordering::SeqCst) {
+            // Stop watching once the window has been closed on its own
+            if let Ok(Some(_)) = child.try_wait() {
+                return;
+            }
+            thread::sleep(time::Duration::from_millis(100));
+        }
+
+        // Kill the Electron process when exit is called
+        let _ = child.kill();
+        let _ = child.wait();
+    })
+}
+
+#[derive(Parser)]
+#[command(name = "procmanager", version, about = "Elreqaba LPM: a Linux process manager")]
+struct Cli {
+    /// Command to run; starts the interactive prompt when omitted
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    /// Output format for command results
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    format: OutputFormat,
+
+    /// Recorded as the source of actions in the audit log; set by the GUIs when they run this binary
+    #[arg(long = "audit-source", global = true, hide = true, value_parser = ["cli", "gui"], default_value = "cli")]
+    audit_source: String,
+}
+
+impl Cli {
+    fn source(&self) -> Source {
+        if self.audit_source == "gui" {
+            Source::Gui
+        } else {
+            Source::Cli
+        }
+    }
+}
+
+/// Ids of the `SelectorArgs` flags, which replace the PID argument when given
+const SELECTORS: [&str; 7] = ["name", "cmdline", "user", "ppid", "tree", "older_than", "port"];
+
+/// Picks processes instead of a single PID; every selector given must match
+#[derive(Args, Clone)]
+struct SelectorArgs {
+    /// Processes whose name matches this regex
+    #[arg(long)]
+    name: Option<Regex>,
+    /// Processes whose full command line matches this regex
+    #[arg(long)]
+    cmdline: Option<Regex>,
+    /// Processes owned by this user name or uid
+    #[arg(long)]
+    user: Option<String>,
+    /// Direct children of this PID
+    #[arg(long)]
+    ppid: Option<u32>,
+    /// This PID and every process below it
+    #[arg(long)]
+    tree: Option<u32>,
+    /// Processes started at least this long ago, e.g. 30s, 15m, 2h, 7d
+    #[arg(long = "older-than", value_parser = selector::parse_age)]
+    older_than: Option<time::Duration>,
+    /// Processes with a TCP or UDP socket on this local port, e.g. whatever is listening on 8080
+    #[arg(long)]
+    port: Option<u16>,
+    /// List the processes that would be acted on, without acting
+    #[arg(long = "dry-run")]
+    dry_run: bool,
+}
+
+impl SelectorArgs {
+    fn selector(&self) -> Selector {
+        Selector {
+            name: self.name.clone(),
+            cmdline: self.cmdline.clone(),
+            user: self.user.clone(),
+            ppid: self.ppid,
+            tree: self.tree,
+            older_than: self.older_than,
+            port: self.port,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+#[command(rename_all = "snake_case")]
+enum Commands {
+    /// Display the current operating system
+    GetOs,
+    /// Display the process table (optionally export to CSV)
+    Ptable {
+        /// Column used to order the table
+        #[arg(long, value_enum, default_value_t = SortKey::Cpu)]
+        sort: SortKey,
+        /// Only show the first N processes
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Only show processes matching this filter, e.g. 'cpu > 20 and user == "ci"'
+        #[arg(long = "where", value_name = "EXPR")]
+        filter: Option<Filter>,
+        /// Fill in PSS and USS, which makes the kernel walk every mapping of every process; implied by
+        /// sorting or filtering on them
+        #[arg(long)]
+        pss: bool,
+        /// Export the table to this .csv file instead of printing it
+        file: Option<String>,
+    },
+    /// Change process priority
+    ChangeNice {
+        /// `<PID> <NICENESS>`, or only `<NICENESS>` when processes are picked with selectors
+        #[arg(value_names = ["PID", "NICENESS"], num_args = 1..=2, required = true, allow_negative_numbers = true)]
+        values: Vec<i32>,
+        #[command(flatten)]
+        select: SelectorArgs,
+    },
+    /// Terminate a process
+    Kill {
+        #[arg(required_unless_present_any = SELECTORS, conflicts_with_all = SELECTORS)]
+        pid: Option<u32>,
+        #[command(flatten)]
+        select: SelectorArgs,
+    },
+    /// Send a signal; without one, SIGTERM then SIGKILL if the process is still running after --timeout
+    Signal {
+        pid: u32,
+        /// Name (TERM, SIGHUP, usr1) or number
+        #[arg(value_parser = parse_signal_arg)]
+        signal: Option<Signal>,
+        /// Seconds to wait for the process to exit after SIGTERM before sending SIGKILL
+        #[arg(long = "timeout", default_value_t = actions::DEFAULT_TERM_TIMEOUT.as_secs())]
+        timeout_secs: u64,
+    },
+    /// Stream process lifecycle events: fork, exec, exit, uid and state changes
+    #[command(group(clap::ArgGroup::new("target").required(true).args(["pid", "all"])))]
+    Log {
+        pid: Option<u32>,
+        /// Also follow every process forked by PID, recursively
+        #[arg(long, requires = "pid")]
+        descendants: bool,
+        /// Follow every process on the system
+        #[arg(long)]
+        all: bool,
+        /// Append events to this file instead of printing them
+        #[arg(long)]
+        output: Option<String>,
+        /// Poll /proc instead of using the netlink process connector
+        #[arg(long)]
+        poll: bool,
+        /// Milliseconds between /proc scans for state changes (and everything else with --poll)
+        #[arg(long = "interval-ms", default_value_t = 500, value_parser = clap::value_parser!(u64).range(50..))]
+        interval_ms: u64,
+    },
+    /// Pause a process
+    Pause {
+        #[arg(required_unless_present_any = SELECTORS, conflicts_with_all = SELECTORS)]
+        pid: Option<u32>,
+        #[command(flatten)]
+        select: SelectorArgs,
+    },
+    /// Resume a paused process
+    Resume {
+        #[arg(required_unless_present_any = SELECTORS, conflicts_with_all = SELECTORS)]
+        pid: Option<u32>,
+        #[command(flatten)]
+        select: SelectorArgs,
+    },
+    /// Track process metrics into a file (CSV unless --format is given)
+    TrackProcess {
+        pid: u32,
+        output: String,
+        #[arg(value_parser = clap::value_parser!(u64).range(1..))]
+        duration_secs: u64,
+    },
+    /// Get command details for a process
+    GetProcessCommand { pid: u32 },
+    /// List the open files of a process: paths, pipes, sockets with their addresses and state, anonymous inodes
+    Files { pid: u32 },
+    /// List the threads of a process, busiest first: CPU usage, state, last CPU, nice and context switches
+    Threads { pid: u32 },
+    /// List the memory mappings of a process from /proc/[pid]/smaps, with RSS, PSS and swap per mapping
+    Maps {
+        pid: u32,
+        /// Order of the list; sizes sort largest first
+        #[arg(long, value_enum, default_value_t = MapSortKey::Address)]
+        sort: MapSortKey,
+        /// Add up the mappings of each file, e.g. every segment of a shared library, and of [heap], [stack] and [anon]
+        #[arg(long)]
+        totals: bool,
+    },
+    /// Show which processes hold a TCP or UDP socket on a local port, listening or connected
+    Port { port: u16 },
+    /// Relaunch a process that was running when the session started, or was captured, if it stopped
+    RestartIfFailed { pid: u32 },
+    /// Save what is needed to relaunch a process (argv, cwd, environment, user) to disk
+    CaptureLaunch { pid: u32 },
+    /// Show the audit log of actions taken on processes, newest last
+    History {
+        /// Only show the last N entries
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+        /// Only show actions on this PID
+        #[arg(long)]
+        pid: Option<u32>,
+        /// Only show this action (kill, pause, resume, renice, signal)
+        #[arg(long)]
+        action: Option<String>,
+    },
+    /// Launch the Terminal User Interface
+    Tui {
+        /// TUI settings file [default: ~/.config/procmanager/config.toml]
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Columns to show instead of the configured ones, in order: PID, PPID, USER, CPU, MEM, NI, CMD, CMDLINE, EXE, CWD, THREADS, START, STATUS
+        #[arg(long, value_delimiter = ',', value_name = "COLUMNS")]
+        columns: Option<Vec<String>>,
+    },
+    /// Launch the Electron-based GUI
+    Gui,
+    /// Read commands from an interactive prompt
+    Interactive,
+    /// Keep the programs of a supervisor file running
+    Supervise {
+        /// Supervisor file [default: ~/.config/procmanager/supervisor.toml]
+        #[arg(long)]
+        config: Option<PathBuf>,
+        #[command(subcommand)]
+        action: SuperviseAction,
+    },
+    /// Serve the process table and actions over an HTTP/JSON API
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:9100")]
+        listen: std::net::SocketAddr,
+        /// Require `Authorization: Bearer <TOKEN>` on every request
+        #[arg(long, env = "PROCMANAGER_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+        /// Only allow GET endpoints
+        #[arg(long = "read-only")]
+        read_only: bool,
+        /// Milliseconds between process table refreshes
+        #[arg(long = "interval-ms", default_value_t = 1000, value_parser = clap::value_parser!(u64).range(100..))]
+        interval_ms: u64,
+        /// Only export /metrics series for processes whose name matches this regex (repeatable)
+        #[arg(long = "metrics-include")]
+        metrics_include: Vec<regex::Regex>,
+        /// Leave out processes whose name matches this regex (repeatable)
+        #[arg(long = "metrics-exclude")]
+        metrics_exclude: Vec<regex::Regex>,
+        /// Only export the N processes using the most CPU
+        #[arg(long = "metrics-top")]
+        metrics_top: Option<usize>,
+    },
+}
+
+#[derive(Subcommand)]
+enum SuperviseAction {
+    /// Run the supervisor in the foreground until interrupted
+    Run,
+    /// Show the state of every program, or of one
+    Status { name: Option<String> },
+    /// Start a stopped program, or all of them
+    Start { name: Option<String> },
+    /// Stop a program, or all of them, without restarting it
+    Stop { name: Option<String> },
+    /// Stop and start a program, or all of them
+    Restart { name: Option<String> },
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum MapSortKey {
+    Address,
+    Size,
+    Rss,
+    Pss,
+    Swap,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum SortKey {
+    Cpu,
+    /// Resident memory (RSS)
+    Mem,
+    Pss,
+    Uss,
+    Swap,
+    Pid,
+    Name,
+}
+
+// Runs a single command and reports whether it succeeded
+fn run_command(command: Commands, format: OutputFormat, source: Source, initial_specs: &[ProcessLaunchSpec], running: &Arc<AtomicBool>) -> bool {
+    match command {
+        Commands::GetOs => {
+            get_os(format);
+            true
+        }
+        Commands::Ptable { sort, limit, filter, pss, file } => ptable(file.as_deref(), sort, limit, filter.as_ref(), pss, format),
+        Commands::ChangeNice { values, select } => {
+            let (pid, niceness) = match (values.as_slice(), select.selector().is_empty()) {
+                (&[pid, niceness], true) => (Some(pid), niceness),
+                (&[niceness], false) => (None, niceness),
+                (&[_, _], false) => {
+                    eprintln!("Give either a PID or selectors, not both.");
+                    return false;
+                }
+                _ => {
+                    eprintln!("Missing PID: use change_nice <PID> <NICENESS> or pick processes with selectors.");
+                    return false;
+                }
+            };
+            match pid {
+                Some(pid) if pid <= 0 => {
+                    eprintln!("Invalid PID.");
+                    false
+                }
+                pid => for_each_target(pid.map(|pid| pid as u32), &select, format, |pid| {
+                    change_niceness(pid, niceness, source)
+                }),
+            }
+        }
+        Commands::Kill { pid, select } => {
+            for_each_target(pid, &select, format, |pid| kill_by_pid(pid, source))
+        }
+        Commands::Signal { pid, signal, timeout_secs } => {
+            signal_process(pid, signal, time::Duration::from_secs(timeout_secs), format, source)
+        }
+        Commands::Log { pid, descendants, all: _, output, poll, interval_ms } => {
+            let scope = match pid {
+                Some(pid) if descendants => Scope::Descendants(pid),
+                Some(pid) => Scope::Pid(pid),
+                None => Scope::All,
+            };
+            log_lifecycle(scope, output.as_deref(), poll, time::Duration::from_millis(interval_ms), format)
+        }
+        Commands::Pause { pid, select } => for_each_target(pid, &select, format, |pid| {
+            let result = pause_process(pid, source);
+            ActionRecord {
+                pid,
+                action: "pause".to_string(),
+                success: result.is_ok(),
+                message: match &result {
+                    Ok(()) => format!("Paused process with PID {}", pid),
+                    Err(e) => format!("Failed to pause process with PID {}: {}", pid, e),
+                },
+            }
+        }),
+        Commands::Resume { pid, select } => for_each_target(pid, &select, format, |pid| {
+            let result = resume_process(pid, source);
+            ActionRecord {
+                pid,
+                action: "resume".to_string(),
+                success: result.is_ok(),
+                message: match &result {
+                    Ok(()) => format!("Resumed process with PID {}", pid),
+                    Err(e) => format!("Failed to resume process with PID {}: {}", pid, e),
+                },
+            }
+        }),
+        Commands::TrackProcess { pid, output, duration_secs } => {
+            track_process(pid, output, duration_secs, format)
+        }
+        Commands::GetProcessCommand { pid } => {
+            let command = get_process_command(pid);
+            if command.is_empty() {
+                eprintln!("Failed to retrieve command for PID {}", pid);
+                false
+            } else if format == OutputFormat::Table {
+                println!("Command for PID {}: {}", pid, command);
+                true
+            } else {
+                print_records(format, &[CommandRecord { pid, command }]);
+                true
+            }
+        }
+        Commands::Threads { pid } => threads(pid, format),
+        Commands::Files { pid } => files(pid, format),
+        Commands::Port { port: number } => port(number, format),
+        Commands::Maps { pid, sort, totals } => memory_maps(pid, sort, totals, format),
+        Commands::RestartIfFailed { pid } => restart_if_failed(pid, initial_specs),
+        Commands::CaptureLaunch { pid } => capture_launch(pid, format),
+        Commands::History { limit, pid, action } => history(limit, pid, action, format),
+        Commands::Tui { config, columns } => tui(config, columns),
+        Commands::Gui => {
+            // Outside the prompt there is nothing else to do, so wait for the window
+            let _ = launch_gui(running.clone()).join();
+            true
+        }
+        Commands::Interactive => {
+            interactive();
+            true
+        }
+        Commands::Supervise { config, action } => supervise(config, action, format),
+        Commands::Serve { listen, token, read_only, interval_ms, metrics_include, metrics_exclude, metrics_top } => {
+            serve::serve(serve::ServeOptions {
+                listen,
+                token,
+                read_only,
+                interval: time::Duration::from_millis(interval_ms),
+                metrics: MetricsFilter { include: metrics_include, exclude: metrics_exclude, top: metrics_top },
+            })
+        }
     }
 }
-fn main() {
-    let initial_pids = get_pid_and_command();
+
+fn interactive() {
     let running = Arc::new(AtomicBool::new(true));
+    // What `restart_if_failed` relaunches for processes that stop during the session
+    let initial_specs = ProcessLaunchSpec::capture_all(&ProcFs::new());
 
     loop {
         let mut command = String::new();
 
         print!("Enter command (or type 'exit' to quit): ");
         let _ = io::stdout().flush(); // Make sure prompt appears before input
-        let _ = io::stdin().read_line(&mut command);
+        match io::stdin().read_line(&mut command) {
+            Ok(0) | Err(_) => break, // EOF
+            Ok(_) => {}
+        }
 
         let command = command.trim();
 
         if command.eq_ignore_ascii_case("exit") {
-            running.store(false, Ordering::SeqCst);
             println!("Exiting...");
             break;
         }
 
-        let parts: Vec<&str> = command.split_whitespace().collect();
-
-        match parts.get(0) {
-            Some(&"get_os") => get_os(),
-
-            Some(&"ptable") => {
-                let file_path = parts.get(1).copied();
-                ptable(file_path);
-            }
-            Some(&"change_nice") => {
-                if parts.len() < 3 {
-                    eprintln!("Usage: change_nice <pid> <niceness>");
-                } else {
-                    let pid = parts[1].parse::<u32>().unwrap_or(0);
-                    let niceness = parts[2].parse::<i32>().unwrap_or(0);
-                    if pid == 0 {
-                        eprintln!("Invalid PID.");
-                    } else {
-                        change_niceness(pid, niceness);
-                    }
-                }
-            }
-            Some(&"kill") => {
-                if let Some(&pid) = parts.get(1) {
-                    kill_by_pid(pid.to_string());
-                } else {
-                    eprintln!("Usage: kill <pid>");
-                }
-            }
-            Some(&"log") => {
-                if let Some(&pid) = parts.get(1) {
-                    log_by_pid(pid.to_string());
-                } else {
-                    eprintln!("Usage: log <pid>");
-                }
-            }
-            Some(&"pause") => {
-                if let Some(&pid_str) = parts.get(1) {
-                    if let Ok(pid) = pid_str.parse::<u32>() {
-                        if pause_process(pid) {
-                            println!("Paused process with PID {}", pid);
-                        } else {
-                            eprintln!("Failed to pause process with PID {}", pid);
-                        }
-                    } else {
-                        eprintln!("Invalid PID.");
-                    }
-                } else {
-                    eprintln!("Usage: pause <pid>");
-                }
+        // Split like a shell would, so quoted arguments such as `--cmdline "python app.py"` stay whole
+        let parts = match shell_words::split(command) {
+            Ok(parts) if parts.is_empty() => continue,
+            Ok(parts) => parts,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                continue;
             }
+        };
 
-            Some(&"resume") => {
-                if let Some(&pid_str) = parts.get(1) {
-                    if let Ok(pid) = pid_str.parse::<u32>() {
-                        if resume_process(pid) {
-                            println!("Resumed process with PID {}", pid);
-                        } else {
-                            eprintln!("Failed to resume process with PID {}", pid);
-                        }
-                    } else {
-                        eprintln!("Invalid PID.");
-                    }
-                } else {
-                    eprintln!("Usage: resume <pid>");
-                }
-            }
-            Some(&"track_process") => {
-                if parts.len() < 4 {
-                    eprintln!("Usage: track_process <pid> <output.csv> <duration_secs>");
-                } else {
-                    let pid = parts[1].to_string();
-                    let path = parts[2].to_string();
-                    let duration = parts[3].parse::<u64>().unwrap_or(0);
-                    if duration == 0 {
-                        eprintln!("Invalid duration. Please enter a positive integer.");
-                    } else {
-                        track_process(pid, path, duration);
-                    }
-                }
+        // Commands typed at the prompt use the same grammar as the command line
+        let cli = match Cli::try_parse_from(std::iter::once("procmanager".to_string()).chain(parts)) {
+            Ok(cli) => cli,
+            Err(e) => {
+                let _ = e.print();
+                continue;
             }
+        };
 
-            Some(&"get_process_command") => {
-                if let Some(&pid_str) = parts.get(1) {
-                    let pid = pid_str.parse::<u32>().unwrap_or(0);
-                    if pid == 0 {
-                        eprintln!("Invalid PID. Please enter a valid process ID.");
-                    } else {
-                        let command = get_process_command(pid);
-                        if !command.is_empty() {
-                            println!("Command for PID {}: {}", pid, command);
-                        } else {
-                            println!("Failed to retrieve command for PID {}", pid);
-                        }
-                    }
-                } else {
-                    eprintln!("Usage: get_process_command <pid>");
-                }
+        let source = cli.source();
+        match cli.command {
+            Some(Commands::Interactive) | None => eprintln!("Already in interactive mode."),
+            Some(Commands::Gui) => {
+                launch_gui(running.clone());
             }
-
-            Some(&"restart_if_failed") => {
-                if let Some(&pid_str) = parts.get(1) {
-                    let pid = pid_str.parse::<u32>().unwrap_or(0);
-                    if pid == 0 {
-                        eprintln!("Invalid PID. Please enter a valid process ID.");
-                    } else {
-                        let current_pids = get_pid_and_command();
-                        restart_if_failed(pid, &initial_pids, &current_pids);
-                    }
-                } else {
-                    eprintln!("Usage: restart_if_failed <pid>");
-                }
+            Some(command) => {
+                run_command(command, cli.format, source, &initial_specs, &running);
             }
+        }
+    }
 
-            Some(&"tui") => tui(),
-
-            Some(&"gui") | Some(&"GUI") => {
-                let running_clone = running.clone();
-                let current_dir = std::env::current_dir().expect("Failed to get current directory");
-                let electron_dir = current_dir.join("../electron-gui");
-                
-                thread::spawn(move || {
-                    let mut child = Command::new("npm")
-                        .arg("start")
-                        .current_dir(&electron_dir)
-                        .spawn()
-                        .expect("Failed to start Electron");
-
-                    println!("GUI running successfully!");
-                    
-                    while running_clone.load(Ordering::SeqCst) {
-                        thread::sleep(time::Duration::from_millis(100));
-                    }
-                    
-                    // Kill the Electron process when exit is called
-                    let _ = child.kill();
-                });
-            }
+    running.store(false, Ordering::SeqCst);
+}
 
-            Some(cmd) => eprintln!("Unknown command: {}", cmd),
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let running = Arc::new(AtomicBool::new(true));
+    let source = cli.source();
 
-            None => continue,
+    let ok = match cli.command {
+        Some(command) => run_command(command, cli.format, source, &[], &running),
+        None => {
+            interactive();
+            true
         }
+    };
+
+    if ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
     }
 }
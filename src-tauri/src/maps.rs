@@ -0,0 +1,233 @@
+// Memory mappings of a process, like `pmap -X`, read from /proc/[pid]/smaps
+use std::collections::HashMap;
+use std::io;
+
+use serde::Serialize;
+
+use crate::procfs::{self, MemoryCounters, ProcFs};
+
+/// One region of the address space
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Mapping {
+    pub start: u64,
+    pub end: u64,
+    /// `r`, `w`, `x` and `p` (private) or `s` (shared), e.g. `r-xp`
+    pub perms: String,
+    /// Offset into the mapped file
+    pub offset: u64,
+    /// The mapped file, a pseudo path such as `[heap]` or `[stack]`, or empty for anonymous memory
+    pub path: String,
+    pub size_kb: u64,
+    pub rss_kb: u64,
+    pub pss_kb: u64,
+    pub swap_kb: u64,
+    /// Resident pages only this process maps
+    pub private_kb: u64,
+}
+
+impl Mapping {
+    /// The address range as `/proc/[pid]/maps` prints it
+    pub fn address(&self) -> String {
+        format!("{:x}-{:x}", self.start, self.end)
+    }
+
+    /// What the mapping is grouped under in `totals`
+    pub fn name(&self) -> &str {
+        if self.path.is_empty() {
+            "[anon]"
+        } else {
+            &self.path
+        }
+    }
+}
+
+/// The mappings of one file or pseudo path added up, e.g. every segment of a shared library
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MappingTotal {
+    pub name: String,
+    /// Number of regions
+    pub mappings: usize,
+    /// Lowest start address, to list the totals in address order
+    pub start: u64,
+    pub size_kb: u64,
+    pub rss_kb: u64,
+    pub pss_kb: u64,
+    pub swap_kb: u64,
+    pub private_kb: u64,
+}
+
+/// Parses `7f1c2a000000-7f1c2a021000 rw-p 00000000 00:00 0    [heap]`; the counters are filled in by `with_counters`
+fn parse_header(line: &str) -> Option<Mapping> {
+    let mut fields = line.split_whitespace();
+    let (start, end) = fields.next()?.split_once('-')?;
+    let start = u64::from_str_radix(start, 16).ok()?;
+    let end = u64::from_str_radix(end, 16).ok()?;
+    let perms = fields.next()?.to_string();
+    let offset = u64::from_str_radix(fields.next()?, 16).ok()?;
+    // Device and inode
+    fields.next()?;
+    fields.next()?;
+    Some(Mapping {
+        start,
+        end,
+        perms,
+        offset,
+        // Paths may contain spaces
+        path: fields.collect::<Vec<&str>>().join(" "),
+        size_kb: end.saturating_sub(start) / 1024,
+        rss_kb: 0,
+        pss_kb: 0,
+        swap_kb: 0,
+        private_kb: 0,
+    })
+}
+
+fn with_counters(mapping: Mapping, counters: &MemoryCounters) -> Mapping {
+    Mapping {
+        rss_kb: counters.rss_kb,
+        pss_kb: counters.pss_kb,
+        swap_kb: counters.swap_kb,
+        private_kb: counters.uss_kb(),
+        ..mapping
+    }
+}
+
+/// Parses /proc/[pid]/smaps: a header line per mapping followed by its `Key: value kB` counters
+pub fn parse_smaps(contents: &str) -> Vec<Mapping> {
+    let mut mappings = Vec::new();
+    let mut current: Option<(Mapping, MemoryCounters)> = None;
+    for line in contents.lines() {
+        if let Some((_, counters)) = current.as_mut() {
+            if procfs::add_memory_counter(counters, line) {
+                continue;
+            }
+        }
+        // Anything else is either the next header or a line we do not use, such as VmFlags
+        if let Some(header) = parse_header(line) {
+            if let Some((mapping, counters)) = current.replace((header, MemoryCounters::default())) {
+                mappings.push(with_counters(mapping, &counters));
+            }
+        }
+    }
+    if let Some((mapping, counters)) = current {
+        mappings.push(with_counters(mapping, &counters));
+    }
+    mappings
+}
+
+/// The mappings of `pid` in address order. Empty for kernel threads; reading another
+/// user's process needs ptrace privileges, so the error tells that apart from a missing process.
+pub fn mappings(procfs: &ProcFs, pid: u32) -> io::Result<Vec<Mapping>> {
+    Ok(parse_smaps(&procfs.read_smaps(pid)?))
+}
+
+/// Totals per mapped file or pseudo path, in address order
+pub fn totals(mappings: &[Mapping]) -> Vec<MappingTotal> {
+    let mut totals: HashMap<&str, MappingTotal> = HashMap::new();
+    for mapping in mappings {
+        let total = totals.entry(mapping.name()).or_insert_with(|| MappingTotal {
+            name: mapping.name().to_string(),
+            mappings: 0,
+            start: mapping.start,
+            size_kb: 0,
+            rss_kb: 0,
+            pss_kb: 0,
+            swap_kb: 0,
+            private_kb: 0,
+        });
+        total.mappings += 1;
+        total.start = total.start.min(mapping.start);
+        total.size_kb += mapping.size_kb;
+        total.rss_kb += mapping.rss_kb;
+        total.pss_kb += mapping.pss_kb;
+        total.swap_kb += mapping.swap_kb;
+        total.private_kb += mapping.private_kb;
+    }
+    let mut totals: Vec<MappingTotal> = totals.into_values().collect();
+    totals.sort_by_key(|total| total.start);
+    totals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMAPS: &str = "\
+560000000000-560000004000 rw-p 00000000 00:00 0 
+Size:                 16 kB
+KernelPageSize:        4 kB
+Rss:                  12 kB
+Pss:                  12 kB
+Shared_Clean:          0 kB
+Shared_Dirty:          0 kB
+Private_Clean:         0 kB
+Private_Dirty:        12 kB
+Swap:                  4 kB
+VmFlags: rd wr mr mw me ac
+7f0000000000-7f0000028000 r--p 00000000 08:01 1835 /usr/lib/x86_64-linux-gnu/libc.so.6
+Size:                160 kB
+Rss:                 120 kB
+Pss:                  30 kB
+Shared_Clean:        120 kB
+Shared_Dirty:          0 kB
+Private_Clean:         0 kB
+Private_Dirty:         0 kB
+Swap:                  0 kB
+VmFlags: rd mr mw me
+7f0000028000-7f00001bd000 r-xp 00028000 08:01 1835 /usr/lib/x86_64-linux-gnu/libc.so.6
+Size:               1620 kB
+Rss:                1000 kB
+Pss:                 250 kB
+Shared_Clean:        980 kB
+Shared_Dirty:          0 kB
+Private_Clean:        20 kB
+Private_Dirty:         0 kB
+Swap:                  0 kB
+VmFlags: rd ex mr mw me
+";
+
+    const LIBC: &str = "/usr/lib/x86_64-linux-gnu/libc.so.6";
+
+    #[test]
+    fn parses_each_mapping_with_its_counters() {
+        let mappings = parse_smaps(SMAPS);
+        assert_eq!(mappings.len(), 3);
+        assert_eq!(mappings[0].name(), "[anon]");
+        assert_eq!((mappings[0].size_kb, mappings[0].private_kb, mappings[0].swap_kb), (16, 12, 4));
+        assert_eq!(mappings[2].address(), "7f0000028000-7f00001bd000");
+        assert_eq!(mappings[2].perms, "r-xp");
+        assert_eq!(mappings[2].offset, 0x28000);
+        assert_eq!(mappings[2].path, LIBC);
+        assert_eq!((mappings[2].rss_kb, mappings[2].pss_kb, mappings[2].private_kb), (1000, 250, 20));
+    }
+
+    #[test]
+    fn totals_group_mappings_of_the_same_file() {
+        let totals = totals(&parse_smaps(SMAPS));
+        assert_eq!(
+            totals,
+            [
+                MappingTotal {
+                    name: "[anon]".to_string(),
+                    mappings: 1,
+                    start: 0x5600_0000_0000,
+                    size_kb: 16,
+                    rss_kb: 12,
+                    pss_kb: 12,
+                    swap_kb: 4,
+                    private_kb: 12,
+                },
+                MappingTotal {
+                    name: LIBC.to_string(),
+                    mappings: 2,
+                    start: 0x7f00_0000_0000,
+                    size_kb: 1780,
+                    rss_kb: 1120,
+                    pss_kb: 280,
+                    swap_kb: 0,
+                    private_kb: 20,
+                },
+            ]
+        );
+    }
+}
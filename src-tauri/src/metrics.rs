@@ -0,0 +1,128 @@
+// Prometheus text exposition of the process table and system metrics
+use std::cmp::Ordering;
+use std::fmt::Write;
+
+use regex::Regex;
+
+use crate::snapshot::ProcessSnapshot;
+use crate::system::SystemInfo;
+
+const MB: f64 = 1024.0 * 1024.0;
+
+/// Limits which processes get per-process series, to keep cardinality under control
+#[derive(Clone, Debug, Default)]
+pub struct MetricsFilter {
+    /// Only processes whose name matches one of these; every process when empty
+    pub include: Vec<Regex>,
+    /// Processes whose name matches one of these are left out
+    pub exclude: Vec<Regex>,
+    /// Keep only the N processes using the most CPU (then memory)
+    pub top: Option<usize>,
+}
+
+impl MetricsFilter {
+    pub fn select<'a>(&self, processes: &'a [ProcessSnapshot]) -> Vec<&'a ProcessSnapshot> {
+        let mut selected: Vec<&ProcessSnapshot> = processes
+            .iter()
+            .filter(|p| self.include.is_empty() || self.include.iter().any(|re| re.is_match(&p.cmd)))
+            .filter(|p| !self.exclude.iter().any(|re| re.is_match(&p.cmd)))
+            .collect();
+
+        if let Some(top) = self.top {
+            selected.sort_by(|a, b| {
+                b.cpu
+                    .partial_cmp(&a.cpu)
+                    .unwrap_or(Ordering::Equal)
+                    .then(b.mem.partial_cmp(&a.mem).unwrap_or(Ordering::Equal))
+            });
+            selected.truncate(top);
+        }
+        selected.sort_by_key(|p| p.pid);
+        selected
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+struct Family<'a> {
+    out: &'a mut String,
+}
+
+impl Family<'_> {
+    fn header(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+        let _ = writeln!(self.out, "# TYPE {} {}", name, kind);
+    }
+
+    fn sample(&mut self, name: &str, labels: &str, value: f64) {
+        if labels.is_empty() {
+            let _ = writeln!(self.out, "{} {}", name, value);
+        } else {
+            let _ = writeln!(self.out, "{}{{{}}} {}", name, labels, value);
+        }
+    }
+}
+
+/// Renders per-process series for the processes `filter` selects, followed by the system series
+pub fn render(processes: &[ProcessSnapshot], system: &SystemInfo, filter: &MetricsFilter) -> String {
+    let selected = filter.select(processes);
+    let labels: Vec<String> = selected
+        .iter()
+        .map(|p| {
+            format!(
+                "pid=\"{}\",name=\"{}\",user=\"{}\"",
+                p.pid,
+                escape_label(&p.cmd),
+                escape_label(p.user.as_deref().unwrap_or(""))
+            )
+        })
+        .collect();
+
+    let mut out = String::new();
+    let mut family = Family { out: &mut out };
+
+    type Value = fn(&ProcessSnapshot) -> Option<f64>;
+    let process_series: [(&str, &str, &str, Value); 6] = [
+        ("procmanager_process_cpu_percent", "gauge", "CPU usage in percent of one core", |p| Some(p.cpu as f64)),
+        ("procmanager_process_resident_memory_bytes", "gauge", "Resident set size", |p| Some((p.mem as f64 * MB).round())),
+        ("procmanager_process_nice", "gauge", "Nice value", |p| Some(p.nice as f64)),
+        ("procmanager_process_threads", "gauge", "Number of threads", |p| Some(p.threads as f64)),
+        ("procmanager_process_read_bytes_total", "counter", "Bytes read from storage", |p| p.read_bytes.map(|b| b as f64)),
+        ("procmanager_process_write_bytes_total", "counter", "Bytes written to storage", |p| p.write_bytes.map(|b| b as f64)),
+    ];
+    for (name, kind, help, value) in process_series {
+        family.header(name, kind, help);
+        for (process, labels) in selected.iter().zip(&labels) {
+            if let Some(value) = value(process) {
+                family.sample(name, labels, value);
+            }
+        }
+    }
+
+    family.header("procmanager_process_state", "gauge", "1 for the current state of the process");
+    for (process, labels) in selected.iter().zip(&labels) {
+        let labels = format!("{},state=\"{}\"", labels, escape_label(&process.state.to_string()));
+        family.sample("procmanager_process_state", &labels, 1.0);
+    }
+
+    let system_series = [
+        ("procmanager_system_cpu_usage_percent", "Average CPU usage over all cores", system.cpu_usage as f64),
+        ("procmanager_system_memory_total_bytes", "Total memory", (system.total_memory as f64 * MB).round()),
+        ("procmanager_system_memory_used_bytes", "Used memory", (system.used_memory as f64 * MB).round()),
+        ("procmanager_system_memory_available_bytes", "Available memory", (system.available_memory as f64 * MB).round()),
+        ("procmanager_system_swap_total_bytes", "Total swap", (system.swap as f64 * MB).round()),
+        ("procmanager_system_swap_used_bytes", "Used swap", (system.used_swap as f64 * MB).round()),
+        ("procmanager_system_uptime_seconds", "Seconds since boot", system.uptime as f64),
+        ("procmanager_system_processes", "Number of processes", system.process_count as f64),
+        ("procmanager_system_physical_cores", "Physical CPU cores", system.physical_cores as f64),
+        ("procmanager_system_logical_cores", "Logical CPU cores", system.logical_cores as f64),
+    ];
+    for (name, help, value) in system_series {
+        family.header(name, "gauge", help);
+        family.sample(name, "", value);
+    }
+
+    out
+}
@@ -0,0 +1,341 @@
+// Socket tables of /proc/[pid]/net/{tcp,tcp6,udp,udp6,unix}, keyed by inode so they can be joined
+// with the `socket:[inode]` links of /proc/[pid]/fd
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::files::{self, FileKind};
+use crate::procfs::ProcFs;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    Tcp,
+    Tcp6,
+    Udp,
+    Udp6,
+    Unix,
+}
+
+impl Protocol {
+    pub const ALL: [Protocol; 5] = [Protocol::Tcp, Protocol::Tcp6, Protocol::Udp, Protocol::Udp6, Protocol::Unix];
+
+    /// The file under /proc/[pid]/net
+    pub fn file(self) -> &'static str {
+        match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Tcp6 => "tcp6",
+            Protocol::Udp => "udp",
+            Protocol::Udp6 => "udp6",
+            Protocol::Unix => "unix",
+        }
+    }
+
+    fn is_udp(self) -> bool {
+        matches!(self, Protocol::Udp | Protocol::Udp6)
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.file())
+    }
+}
+
+/// One line of a socket table
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Socket {
+    pub protocol: Protocol,
+    pub inode: u64,
+    /// `address:port`, or the path of a unix socket (empty when unnamed)
+    pub local: String,
+    /// Empty for unix sockets and unconnected sockets
+    pub remote: String,
+    /// `None` for unix sockets
+    pub local_port: Option<u16>,
+    pub remote_port: Option<u16>,
+    /// TCP state as `ss` names it (LISTEN, ESTAB, TIME-WAIT...), UNCONN for idle UDP and unix sockets
+    pub state: String,
+}
+
+impl Socket {
+    /// A TCP or unix socket accepting connections, or a bound UDP socket
+    pub fn is_listening(&self) -> bool {
+        self.state == "LISTEN" || (self.protocol.is_udp() && self.state == "UNCONN" && self.local_port != Some(0))
+    }
+}
+
+fn tcp_state(code: u8) -> &'static str {
+    match code {
+        0x01 => "ESTAB",
+        0x02 => "SYN-SENT",
+        0x03 => "SYN-RECV",
+        0x04 => "FIN-WAIT-1",
+        0x05 => "FIN-WAIT-2",
+        0x06 => "TIME-WAIT",
+        0x07 => "UNCONN",
+        0x08 => "CLOSE-WAIT",
+        0x09 => "LAST-ACK",
+        0x0A => "LISTEN",
+        0x0B => "CLOSING",
+        _ => "UNKNOWN",
+    }
+}
+
+/// Decodes `0100007F:1F90`; the address words are printed in host byte order, the port is not
+fn parse_address(field: &str) -> Option<(String, u16)> {
+    let (address, port) = field.split_once(':')?;
+    let port = u16::from_str_radix(port, 16).ok()?;
+    let words = (0..address.len() / 8)
+        .map(|i| u32::from_str_radix(address.get(i * 8..i * 8 + 8)?, 16).ok())
+        .collect::<Option<Vec<u32>>>()?;
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_ne_bytes()).collect();
+    let address = match bytes.len() {
+        4 => Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]).to_string(),
+        16 => {
+            let bytes: [u8; 16] = bytes.try_into().ok()?;
+            let address = Ipv6Addr::from(bytes);
+            // IPv4 clients of a dual stack socket show up as ::ffff:a.b.c.d
+            match address.to_ipv4_mapped() {
+                Some(v4) => format!("[::ffff:{}]", v4),
+                None => format!("[{}]", address),
+            }
+        }
+        _ => return None,
+    };
+    Some((address, port))
+}
+
+/// Parses /proc/net/{tcp,tcp6,udp,udp6}
+pub fn parse_inet(protocol: Protocol, contents: &str) -> Vec<Socket> {
+    contents
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (local, local_port) = parse_address(fields.get(1)?)?;
+            let (remote, remote_port) = parse_address(fields.get(2)?)?;
+            let state = u8::from_str_radix(fields.get(3)?, 16).ok()?;
+            let inode = fields.get(9)?.parse().ok()?;
+            // An unconnected socket has the all-zero address as its peer
+            let connected = remote_port != 0;
+            Some(Socket {
+                protocol,
+                inode,
+                local: format!("{}:{}", local, local_port),
+                remote: if connected { format!("{}:{}", remote, remote_port) } else { String::new() },
+                local_port: Some(local_port),
+                remote_port: connected.then_some(remote_port),
+                state: tcp_state(state).to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Parses /proc/net/unix
+pub fn parse_unix(contents: &str) -> Vec<Socket> {
+    // __SO_ACCEPTCON in the flags column marks a listening socket
+    const ACCEPTING: u32 = 0x0001_0000;
+    contents
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let flags = u32::from_str_radix(fields.get(3)?, 16).ok()?;
+            let state = u8::from_str_radix(fields.get(5)?, 16).ok()?;
+            let inode = fields.get(6)?.parse().ok()?;
+            let state = if flags & ACCEPTING != 0 {
+                "LISTEN"
+            } else if state == 0x03 {
+                "ESTAB"
+            } else {
+                "UNCONN"
+            };
+            Some(Socket {
+                protocol: Protocol::Unix,
+                inode,
+                local: fields.get(7..).map(|path| path.join(" ")).unwrap_or_default(),
+                remote: String::new(),
+                local_port: None,
+                remote_port: None,
+                state: state.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// A TCP or UDP socket and the process holding it open
+#[derive(Clone, Debug, Serialize)]
+pub struct PortOwner {
+    pub pid: u32,
+    /// Process name
+    pub name: String,
+    pub fd: u32,
+    pub socket: Socket,
+}
+
+/// The TCP and UDP sockets of every process whose descriptors can be read. The socket tables are
+/// read once per network namespace rather than once per process.
+fn inet_socket_owners(procfs: &ProcFs) -> Vec<PortOwner> {
+    let mut tables: HashMap<Option<PathBuf>, HashMap<u64, Socket>> = HashMap::new();
+    let mut owners = Vec::new();
+    for pid in procfs.pids() {
+        // Other users' processes are skipped unless running as root, like `lsof` and `ss -p`
+        let Ok(fds) = procfs.read_fds(pid) else { continue };
+        let inodes: Vec<(u32, u64)> = fds
+            .iter()
+            .filter_map(|(fd, target)| match files::classify(&target.to_string_lossy()) {
+                (FileKind::Socket, Some(inode)) => Some((*fd, inode)),
+                _ => None,
+            })
+            .collect();
+        if inodes.is_empty() {
+            continue;
+        }
+        let sockets = tables.entry(procfs.read_net_namespace(pid)).or_insert_with(|| {
+            let mut table = sockets(procfs, pid);
+            table.retain(|_, socket| socket.protocol != Protocol::Unix);
+            table
+        });
+        let name = procfs.read_stat(pid).map(|stat| stat.comm).unwrap_or_default();
+        for (fd, inode) in inodes {
+            if let Some(socket) = sockets.get(&inode) {
+                owners.push(PortOwner { pid, name: name.clone(), fd, socket: socket.clone() });
+            }
+        }
+    }
+    owners
+}
+
+/// Every process with a socket bound to local port `port`: listeners and the connections they
+/// accepted, plus outgoing connections that happen to use it as their source port
+pub fn port_owners(procfs: &ProcFs, port: u16) -> Vec<PortOwner> {
+    let mut owners: Vec<PortOwner> =
+        inet_socket_owners(procfs).into_iter().filter(|owner| owner.socket.local_port == Some(port)).collect();
+    owners.sort_by_key(|owner| (!owner.socket.is_listening(), owner.pid, owner.fd));
+    owners
+}
+
+/// The local ports each process is listening on, sorted
+pub fn listening_ports(procfs: &ProcFs) -> HashMap<u32, Vec<u16>> {
+    let mut ports: HashMap<u32, Vec<u16>> = HashMap::new();
+    for owner in inet_socket_owners(procfs) {
+        if let (true, Some(port)) = (owner.socket.is_listening(), owner.socket.local_port) {
+            ports.entry(owner.pid).or_default().push(port);
+        }
+    }
+    for list in ports.values_mut() {
+        list.sort_unstable();
+        list.dedup();
+    }
+    ports
+}
+
+/// Every socket in the network namespace of `pid`, by inode
+pub fn sockets(procfs: &ProcFs, pid: u32) -> HashMap<u64, Socket> {
+    Protocol::ALL
+        .iter()
+        .filter_map(|&protocol| {
+            let contents = procfs.read_net(pid, protocol.file())?;
+            Some(match protocol {
+                Protocol::Unix => parse_unix(&contents),
+                _ => parse_inet(protocol, &contents),
+            })
+        })
+        .flatten()
+        .map(|socket| (socket.inode, socket))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Lines as a little-endian kernel writes them; the address words are in host byte order
+    const TCP: &str = "\
+  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 12345 1 0000000000000000 100 0 0 10 0
+   1: 0100007F:1F90 0100007F:D431 01 00000000:00000000 02:000A3F2B 00000000     0        0 12346 2 0000000000000000 20 4 30 10 -1
+";
+
+    const TCP6: &str = "\
+  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000000000000000000000000000:0016 00000000000000000000000000000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 22222 1 0000000000000000 100 0 0 10 0
+   1: 00000000000000000000000001000000:0277 00000000000000000000000000000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 22223 1 0000000000000000 100 0 0 10 0
+   2: 0000000000000000FFFF00000100007F:1F90 0000000000000000FFFF00000100007F:C350 01 00000000:00000000 00:00000000 00000000  1000        0 22224 1 0000000000000000 20 4 30 10 -1
+";
+
+    const UNIX: &str = "\
+Num       RefCount Protocol Flags    Type St Inode Path
+0000000000000000: 00000002 00000000 00010000 0001 01 33333 /run/dbus/system_bus_socket
+0000000000000000: 00000003 00000000 00000000 0001 03 33334
+0000000000000000: 00000002 00000000 00000000 0002 01 33335 /tmp/my socket
+";
+
+    #[test]
+    #[cfg(target_endian = "little")]
+    fn parses_addresses() {
+        assert_eq!(parse_address("0100007F:1F90"), Some(("127.0.0.1".to_string(), 8080)));
+        assert_eq!(parse_address("00000000000000000000000001000000:0277"), Some(("[::1]".to_string(), 631)));
+        assert_eq!(
+            parse_address("0000000000000000FFFF00000100007F:C350"),
+            Some(("[::ffff:127.0.0.1]".to_string(), 50000))
+        );
+        assert_eq!(parse_address("0100007F"), None);
+        assert_eq!(parse_address("0100007F:XYZ"), None);
+        assert_eq!(parse_address("007F:1F90"), None);
+    }
+
+    #[test]
+    #[cfg(target_endian = "little")]
+    fn parses_tcp() {
+        let sockets = parse_inet(Protocol::Tcp, TCP);
+        assert_eq!(sockets.len(), 2);
+        assert_eq!(
+            sockets[0],
+            Socket {
+                protocol: Protocol::Tcp,
+                inode: 12345,
+                local: "127.0.0.1:8080".to_string(),
+                remote: String::new(),
+                local_port: Some(8080),
+                remote_port: None,
+                state: "LISTEN".to_string(),
+            }
+        );
+        assert!(sockets[0].is_listening());
+        assert_eq!(sockets[1].remote, "127.0.0.1:54321");
+        assert_eq!(sockets[1].remote_port, Some(54321));
+        assert_eq!(sockets[1].state, "ESTAB");
+        assert!(!sockets[1].is_listening());
+    }
+
+    #[test]
+    #[cfg(target_endian = "little")]
+    fn parses_tcp6() {
+        let sockets = parse_inet(Protocol::Tcp6, TCP6);
+        let locals: Vec<&str> = sockets.iter().map(|socket| socket.local.as_str()).collect();
+        assert_eq!(locals, ["[::]:22", "[::1]:631", "[::ffff:127.0.0.1]:8080"]);
+        assert_eq!(sockets[2].remote, "[::ffff:127.0.0.1]:50000");
+        assert_eq!(sockets[2].inode, 22224);
+    }
+
+    #[test]
+    fn parses_unix() {
+        let sockets = parse_unix(UNIX);
+        let summary: Vec<(u64, &str, &str)> =
+            sockets.iter().map(|socket| (socket.inode, socket.local.as_str(), socket.state.as_str())).collect();
+        assert_eq!(
+            summary,
+            [
+                (33333, "/run/dbus/system_bus_socket", "LISTEN"),
+                (33334, "", "ESTAB"),
+                (33335, "/tmp/my socket", "UNCONN"),
+            ]
+        );
+        assert!(sockets.iter().all(|socket| socket.local_port.is_none() && socket.remote.is_empty()));
+    }
+}
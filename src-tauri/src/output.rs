@@ -0,0 +1,450 @@
+use std::io::{self, Write};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use procmanager_lib::actions::SignalOutcome;
+use procmanager_lib::audit::AuditEntry;
+use procmanager_lib::files::OpenFile;
+use procmanager_lib::launch::ProcessLaunchSpec;
+use procmanager_lib::lifecycle::LifecycleEvent;
+use procmanager_lib::maps::{Mapping, MappingTotal};
+use procmanager_lib::net::PortOwner;
+use procmanager_lib::snapshot::ProcessSnapshot;
+use procmanager_lib::supervisor::ProgramStatus;
+use procmanager_lib::threads::ThreadSnapshot;
+use procmanager_lib::track::{TrackSample, TrackSummary};
+
+/// How command results are printed
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable, aligned columns
+    #[default]
+    Table,
+    /// A single JSON array
+    Json,
+    /// One JSON object per line
+    Ndjson,
+    /// Comma separated values with a header row
+    Csv,
+}
+
+/// A row that can be written in every output format
+pub trait Record: Serialize {
+    fn headers() -> &'static [&'static str];
+    fn fields(&self) -> Vec<String>;
+}
+
+impl Record for ProcessSnapshot {
+    fn headers() -> &'static [&'static str] {
+        &[
+            "PID", "PPID", "USER", "CPU (%)", "MEM (MB)", "CMD", "START", "STATE", "NICE", "MEM (%)", "VSZ (MB)",
+            "SHARED (MB)", "SWAP (MB)", "PSS (MB)", "USS (MB)",
+        ]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        // Memory that could not be read is left empty rather than shown as 0
+        let megabytes = |value: Option<f32>| value.map_or(String::new(), |mb| format!("{:.2}", mb));
+        vec![
+            self.pid.to_string(),
+            self.ppid.map_or(String::new(), |p| p.to_string()),
+            self.user.clone().unwrap_or_default(),
+            format!("{:.2}", self.cpu),
+            format!("{:.2}", self.mem),
+            self.cmd.clone(),
+            self.start_time.to_string(),
+            self.state.to_string(),
+            self.nice.to_string(),
+            format!("{:.2}", self.mem_percent),
+            format!("{:.2}", self.vsz),
+            megabytes(self.shared),
+            megabytes(self.swap),
+            megabytes(self.pss),
+            megabytes(self.uss),
+        ]
+    }
+}
+
+/// Outcome of a kill/pause/resume/renice request
+#[derive(Clone, Debug, Serialize)]
+pub struct ActionRecord {
+    pub pid: u32,
+    pub action: String,
+    pub success: bool,
+    pub message: String,
+}
+
+impl Record for ActionRecord {
+    fn headers() -> &'static [&'static str] {
+        &["PID", "ACTION", "SUCCESS", "MESSAGE"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.pid.to_string(),
+            self.action.clone(),
+            self.success.to_string(),
+            self.message.clone(),
+        ]
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CommandRecord {
+    pub pid: u32,
+    pub command: String,
+}
+
+impl Record for CommandRecord {
+    fn headers() -> &'static [&'static str] {
+        &["PID", "COMMAND"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![self.pid.to_string(), self.command.clone()]
+    }
+}
+
+impl Record for ThreadSnapshot {
+    fn headers() -> &'static [&'static str] {
+        &["TID", "NAME", "CPU (%)", "STATE", "LAST CPU", "NICE", "VOLUNTARY CS", "INVOLUNTARY CS"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.tid.to_string(),
+            self.name.clone(),
+            format!("{:.2}", self.cpu),
+            self.state.to_string(),
+            self.last_cpu.to_string(),
+            self.nice.to_string(),
+            self.voluntary_ctxt_switches.map_or(String::new(), |n| n.to_string()),
+            self.nonvoluntary_ctxt_switches.map_or(String::new(), |n| n.to_string()),
+        ]
+    }
+}
+
+impl Record for OpenFile {
+    fn headers() -> &'static [&'static str] {
+        &["FD", "TYPE", "TARGET", "PROTO", "LOCAL", "REMOTE", "STATE"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        let socket = self.socket.as_ref();
+        vec![
+            self.fd.to_string(),
+            self.kind.name().to_string(),
+            self.target.clone(),
+            socket.map_or(String::new(), |s| s.protocol.to_string()),
+            socket.map_or(String::new(), |s| s.local.clone()),
+            socket.map_or(String::new(), |s| s.remote.clone()),
+            socket.map_or(String::new(), |s| s.state.clone()),
+        ]
+    }
+}
+
+impl Record for Mapping {
+    fn headers() -> &'static [&'static str] {
+        &["ADDRESS", "PERMS", "SIZE (KB)", "RSS (KB)", "PSS (KB)", "SWAP (KB)", "PRIVATE (KB)", "MAPPING"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.address(),
+            self.perms.clone(),
+            self.size_kb.to_string(),
+            self.rss_kb.to_string(),
+            self.pss_kb.to_string(),
+            self.swap_kb.to_string(),
+            self.private_kb.to_string(),
+            self.name().to_string(),
+        ]
+    }
+}
+
+impl Record for MappingTotal {
+    fn headers() -> &'static [&'static str] {
+        &["MAPPING", "REGIONS", "SIZE (KB)", "RSS (KB)", "PSS (KB)", "SWAP (KB)", "PRIVATE (KB)"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.mappings.to_string(),
+            self.size_kb.to_string(),
+            self.rss_kb.to_string(),
+            self.pss_kb.to_string(),
+            self.swap_kb.to_string(),
+            self.private_kb.to_string(),
+        ]
+    }
+}
+
+impl Record for PortOwner {
+    fn headers() -> &'static [&'static str] {
+        &["PID", "NAME", "FD", "PROTO", "LOCAL", "REMOTE", "STATE"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.pid.to_string(),
+            self.name.clone(),
+            self.fd.to_string(),
+            self.socket.protocol.to_string(),
+            self.socket.local.clone(),
+            self.socket.remote.clone(),
+            self.socket.state.clone(),
+        ]
+    }
+}
+
+impl Record for TrackSample {
+    fn headers() -> &'static [&'static str] {
+        &["Timestamp", "PID", "CPU (%)", "Memory (KB)"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.timestamp.clone(),
+            self.pid.to_string(),
+            format!("{:.2}", self.cpu),
+            self.memory_kb.to_string(),
+        ]
+    }
+}
+
+impl Record for TrackSummary {
+    fn headers() -> &'static [&'static str] {
+        &["PID", "SAMPLES", "AVG CPU (%)", "AVG MEMORY (KB)", "EXITED"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.pid.to_string(),
+            self.samples.to_string(),
+            format!("{:.2}", self.avg_cpu),
+            self.avg_memory_kb.to_string(),
+            self.exited.to_string(),
+        ]
+    }
+}
+
+impl Record for LifecycleEvent {
+    fn headers() -> &'static [&'static str] {
+        &["TIMESTAMP", "PID", "EVENT", "DETAILS"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        let event = serde_json::to_value(self.event).ok().and_then(|v| v.as_str().map(String::from)).unwrap_or_default();
+        vec![self.timestamp.clone(), self.pid.to_string(), event, self.describe()]
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct OsRecord {
+    pub os: String,
+}
+
+impl Record for OsRecord {
+    fn headers() -> &'static [&'static str] {
+        &["OS"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![self.os.clone()]
+    }
+}
+
+impl Record for ProcessLaunchSpec {
+    fn headers() -> &'static [&'static str] {
+        &["PID", "PPID", "NAME", "ARGV", "CWD", "UID", "GID", "ENV VARS"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.pid.to_string(),
+            self.ppid.to_string(),
+            self.name.clone(),
+            self.command_line(),
+            self.cwd.as_ref().map_or(String::new(), |cwd| cwd.display().to_string()),
+            self.uid.map_or(String::new(), |uid| uid.to_string()),
+            self.gid.map_or(String::new(), |gid| gid.to_string()),
+            self.env.len().to_string(),
+        ]
+    }
+}
+
+impl Record for ProgramStatus {
+    fn headers() -> &'static [&'static str] {
+        &["NAME", "STATE", "PID", "RESTARTS", "UPTIME (s)", "LAST EXIT", "MESSAGE"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.state.clone(),
+            self.pid.map_or(String::new(), |p| p.to_string()),
+            self.restarts.to_string(),
+            self.uptime_secs.map_or(String::new(), |u| u.to_string()),
+            self.last_exit.clone().unwrap_or_default(),
+            self.message.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+impl Record for SignalOutcome {
+    fn headers() -> &'static [&'static str] {
+        &["PID", "SIGNALS", "ESCALATED", "EXITED"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![self.pid.to_string(), self.signals.join(" "), self.escalated.to_string(), self.exited.to_string()]
+    }
+}
+
+impl Record for AuditEntry {
+    fn headers() -> &'static [&'static str] {
+        &["TIMESTAMP", "USER", "SUDO", "SOURCE", "PID", "NAME", "ACTION", "ARGS", "RESULT"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.timestamp.clone(),
+            self.user.clone(),
+            if self.sudo { "yes" } else { "no" }.to_string(),
+            self.source.to_string(),
+            self.pid.to_string(),
+            self.name.clone().unwrap_or_default(),
+            self.action.clone(),
+            self.args.join(" "),
+            match &self.error {
+                Some(error) => format!("failed: {}", error),
+                None => "ok".to_string(),
+            },
+        ]
+    }
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains(',') || field.contains('"') || field.contains('\n') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Writes records one at a time so long running commands can stream their output.
+/// Table output needs every row to size its columns, so it is buffered until `finish`.
+pub struct RecordWriter<W: Write, T: Record> {
+    out: W,
+    format: OutputFormat,
+    rows: Vec<Vec<String>>,
+    count: usize,
+    _record: std::marker::PhantomData<T>,
+}
+
+impl<W: Write, T: Record> RecordWriter<W, T> {
+    pub fn new(mut out: W, format: OutputFormat) -> io::Result<Self> {
+        match format {
+            OutputFormat::Csv => {
+                let header: Vec<String> = T::headers().iter().map(|h| csv_escape(h)).collect();
+                writeln!(out, "{}", header.join(","))?;
+            }
+            OutputFormat::Json => write!(out, "[")?,
+            OutputFormat::Ndjson | OutputFormat::Table => {}
+        }
+        out.flush()?;
+        Ok(RecordWriter { out, format, rows: Vec::new(), count: 0, _record: std::marker::PhantomData })
+    }
+
+    pub fn write(&mut self, record: &T) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Table => self.rows.push(record.fields()),
+            OutputFormat::Csv => {
+                let fields: Vec<String> = record.fields().iter().map(|f| csv_escape(f)).collect();
+                writeln!(self.out, "{}", fields.join(","))?;
+            }
+            OutputFormat::Ndjson => {
+                serde_json::to_writer(&mut self.out, record)?;
+                writeln!(self.out)?;
+            }
+            OutputFormat::Json => {
+                if self.count > 0 {
+                    write!(self.out, ",")?;
+                }
+                writeln!(self.out)?;
+                serde_json::to_writer(&mut self.out, record)?;
+            }
+        }
+        self.count += 1;
+        self.out.flush()
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Table => {
+                let headers = T::headers();
+                let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+                for row in &self.rows {
+                    for (width, field) in widths.iter_mut().zip(row) {
+                        *width = (*width).max(field.chars().count());
+                    }
+                }
+                let line = |fields: Vec<String>| {
+                    fields
+                        .iter()
+                        .zip(&widths)
+                        .map(|(field, width)| format!("{:<width$}", field, width = width))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                        .trim_end()
+                        .to_string()
+                };
+                writeln!(self.out, "{}", line(headers.iter().map(|h| h.to_string()).collect()))?;
+                writeln!(self.out, "{}", "-".repeat(widths.iter().sum::<usize>() + widths.len().saturating_sub(1)))?;
+                for row in std::mem::take(&mut self.rows) {
+                    writeln!(self.out, "{}", line(row))?;
+                }
+            }
+            OutputFormat::Json => {
+                if self.count > 0 {
+                    writeln!(self.out)?;
+                }
+                writeln!(self.out, "]")?;
+            }
+            OutputFormat::Csv | OutputFormat::Ndjson => {}
+        }
+        self.out.flush()
+    }
+}
+
+/// Writes a complete list of records
+pub fn write_records<W: Write, T: Record>(out: W, format: OutputFormat, records: &[T]) -> io::Result<()> {
+    let mut writer = RecordWriter::new(out, format)?;
+    for record in records {
+        writer.write(record)?;
+    }
+    writer.finish()
+}
+
+/// Prints a complete list of records to stdout
+pub fn print_records<T: Record>(format: OutputFormat, records: &[T]) {
+    if let Err(e) = write_records(io::stdout().lock(), format, records) {
+        eprintln!("Failed to write output: {}", e);
+    }
+}
+
+/// Reports the outcome of an action: the plain message for table output,
+/// a structured record otherwise
+pub fn report_action(format: OutputFormat, record: ActionRecord) {
+    if format == OutputFormat::Table {
+        if record.success {
+            println!("{}", record.message);
+        } else {
+            eprintln!("{}", record.message);
+        }
+    } else {
+        print_records(format, &[record]);
+    }
+}
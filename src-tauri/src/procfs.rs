@@ -0,0 +1,473 @@
+// Direct readers for the /proc files the process table needs, so a refresh
+// never has to fork `ps` or `renice`
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use nix::errno::Errno;
+use nix::libc;
+use nix::unistd::{sysconf, SysconfVar};
+
+/// Fields of /proc/[pid]/stat
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stat {
+    pub pid: u32,
+    pub comm: String,
+    pub state: char,
+    pub ppid: u32,
+    /// User and system time in clock ticks
+    pub utime: u64,
+    pub stime: u64,
+    pub nice: i32,
+    pub num_threads: u32,
+    /// Start time in clock ticks after boot
+    pub starttime: u64,
+    /// CPU the task last ran on
+    pub processor: u32,
+}
+
+/// Fields of /proc/[pid]/status
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Status {
+    pub name: String,
+    pub ruid: Option<u32>,
+    pub euid: Option<u32>,
+    pub rgid: Option<u32>,
+    pub egid: Option<u32>,
+    pub threads: Option<u32>,
+    pub vm_rss_kb: Option<u64>,
+    pub vm_size_kb: Option<u64>,
+    pub vm_swap_kb: Option<u64>,
+    pub voluntary_ctxt_switches: Option<u64>,
+    pub nonvoluntary_ctxt_switches: Option<u64>,
+}
+
+/// Fields of /proc/[pid]/io
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Io {
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+}
+
+/// /proc/[pid]/statm, in pages
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Statm {
+    pub size: u64,
+    pub resident: u64,
+    pub shared: u64,
+}
+
+/// The kB counters of an smaps entry. /proc/[pid]/smaps_rollup holds their sum over every mapping.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MemoryCounters {
+    pub rss_kb: u64,
+    /// Proportional set size: each resident page divided by the number of processes mapping it
+    pub pss_kb: u64,
+    pub shared_clean_kb: u64,
+    pub shared_dirty_kb: u64,
+    pub private_clean_kb: u64,
+    pub private_dirty_kb: u64,
+    pub swap_kb: u64,
+}
+
+impl MemoryCounters {
+    /// Unique set size: the resident pages only this process maps, freed if it exits
+    pub fn uss_kb(&self) -> u64 {
+        self.private_clean_kb + self.private_dirty_kb
+    }
+
+    pub fn shared_kb(&self) -> u64 {
+        self.shared_clean_kb + self.shared_dirty_kb
+    }
+}
+
+/// Everything read for one process in a single pass
+#[derive(Clone, Debug)]
+pub struct ProcessSample {
+    pub stat: Stat,
+    pub status: Option<Status>,
+    pub cmdline: Vec<String>,
+    pub io: Option<Io>,
+    pub statm: Option<Statm>,
+}
+
+pub fn parse_stat(contents: &str) -> Option<Stat> {
+    // The command name is wrapped in parentheses and may itself contain spaces or parentheses
+    let open = contents.find('(')?;
+    let close = contents.rfind(')')?;
+    let pid = contents[..open].trim().parse().ok()?;
+    let comm = contents[open + 1..close].to_string();
+    let fields: Vec<&str> = contents[close + 1..].split_whitespace().collect();
+    let field = |index: usize| fields.get(index).copied();
+
+    Some(Stat {
+        pid,
+        comm,
+        state: field(0)?.chars().next()?,
+        ppid: field(1)?.parse().ok()?,
+        utime: field(11)?.parse().ok()?,
+        stime: field(12)?.parse().ok()?,
+        nice: field(16)?.parse().ok()?,
+        num_threads: field(17)?.parse().ok()?,
+        starttime: field(19)?.parse().ok()?,
+        processor: field(36).and_then(|p| p.parse().ok()).unwrap_or(0),
+    })
+}
+
+pub fn parse_status(contents: &str) -> Status {
+    let mut status = Status::default();
+    for line in contents.lines() {
+        let Some((key, value)) = line.split_once(':') else { continue };
+        let mut values = value.split_whitespace();
+        match key {
+            "Name" => status.name = value.trim().to_string(),
+            "Uid" => {
+                status.ruid = values.next().and_then(|v| v.parse().ok());
+                status.euid = values.next().and_then(|v| v.parse().ok());
+            }
+            "Gid" => {
+                status.rgid = values.next().and_then(|v| v.parse().ok());
+                status.egid = values.next().and_then(|v| v.parse().ok());
+            }
+            "Threads" => status.threads = values.next().and_then(|v| v.parse().ok()),
+            "VmRSS" => status.vm_rss_kb = values.next().and_then(|v| v.parse().ok()),
+            "VmSize" => status.vm_size_kb = values.next().and_then(|v| v.parse().ok()),
+            "VmSwap" => status.vm_swap_kb = values.next().and_then(|v| v.parse().ok()),
+            "voluntary_ctxt_switches" => status.voluntary_ctxt_switches = values.next().and_then(|v| v.parse().ok()),
+            "nonvoluntary_ctxt_switches" => status.nonvoluntary_ctxt_switches = values.next().and_then(|v| v.parse().ok()),
+            _ => {}
+        }
+    }
+    status
+}
+
+/// Adds a `Key:   123 kB` line of smaps or smaps_rollup to `counters`; false for any other line
+pub fn add_memory_counter(counters: &mut MemoryCounters, line: &str) -> bool {
+    let Some((key, value)) = line.split_once(':') else { return false };
+    let counter = match key {
+        "Rss" => &mut counters.rss_kb,
+        "Pss" => &mut counters.pss_kb,
+        "Shared_Clean" => &mut counters.shared_clean_kb,
+        "Shared_Dirty" => &mut counters.shared_dirty_kb,
+        "Private_Clean" => &mut counters.private_clean_kb,
+        "Private_Dirty" => &mut counters.private_dirty_kb,
+        "Swap" => &mut counters.swap_kb,
+        _ => return false,
+    };
+    match value.split_whitespace().next().and_then(|v| v.parse::<u64>().ok()) {
+        Some(kb) => {
+            *counter += kb;
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn parse_smaps_rollup(contents: &str) -> MemoryCounters {
+    let mut counters = MemoryCounters::default();
+    for line in contents.lines() {
+        add_memory_counter(&mut counters, line);
+    }
+    counters
+}
+
+/// MemTotal of /proc/meminfo, in kB
+pub fn parse_mem_total(contents: &str) -> Option<u64> {
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix("MemTotal:"))
+        .and_then(|value| value.split_whitespace().next()?.parse().ok())
+}
+
+pub fn parse_io(contents: &str) -> Io {
+    let mut io = Io::default();
+    for line in contents.lines() {
+        match line.split_once(':') {
+            Some(("read_bytes", value)) => io.read_bytes = value.trim().parse().unwrap_or(0),
+            Some(("write_bytes", value)) => io.write_bytes = value.trim().parse().unwrap_or(0),
+            _ => {}
+        }
+    }
+    io
+}
+
+pub fn parse_statm(contents: &str) -> Option<Statm> {
+    let mut fields = contents.split_whitespace().map(|f| f.parse::<u64>().ok());
+    Some(Statm {
+        size: fields.next()??,
+        resident: fields.next()??,
+        shared: fields.next()??,
+    })
+}
+
+/// The NUL terminated fields of /proc/[pid]/cmdline or environ. Empty fields are real, e.g. an
+/// `""` argument, so only the terminator after the last field is dropped.
+fn split_nul(contents: &[u8]) -> Vec<&[u8]> {
+    if contents.is_empty() {
+        return Vec::new();
+    }
+    contents.strip_suffix(&[0]).unwrap_or(contents).split(|&b| b == 0).collect()
+}
+
+/// The argument list of /proc/[pid]/cmdline, byte for byte
+pub fn parse_argv(contents: &[u8]) -> Vec<OsString> {
+    split_nul(contents).into_iter().map(|arg| OsStr::from_bytes(arg).to_os_string()).collect()
+}
+
+/// The argument list of /proc/[pid]/cmdline decoded for display
+pub fn parse_cmdline(contents: &[u8]) -> Vec<String> {
+    split_nul(contents).into_iter().map(|arg| String::from_utf8_lossy(arg).into_owned()).collect()
+}
+
+/// Splits the NUL separated `KEY=VALUE` list of /proc/[pid]/environ, byte for byte
+pub fn parse_environ(contents: &[u8]) -> Vec<(OsString, OsString)> {
+    split_nul(contents)
+        .into_iter()
+        .filter_map(|entry| {
+            let eq = entry.iter().position(|&b| b == b'=').filter(|&eq| eq > 0)?;
+            Some((OsStr::from_bytes(&entry[..eq]).to_os_string(), OsStr::from_bytes(&entry[eq + 1..]).to_os_string()))
+        })
+        .collect()
+}
+
+/// A /proc mount. Tests and benchmarks can point it at a synthetic tree.
+#[derive(Clone, Debug)]
+pub struct ProcFs {
+    root: PathBuf,
+    clock_ticks: u64,
+    page_size: u64,
+}
+
+impl Default for ProcFs {
+    fn default() -> Self {
+        ProcFs::new()
+    }
+}
+
+impl ProcFs {
+    pub fn new() -> Self {
+        ProcFs::with_root("/proc")
+    }
+
+    pub fn with_root(root: impl Into<PathBuf>) -> Self {
+        let clock_ticks = sysconf(SysconfVar::CLK_TCK).ok().flatten().unwrap_or(100) as u64;
+        let page_size = sysconf(SysconfVar::PAGE_SIZE).ok().flatten().unwrap_or(4096) as u64;
+        ProcFs { root: root.into(), clock_ticks, page_size }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Clock ticks per second, the unit of utime, stime and starttime
+    pub fn clock_ticks(&self) -> u64 {
+        self.clock_ticks
+    }
+
+    pub fn page_size(&self) -> u64 {
+        self.page_size
+    }
+
+    fn path(&self, pid: u32, file: &str) -> PathBuf {
+        self.root.join(pid.to_string()).join(file)
+    }
+
+    fn task_path(&self, pid: u32, tid: u32, file: &str) -> PathBuf {
+        self.path(pid, "task").join(tid.to_string()).join(file)
+    }
+
+    /// Physical memory in kB
+    pub fn mem_total_kb(&self) -> Option<u64> {
+        parse_mem_total(&fs::read_to_string(self.root.join("meminfo")).ok()?)
+    }
+
+    /// Every numeric entry of the /proc root
+    pub fn pids(&self) -> Vec<u32> {
+        numeric_entries(&self.root)
+    }
+
+    /// Thread IDs of a process, from /proc/[pid]/task; empty when the process is gone
+    pub fn tids(&self, pid: u32) -> Vec<u32> {
+        numeric_entries(&self.path(pid, "task"))
+    }
+
+    /// System boot time in seconds since the Unix epoch
+    pub fn boot_time(&self) -> Option<u64> {
+        fs::read_to_string(self.root.join("stat"))
+            .ok()?
+            .lines()
+            .find_map(|line| line.strip_prefix("btime"))
+            .and_then(|value| value.trim().parse().ok())
+    }
+
+    pub fn read_stat(&self, pid: u32) -> Option<Stat> {
+        parse_stat(&fs::read_to_string(self.path(pid, "stat")).ok()?)
+    }
+
+    pub fn read_status(&self, pid: u32) -> Option<Status> {
+        fs::read_to_string(self.path(pid, "status")).ok().map(|s| parse_status(&s))
+    }
+
+    /// /proc/[pid]/task/[tid]/stat, which has the same fields for a single thread
+    pub fn read_task_stat(&self, pid: u32, tid: u32) -> Option<Stat> {
+        parse_stat(&fs::read_to_string(self.task_path(pid, tid, "stat")).ok()?)
+    }
+
+    pub fn read_task_status(&self, pid: u32, tid: u32) -> Option<Status> {
+        fs::read_to_string(self.task_path(pid, tid, "status")).ok().map(|s| parse_status(&s))
+    }
+
+    pub fn read_cmdline(&self, pid: u32) -> Option<Vec<String>> {
+        fs::read(self.path(pid, "cmdline")).ok().map(|c| parse_cmdline(&c))
+    }
+
+    /// The command line undecoded, for running it again
+    pub fn read_argv(&self, pid: u32) -> Option<Vec<OsString>> {
+        fs::read(self.path(pid, "cmdline")).ok().map(|c| parse_argv(&c))
+    }
+
+    /// Working directory; needs ptrace privileges like `read_io`
+    pub fn read_cwd(&self, pid: u32) -> Option<PathBuf> {
+        fs::read_link(self.path(pid, "cwd")).ok()
+    }
+
+    /// Path of the executable; `None` when it was deleted or replaced since the process started
+    pub fn read_exe(&self, pid: u32) -> Option<PathBuf> {
+        let exe = fs::read_link(self.path(pid, "exe")).ok()?;
+        (!exe.to_string_lossy().ends_with(" (deleted)")).then_some(exe)
+    }
+
+    /// Environment the process was started with; needs ptrace privileges like `read_io`
+    pub fn read_environ(&self, pid: u32) -> Option<Vec<(OsString, OsString)>> {
+        fs::read(self.path(pid, "environ")).ok().map(|e| parse_environ(&e))
+    }
+
+    /// Every open descriptor with what its /proc/[pid]/fd link points to. Reading another
+    /// user's descriptors needs ptrace privileges, so the error tells that apart from a missing process.
+    pub fn read_fds(&self, pid: u32) -> io::Result<Vec<(u32, PathBuf)>> {
+        let mut fds = Vec::new();
+        for entry in fs::read_dir(self.path(pid, "fd"))?.filter_map(|entry| entry.ok()) {
+            let Some(fd) = entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) else { continue };
+            match fs::read_link(entry.path()) {
+                Ok(target) => fds.push((fd, target)),
+                // The directory of a process running as root can be listed, but not its links
+                Err(e) if e.kind() == io::ErrorKind::PermissionDenied => return Err(e),
+                // A descriptor closed since the directory was listed has no link left
+                Err(_) => {}
+            }
+        }
+        fds.sort_by_key(|(fd, _)| *fd);
+        Ok(fds)
+    }
+
+    /// A socket table of /proc/[pid]/net (`tcp`, `udp6`, `unix`...), which shows the process's network namespace
+    pub fn read_net(&self, pid: u32, file: &str) -> Option<String> {
+        fs::read_to_string(self.path(pid, "net").join(file)).ok()
+    }
+
+    /// The `net:[inode]` link naming the process's network namespace; processes sharing it see the same socket tables
+    pub fn read_net_namespace(&self, pid: u32) -> Option<PathBuf> {
+        fs::read_link(self.path(pid, "ns/net")).ok()
+    }
+
+    /// Needs the same privileges as ptrace, so it is `None` for other users' processes
+    pub fn read_io(&self, pid: u32) -> Option<Io> {
+        fs::read_to_string(self.path(pid, "io")).ok().map(|s| parse_io(&s))
+    }
+
+    /// PSS, USS and swap summed over every mapping. Needs ptrace privileges like `read_io`, and
+    /// the kernel walks the page tables to produce it, so it is slower than the other files.
+    pub fn read_smaps_rollup(&self, pid: u32) -> Option<MemoryCounters> {
+        fs::read_to_string(self.path(pid, "smaps_rollup")).ok().map(|s| parse_smaps_rollup(&s))
+    }
+
+    /// Every mapping with its memory counters. Like `read_fds`, the error tells a missing
+    /// process apart from one whose memory needs ptrace privileges to read.
+    pub fn read_smaps(&self, pid: u32) -> io::Result<String> {
+        fs::read_to_string(self.path(pid, "smaps"))
+    }
+
+    pub fn read_statm(&self, pid: u32) -> Option<Statm> {
+        parse_statm(&fs::read_to_string(self.path(pid, "statm")).ok()?)
+    }
+
+    /// Reads every file for `pid`; `None` when the process is gone
+    pub fn sample(&self, pid: u32) -> Option<ProcessSample> {
+        Some(ProcessSample {
+            stat: self.read_stat(pid)?,
+            status: self.read_status(pid),
+            cmdline: self.read_cmdline(pid).unwrap_or_default(),
+            io: self.read_io(pid),
+            statm: self.read_statm(pid),
+        })
+    }
+}
+
+fn numeric_entries(dir: &Path) -> Vec<u32> {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| entry.file_name().to_str().and_then(|name| name.parse().ok()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Whether `pid` can name a single process: 0 and anything above `i32::MAX`, which turns
+/// negative as a `pid_t`, mean the caller, a process group or every process to the kernel
+pub fn is_valid_pid(pid: u32) -> bool {
+    pid != 0 && pid <= i32::MAX as u32
+}
+
+/// Sets the nice value of a process (or, given a TID, a single thread) with setpriority(2)
+pub fn set_nice(pid: u32, nice: i32) -> nix::Result<()> {
+    if !is_valid_pid(pid) {
+        return Err(Errno::ESRCH);
+    }
+    // The `which` argument's type differs between libc targets
+    let res = unsafe { libc::setpriority(libc::PRIO_PROCESS as _, pid as libc::id_t, nice) };
+    Errno::result(res).map(drop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn os(value: &str) -> OsString {
+        OsString::from(value)
+    }
+
+    #[test]
+    fn splits_on_nul_with_or_without_a_trailing_one() {
+        assert_eq!(parse_argv(b"sleep\x0060\x00"), [os("sleep"), os("60")]);
+        // setproctitle() style rewrites can drop the final NUL
+        assert_eq!(parse_argv(b"sleep\x0060"), [os("sleep"), os("60")]);
+        assert!(parse_argv(b"").is_empty());
+    }
+
+    #[test]
+    fn keeps_empty_arguments() {
+        assert_eq!(parse_argv(b"printf\x00\x00x\x00"), [os("printf"), os(""), os("x")]);
+        // Only the terminator is dropped, not an empty last argument
+        assert_eq!(parse_argv(b"echo\x00\x00"), [os("echo"), os("")]);
+    }
+
+    #[test]
+    fn argv_keeps_bytes_that_are_not_utf8() {
+        let argv = parse_argv(b"cat\x00caf\xe9\x00");
+        assert_eq!(argv[1].as_bytes(), b"caf\xe9");
+        assert_eq!(parse_cmdline(b"cat\x00caf\xe9\x00"), ["cat", "caf\u{fffd}"]);
+    }
+
+    #[test]
+    fn environ_skips_entries_without_a_key() {
+        let environ = parse_environ(b"HOME=/root\x00garbage\x00=orphan\x00EMPTY=\x00OPTS=a=b\x00");
+        assert_eq!(
+            environ,
+            [(os("HOME"), os("/root")), (os("EMPTY"), os("")), (os("OPTS"), os("a=b"))]
+        );
+    }
+}
@@ -0,0 +1,142 @@
+// Picks the processes an action applies to by name, command line, owner, parent, subtree, age or port
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use regex::Regex;
+
+use crate::net;
+use crate::procfs::ProcFs;
+use crate::snapshot::ProcessSnapshot;
+
+/// Every criterion that is set must match. An empty selector matches nothing, so a missing
+/// flag can never turn into "every process".
+#[derive(Clone, Debug, Default)]
+pub struct Selector {
+    /// Matched against the process name
+    pub name: Option<Regex>,
+    /// Matched against the full command line
+    pub cmdline: Option<Regex>,
+    /// User name or numeric uid
+    pub user: Option<String>,
+    /// Direct children of this PID
+    pub ppid: Option<u32>,
+    /// This PID and all of its descendants
+    pub tree: Option<u32>,
+    /// Processes started at least this long ago
+    pub older_than: Option<Duration>,
+    /// Processes with a TCP or UDP socket on this local port
+    pub port: Option<u16>,
+}
+
+impl Selector {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.cmdline.is_none()
+            && self.user.is_none()
+            && self.ppid.is_none()
+            && self.tree.is_none()
+            && self.older_than.is_none()
+            && self.port.is_none()
+    }
+
+    /// The matching processes, leaving out procmanager itself
+    pub fn select<'a>(&self, processes: &'a [ProcessSnapshot]) -> Vec<&'a ProcessSnapshot> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        let own_pid = std::process::id();
+        let subtree = self.tree.map(|root| subtree(processes, root));
+        let port_owners: Option<HashSet<u32>> =
+            self.port.map(|port| net::port_owners(&ProcFs::new(), port).iter().map(|owner| owner.pid).collect());
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+
+        processes
+            .iter()
+            .filter(|p| p.pid != own_pid)
+            .filter(|p| self.name.as_ref().is_none_or(|re| re.is_match(&p.cmd)))
+            .filter(|p| self.cmdline.as_ref().is_none_or(|re| re.is_match(&p.cmdline)))
+            .filter(|p| self.user.as_ref().is_none_or(|user| matches_user(p, user)))
+            .filter(|p| self.ppid.is_none_or(|ppid| p.ppid == Some(ppid)))
+            .filter(|p| subtree.as_ref().is_none_or(|pids| pids.contains(&p.pid)))
+            .filter(|p| self.older_than.is_none_or(|age| now.saturating_sub(p.start_time) >= age.as_secs()))
+            .filter(|p| port_owners.as_ref().is_none_or(|pids| pids.contains(&p.pid)))
+            .collect()
+    }
+}
+
+fn matches_user(process: &ProcessSnapshot, user: &str) -> bool {
+    match user.parse::<u32>() {
+        Ok(uid) => process.uid == Some(uid),
+        Err(_) => process.user.as_deref() == Some(user),
+    }
+}
+
+/// `root` and every process below it
+fn subtree(processes: &[ProcessSnapshot], root: u32) -> HashSet<u32> {
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    for process in processes {
+        if let Some(ppid) = process.ppid {
+            children.entry(ppid).or_default().push(process.pid);
+        }
+    }
+    let mut pids = HashSet::new();
+    let mut pending = vec![root];
+    while let Some(pid) = pending.pop() {
+        if pids.insert(pid) {
+            pending.extend(children.get(&pid).into_iter().flatten());
+        }
+    }
+    pids
+}
+
+/// Parses an age such as `90`, `30s`, `15m`, `2h` or `7d`; plain numbers are seconds
+pub fn parse_age(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number.parse().map_err(|_| format!("invalid age {:?}, expected e.g. 30s, 15m, 2h or 7d", value))?;
+    let seconds = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("invalid age unit {:?}, expected s, m, h or d", unit)),
+    };
+    number.checked_mul(seconds).map(Duration::from_secs).ok_or_else(|| format!("age {:?} is too large", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ages() {
+        let cases: &[(&str, Result<u64, &str>)] = &[
+            ("90", Ok(90)),
+            ("30s", Ok(30)),
+            ("15m", Ok(15 * 60)),
+            ("2h", Ok(2 * 60 * 60)),
+            ("7d", Ok(7 * 24 * 60 * 60)),
+            (" 2h ", Ok(2 * 60 * 60)),
+            ("0", Ok(0)),
+            ("18446744073709551615", Ok(u64::MAX)),
+            ("", Err("invalid age")),
+            ("h", Err("invalid age")),
+            ("-5m", Err("invalid age")),
+            ("1.5h", Err("invalid age unit")),
+            ("2w", Err("invalid age unit")),
+            ("2 h", Err("invalid age unit")),
+            ("99999999999999999999", Err("invalid age")),
+            ("18446744073709551615m", Err("too large")),
+            ("213503982334601d", Ok(213503982334601 * 24 * 60 * 60)),
+            ("213503982334602d", Err("too large")),
+        ];
+        for (value, expected) in cases {
+            match (parse_age(value), expected) {
+                (Ok(age), Ok(seconds)) => assert_eq!(age, Duration::from_secs(*seconds), "{:?}", value),
+                (Err(error), Err(message)) => assert!(error.contains(message), "{:?}: {}", value, error),
+                (result, _) => panic!("{:?} gave {:?}, expected {:?}", value, result, expected),
+            }
+        }
+    }
+}
@@ -0,0 +1,268 @@
+// `serve`: the process table and actions over a local HTTP/JSON API, for headless machines
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, Method, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use nix::sys::signal::Signal;
+use serde::Deserialize;
+use sysinfo::System;
+
+use procmanager_lib::actions::{self, ActionError};
+use procmanager_lib::audit::{self, Source};
+use procmanager_lib::filter::{Filter, FilterError};
+use procmanager_lib::metrics::{self, MetricsFilter};
+use procmanager_lib::snapshot::{ProcessSnapshot, Snapshotter};
+use procmanager_lib::system::{self, SystemInfo};
+use procmanager_lib::tree::build_process_tree;
+
+use crate::output::ActionRecord;
+
+pub struct ServeOptions {
+    pub listen: SocketAddr,
+    /// Clients must send `Authorization: Bearer <token>` when set
+    pub token: Option<String>,
+    /// Rejects every POST endpoint
+    pub read_only: bool,
+    pub interval: Duration,
+    /// Which processes get series on /metrics
+    pub metrics: MetricsFilter,
+}
+
+struct ServerState {
+    /// Latest snapshot and system metrics, refreshed in the background so requests never wait for a
+    /// CPU sample and CPU usage is always measured over a whole interval, however often clients poll
+    processes: RwLock<Vec<ProcessSnapshot>>,
+    system: RwLock<SystemInfo>,
+    token: Option<String>,
+    read_only: bool,
+    metrics: MetricsFilter,
+}
+
+type Shared = Arc<ServerState>;
+
+#[derive(Deserialize)]
+struct SignalRequest {
+    /// Name (`KILL`, `SIGSTOP`) or number
+    signal: String,
+}
+
+#[derive(Deserialize)]
+struct ReniceRequest {
+    nice: i32,
+}
+
+struct ApiError(StatusCode, serde_json::Value);
+
+impl From<ActionError> for ApiError {
+    fn from(error: ActionError) -> Self {
+        let status = match error {
+            ActionError::NotFound { .. } => StatusCode::NOT_FOUND,
+            ActionError::PermissionDenied { .. } => StatusCode::FORBIDDEN,
+            ActionError::InvalidNice { .. } => StatusCode::BAD_REQUEST,
+            ActionError::Failed { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError(status, serde_json::to_value(&error).unwrap_or_default())
+    }
+}
+
+impl From<FilterError> for ApiError {
+    fn from(error: FilterError) -> Self {
+        ApiError(
+            StatusCode::BAD_REQUEST,
+            serde_json::json!({ "kind": "InvalidFilter", "message": error.message, "position": error.position }),
+        )
+    }
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: &str) -> Self {
+        ApiError(status, serde_json::json!({ "kind": "Failed", "message": message }))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(self.1)).into_response()
+    }
+}
+
+/// Compares every byte whatever the first mismatch, so response times do not reveal how much of
+/// a guessed token is right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+async fn authorize(State(state): State<Shared>, request: Request, next: Next) -> Response {
+    if let Some(token) = &state.token {
+        let expected = format!("Bearer {}", token);
+        let given = request.headers().get(header::AUTHORIZATION).map_or(&[][..], |v| v.as_bytes());
+        if !constant_time_eq(given, expected.as_bytes()) {
+            return ApiError::new(StatusCode::UNAUTHORIZED, "missing or invalid token").into_response();
+        }
+    }
+    if state.read_only && request.method() == Method::POST {
+        return ApiError::new(StatusCode::FORBIDDEN, "the server is read-only").into_response();
+    }
+    next.run(request).await
+}
+
+#[derive(Deserialize)]
+struct ProcessQuery {
+    /// Filter expression, as for `ptable --where`
+    #[serde(rename = "where")]
+    filter: Option<String>,
+}
+
+async fn list_processes(
+    State(state): State<Shared>,
+    Query(query): Query<ProcessQuery>,
+) -> Result<Json<Vec<ProcessSnapshot>>, ApiError> {
+    let processes = state.processes.read().unwrap();
+    match query.filter {
+        Some(expression) => {
+            let filter = Filter::parse(&expression)?;
+            Ok(Json(processes.iter().filter(|p| filter.matches(*p)).cloned().collect()))
+        }
+        None => Ok(Json(processes.clone())),
+    }
+}
+
+async fn get_process(State(state): State<Shared>, Path(pid): Path<u32>) -> Result<Json<ProcessSnapshot>, ApiError> {
+    let processes = state.processes.read().unwrap();
+    match processes.iter().find(|p| p.pid == pid) {
+        Some(process) => Ok(Json(process.clone())),
+        None => Err(ActionError::NotFound { pid }.into()),
+    }
+}
+
+async fn process_tree(State(state): State<Shared>) -> impl IntoResponse {
+    Json(build_process_tree(&state.processes.read().unwrap()))
+}
+
+async fn system_info(State(state): State<Shared>) -> impl IntoResponse {
+    Json(state.system.read().unwrap().clone())
+}
+
+async fn prometheus_metrics(State(state): State<Shared>) -> impl IntoResponse {
+    let body = metrics::render(&state.processes.read().unwrap(), &state.system.read().unwrap(), &state.metrics);
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
+
+async fn send_signal(Path(pid): Path<u32>, Json(body): Json<SignalRequest>) -> Result<Json<ActionRecord>, ApiError> {
+    let signal = actions::parse_signal(&body.signal)
+        .ok_or_else(|| ApiError::new(StatusCode::BAD_REQUEST, &format!("unknown signal {}", body.signal)))?;
+
+    // The same actions `kill`, `pause` and `resume` run from the command line
+    let action = match signal {
+        Signal::SIGKILL => "kill",
+        Signal::SIGSTOP => "pause",
+        Signal::SIGCONT => "resume",
+        _ => "signal",
+    };
+    let args = if action == "signal" { vec![signal.as_str().to_string()] } else { Vec::new() };
+    audit::audited(Source::Api, pid, action, &args, false, || match signal {
+        Signal::SIGKILL => actions::kill(pid),
+        Signal::SIGSTOP => actions::pause(pid),
+        Signal::SIGCONT => actions::resume(pid),
+        other => actions::signal(pid, other),
+    })?;
+    Ok(Json(ActionRecord {
+        pid,
+        action: action.to_string(),
+        success: true,
+        message: format!("Sent {} to PID {}", signal.as_str(), pid),
+    }))
+}
+
+async fn renice(Path(pid): Path<u32>, Json(body): Json<ReniceRequest>) -> Result<Json<ActionRecord>, ApiError> {
+    audit::audited(Source::Api, pid, "renice", &[body.nice.to_string()], false, || actions::renice(pid, body.nice))?;
+    Ok(Json(ActionRecord {
+        pid,
+        action: "renice".to_string(),
+        success: true,
+        message: format!("Changed niceness of PID {} to {}", pid, body.nice),
+    }))
+}
+
+fn router(state: Shared) -> Router {
+    Router::new()
+        .route("/processes", get(list_processes))
+        .route("/processes/:pid", get(get_process))
+        .route("/processes/:pid/signal", post(send_signal))
+        .route("/processes/:pid/renice", post(renice))
+        .route("/tree", get(process_tree))
+        .route("/system", get(system_info))
+        .route("/metrics", get(prometheus_metrics))
+        .layer(middleware::from_fn_with_state(state.clone(), authorize))
+        .with_state(state)
+}
+
+/// Keeps `processes` and `system` of the state up to date
+fn start_refresher(state: Shared, mut snapshotter: Snapshotter, mut system: System, interval: Duration) {
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        let processes = snapshotter.refresh();
+        *state.processes.write().unwrap() = processes;
+        *state.system.write().unwrap() = system::get_system_metrics(&mut system);
+    });
+}
+
+/// Serves until interrupted with Ctrl-C
+pub fn serve(options: ServeOptions) -> bool {
+    // The first sample is taken before listening, so no client sees an empty table or 0% CPU
+    let mut system = System::new();
+    system.refresh_cpu_usage();
+    let mut snapshotter = Snapshotter::new(options.interval);
+    let processes = snapshotter.refresh();
+    let system_info = system::get_system_metrics(&mut system);
+    let state = Arc::new(ServerState {
+        processes: RwLock::new(processes),
+        system: RwLock::new(system_info),
+        token: options.token,
+        read_only: options.read_only,
+        metrics: options.metrics,
+    });
+    start_refresher(state.clone(), snapshotter, system, options.interval);
+
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Failed to start the server runtime: {}", e);
+            return false;
+        }
+    };
+
+    runtime.block_on(async {
+        let listener = match tokio::net::TcpListener::bind(options.listen).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Failed to listen on {}: {}", options.listen, e);
+                return false;
+            }
+        };
+        println!(
+            "Serving on http://{}{}{}",
+            options.listen,
+            if state.token.is_some() { " (token required)" } else { "" },
+            if state.read_only { " (read-only)" } else { "" }
+        );
+
+        let shutdown = async {
+            let _ = tokio::signal::ctrl_c().await;
+        };
+        match axum::serve(listener, router(state)).with_graceful_shutdown(shutdown).await {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("Server error: {}", e);
+                false
+            }
+        }
+    })
+}
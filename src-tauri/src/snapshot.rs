@@ -0,0 +1,380 @@
+// Process enumeration shared by the CLI, the TUI and the GUIs so they all report the same numbers
+use std::collections::HashMap;
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+#[cfg(target_os = "linux")]
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use sysinfo::ProcessStatus;
+#[cfg(not(target_os = "linux"))]
+use sysinfo::System;
+
+#[cfg(target_os = "linux")]
+use crate::net;
+use crate::procfs::ProcFs;
+
+/// Default window used to measure CPU usage
+pub const DEFAULT_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ProcessState {
+    Running,
+    Sleeping,
+    DiskSleep,
+    Stopped,
+    Tracing,
+    Zombie,
+    Dead,
+    Idle,
+    Unknown,
+}
+
+impl From<ProcessStatus> for ProcessState {
+    fn from(status: ProcessStatus) -> Self {
+        match status {
+            ProcessStatus::Run | ProcessStatus::Waking => ProcessState::Running,
+            ProcessStatus::Sleep | ProcessStatus::Parked | ProcessStatus::Wakekill => ProcessState::Sleeping,
+            ProcessStatus::UninterruptibleDiskSleep | ProcessStatus::LockBlocked => ProcessState::DiskSleep,
+            ProcessStatus::Stop => ProcessState::Stopped,
+            ProcessStatus::Tracing => ProcessState::Tracing,
+            ProcessStatus::Zombie => ProcessState::Zombie,
+            ProcessStatus::Dead => ProcessState::Dead,
+            ProcessStatus::Idle => ProcessState::Idle,
+            _ => ProcessState::Unknown,
+        }
+    }
+}
+
+impl ProcessState {
+    /// Maps the state letter of /proc/[pid]/stat
+    pub fn from_code(code: char) -> Self {
+        match code {
+            'R' => ProcessState::Running,
+            'S' => ProcessState::Sleeping,
+            'D' => ProcessState::DiskSleep,
+            'T' => ProcessState::Stopped,
+            't' => ProcessState::Tracing,
+            'Z' => ProcessState::Zombie,
+            'X' | 'x' => ProcessState::Dead,
+            'I' => ProcessState::Idle,
+            _ => ProcessState::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for ProcessState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ProcessState::Running => "Running",
+            ProcessState::Sleeping => "Sleeping",
+            ProcessState::DiskSleep => "Uninterruptible Sleep",
+            ProcessState::Stopped => "Stopped",
+            ProcessState::Tracing => "Tracing",
+            ProcessState::Zombie => "Zombie",
+            ProcessState::Dead => "Dead",
+            ProcessState::Idle => "Idle",
+            ProcessState::Unknown => "Unknown",
+        };
+        f.write_str(name)
+    }
+}
+
+/// One process as seen at the time of a refresh
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProcessSnapshot {
+    pub pid: u32,
+    pub ppid: Option<u32>,
+    pub uid: Option<u32>,
+    pub user: Option<String>,
+    /// CPU usage in percent of one core over the sampling interval
+    pub cpu: f32,
+    /// Resident memory (RSS) in MB
+    pub mem: f32,
+    /// Resident memory as a percentage of physical memory
+    pub mem_percent: f32,
+    /// Virtual memory size in MB
+    pub vsz: f32,
+    /// Resident memory backed by files or shared memory, which other processes may map too, in MB
+    pub shared: Option<f32>,
+    /// Swapped out memory in MB
+    pub swap: Option<f32>,
+    /// Proportional and unique set size in MB, from /proc/[pid]/smaps_rollup. `None` unless the
+    /// `Snapshotter` reads it (`set_proportional_memory`) and the process's memory is readable.
+    pub pss: Option<f32>,
+    pub uss: Option<f32>,
+    /// Process name
+    pub cmd: String,
+    /// Full command line, arguments joined by spaces
+    pub cmdline: String,
+    /// Path of the executable and the working directory; `None` when not readable, e.g. for
+    /// another user's process or a kernel thread
+    pub exe: Option<String>,
+    pub cwd: Option<String>,
+    /// Seconds since the Unix epoch
+    pub start_time: u64,
+    pub state: ProcessState,
+    pub nice: i32,
+    pub threads: u32,
+    /// Bytes read from and written to storage; `None` when /proc/[pid]/io is not readable
+    pub read_bytes: Option<u64>,
+    pub write_bytes: Option<u64>,
+    /// Local ports of listening TCP and UDP sockets, sorted. Only filled in by a `Snapshotter`
+    /// with `set_listening_ports(true)`, since finding them means reading every process's descriptors.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<u16>,
+}
+
+/// Resolves uids to user names, remembering every lookup
+#[derive(Default)]
+pub struct UserCache {
+    names: HashMap<u32, String>,
+}
+
+impl UserCache {
+    pub fn new() -> Self {
+        UserCache::default()
+    }
+
+    pub fn name(&mut self, uid: u32) -> String {
+        self.names
+            .entry(uid)
+            .or_insert_with(|| match users::get_user_by_uid(uid) {
+                Some(user) => user.name().to_string_lossy().into_owned(),
+                None => format!("uid:{}", uid),
+            })
+            .clone()
+    }
+}
+
+/// Reads the nice value from /proc/[pid]/stat
+pub fn read_nice(pid: u32) -> Option<i32> {
+    ProcFs::new().read_stat(pid).map(|stat| stat.nice)
+}
+
+/// CPU ticks per process, keyed by pid and start time so a reused pid is not mistaken for the old process
+#[cfg(target_os = "linux")]
+type CpuTicks = HashMap<(u32, u64), u64>;
+
+/// Keeps the state needed to compute CPU usage between refreshes
+pub struct Snapshotter {
+    users: UserCache,
+    interval: Duration,
+    /// Whether to look up `ProcessSnapshot::ports`
+    listening_ports: bool,
+    /// Whether to read `ProcessSnapshot::pss` and `uss`
+    proportional_memory: bool,
+    #[cfg(target_os = "linux")]
+    procfs: ProcFs,
+    /// CPU ticks per process at the previous refresh
+    #[cfg(target_os = "linux")]
+    previous: Option<(Instant, CpuTicks)>,
+    #[cfg(not(target_os = "linux"))]
+    system: System,
+    #[cfg(not(target_os = "linux"))]
+    primed: bool,
+}
+
+impl Snapshotter {
+    /// `interval` is the CPU sampling window used for the first refresh.
+    /// Later refreshes measure CPU usage since the previous one.
+    pub fn new(interval: Duration) -> Self {
+        Snapshotter {
+            users: UserCache::new(),
+            interval,
+            listening_ports: false,
+            proportional_memory: false,
+            #[cfg(target_os = "linux")]
+            procfs: ProcFs::new(),
+            #[cfg(target_os = "linux")]
+            previous: None,
+            #[cfg(not(target_os = "linux"))]
+            system: System::new_all(),
+            #[cfg(not(target_os = "linux"))]
+            primed: false,
+        }
+    }
+
+    /// Reads processes from another /proc tree, e.g. a synthetic one in benchmarks
+    #[cfg(target_os = "linux")]
+    pub fn with_procfs(procfs: ProcFs, interval: Duration) -> Self {
+        Snapshotter { procfs, ..Snapshotter::new(interval) }
+    }
+
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Also fill in the listening ports of each process on later refreshes. Only processes whose
+    /// descriptors can be read have any, and other platforms never do.
+    pub fn set_listening_ports(&mut self, enabled: bool) {
+        self.listening_ports = enabled;
+    }
+
+    /// Also fill in PSS and USS on later refreshes. The kernel walks every mapping of every
+    /// process to produce them, so this costs more than the rest of a refresh. Linux only.
+    pub fn set_proportional_memory(&mut self, enabled: bool) {
+        self.proportional_memory = enabled;
+    }
+
+    pub fn users(&mut self) -> &mut UserCache {
+        &mut self.users
+    }
+
+    #[cfg(target_os = "linux")]
+    fn cpu_ticks(&self) -> CpuTicks {
+        self.procfs
+            .pids()
+            .into_iter()
+            .filter_map(|pid| self.procfs.read_stat(pid))
+            .map(|stat| ((stat.pid, stat.starttime), stat.utime + stat.stime))
+            .collect()
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn refresh(&mut self) -> Vec<ProcessSnapshot> {
+        if self.previous.is_none() {
+            self.previous = Some((Instant::now(), self.cpu_ticks()));
+            thread::sleep(self.interval);
+        }
+
+        let now = Instant::now();
+        let boot_time = self.procfs.boot_time().unwrap_or(0);
+        let clock_ticks = self.procfs.clock_ticks();
+        let page_size = self.procfs.page_size();
+        let mem_total = self.procfs.mem_total_kb().unwrap_or(0) * 1024;
+        let (last_refresh, previous_ticks) = self.previous.take().unwrap_or_else(|| (now, HashMap::new()));
+        let elapsed = now.duration_since(last_refresh).as_secs_f64();
+
+        let mut ports = if self.listening_ports { net::listening_ports(&self.procfs) } else { HashMap::new() };
+
+        let mut ticks = HashMap::with_capacity(previous_ticks.len());
+        let mut processes = Vec::with_capacity(previous_ticks.len());
+        for pid in self.procfs.pids() {
+            let Some(sample) = self.procfs.sample(pid) else { continue };
+            let stat = &sample.stat;
+            let key = (stat.pid, stat.starttime);
+            let total = stat.utime + stat.stime;
+            ticks.insert(key, total);
+
+            // A process started since the last refresh is measured from zero
+            let used = total.saturating_sub(previous_ticks.get(&key).copied().unwrap_or(0));
+            let cpu = if elapsed > 0.0 {
+                (used as f64 / clock_ticks as f64 / elapsed * 100.0) as f32
+            } else {
+                0.0
+            };
+
+            let status = sample.status.as_ref();
+            let uid = status.and_then(|s| s.ruid);
+            let rss_bytes = match (&sample.statm, status.and_then(|s| s.vm_rss_kb)) {
+                (Some(statm), _) => statm.resident * page_size,
+                (None, Some(kb)) => kb * 1024,
+                (None, None) => 0,
+            };
+            let vsz_bytes = match (&sample.statm, status.and_then(|s| s.vm_size_kb)) {
+                (Some(statm), _) => statm.size * page_size,
+                (None, Some(kb)) => kb * 1024,
+                (None, None) => 0,
+            };
+            let rollup = if self.proportional_memory { self.procfs.read_smaps_rollup(pid) } else { None };
+
+            processes.push(ProcessSnapshot {
+                pid: stat.pid,
+                ppid: (stat.ppid != 0).then_some(stat.ppid),
+                uid,
+                user: Some(uid.map_or_else(|| "unknown".to_string(), |uid| self.users.name(uid))),
+                cpu,
+                mem: rss_bytes as f32 / 1024.0 / 1024.0,
+                mem_percent: percent_of(rss_bytes, mem_total),
+                vsz: vsz_bytes as f32 / 1024.0 / 1024.0,
+                shared: sample.statm.as_ref().map(|statm| (statm.shared * page_size) as f32 / 1024.0 / 1024.0),
+                swap: status.and_then(|s| s.vm_swap_kb).map(|kb| kb as f32 / 1024.0),
+                pss: rollup.as_ref().map(|r| r.pss_kb as f32 / 1024.0),
+                uss: rollup.as_ref().map(|r| r.uss_kb() as f32 / 1024.0),
+                cmd: stat.comm.clone(),
+                cmdline: sample.cmdline.join(" "),
+                exe: self.procfs.read_exe(pid).map(|path| path.to_string_lossy().into_owned()),
+                cwd: self.procfs.read_cwd(pid).map(|path| path.to_string_lossy().into_owned()),
+                start_time: boot_time + stat.starttime / clock_ticks,
+                state: ProcessState::from_code(stat.state),
+                nice: stat.nice,
+                threads: stat.num_threads,
+                read_bytes: sample.io.as_ref().map(|io| io.read_bytes),
+                write_bytes: sample.io.as_ref().map(|io| io.write_bytes),
+                ports: ports.remove(&pid).unwrap_or_default(),
+            });
+        }
+
+        self.previous = Some((now, ticks));
+        processes
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn refresh(&mut self) -> Vec<ProcessSnapshot> {
+        if !self.primed {
+            self.system.refresh_all();
+            thread::sleep(self.interval.max(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL));
+            self.primed = true;
+        }
+        self.system.refresh_all();
+
+        let users = &mut self.users;
+        let mem_total = self.system.total_memory();
+        self.system
+            .processes()
+            .iter()
+            .map(|(pid, process)| {
+                let uid = process.user_id().map(|uid| **uid);
+                ProcessSnapshot {
+                    pid: pid.as_u32(),
+                    ppid: process.parent().map(|p| p.as_u32()),
+                    uid,
+                    user: Some(uid.map_or_else(|| "unknown".to_string(), |uid| users.name(uid))),
+                    cpu: process.cpu_usage(),
+                    mem: process.memory() as f32 / 1024.0 / 1024.0,
+                    mem_percent: percent_of(process.memory(), mem_total),
+                    vsz: process.virtual_memory() as f32 / 1024.0 / 1024.0,
+                    shared: None,
+                    swap: None,
+                    pss: None,
+                    uss: None,
+                    cmd: process.name().to_string_lossy().into_owned(),
+                    cmdline: process
+                        .cmd()
+                        .iter()
+                        .map(|arg| arg.to_string_lossy().into_owned())
+                        .collect::<Vec<String>>()
+                        .join(" "),
+                    exe: process.exe().map(|path| path.to_string_lossy().into_owned()),
+                    cwd: process.cwd().map(|path| path.to_string_lossy().into_owned()),
+                    start_time: process.start_time(),
+                    state: process.status().into(),
+                    nice: 0,
+                    threads: process.tasks().map_or(1, |tasks| tasks.len() as u32),
+                    read_bytes: Some(process.disk_usage().total_read_bytes),
+                    write_bytes: Some(process.disk_usage().total_written_bytes),
+                    ports: Vec::new(),
+                }
+            })
+            .collect()
+    }
+}
+
+fn percent_of(bytes: u64, total: u64) -> f32 {
+    if total == 0 {
+        0.0
+    } else {
+        (bytes as f64 / total as f64 * 100.0) as f32
+    }
+}
+
+/// Takes a single snapshot, sampling CPU usage over `interval`
+pub fn take_snapshot(interval: Duration) -> Vec<ProcessSnapshot> {
+    Snapshotter::new(interval).refresh()
+}
@@ -0,0 +1,118 @@
+// Background task pushing process snapshots and system metrics to the webview
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde::Serialize;
+use sysinfo::System;
+use tauri::{AppHandle, Emitter, Manager, State, WebviewWindow};
+
+use crate::delta::{self, SnapshotDelta};
+use crate::snapshot::{self, ProcessSnapshot, Snapshotter};
+use crate::system;
+
+pub const PROCESS_SNAPSHOT_EVENT: &str = "process-snapshot";
+pub const SYSTEM_METRICS_EVENT: &str = "system-metrics";
+
+const DEFAULT_INTERVAL_MS: u64 = 2000;
+const MIN_INTERVAL_MS: u64 = 100;
+
+/// What one window asked for; windows that never called `configure_stream` get full lists
+#[derive(Default)]
+struct Subscriber {
+    deltas: bool,
+    /// Send the full list on the next tick, e.g. after switching to deltas
+    resync: bool,
+}
+
+pub struct StreamSettings {
+    interval: Duration,
+    /// By window label
+    subscribers: HashMap<String, Subscriber>,
+    /// Set by `configure_stream` to end the current wait early
+    changed: bool,
+}
+
+pub struct StreamState {
+    settings: Mutex<StreamSettings>,
+    changed: Condvar,
+}
+
+impl Default for StreamState {
+    fn default() -> Self {
+        StreamState {
+            settings: Mutex::new(StreamSettings {
+                interval: Duration::from_millis(DEFAULT_INTERVAL_MS),
+                subscribers: HashMap::new(),
+                changed: false,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum SnapshotEvent {
+    Full { processes: Vec<ProcessSnapshot> },
+    Delta(SnapshotDelta),
+}
+
+/// Changes the stream interval, shared by every window, and whether the calling window's
+/// `process-snapshot` events carry deltas or full lists. The window gets a full list right
+/// away, as a baseline for deltas, and the new interval applies from then on.
+#[tauri::command]
+pub fn configure_stream(window: WebviewWindow, state: State<'_, StreamState>, interval_ms: Option<u64>, deltas: Option<bool>) {
+    let mut settings = state.settings.lock().unwrap();
+    if let Some(interval_ms) = interval_ms {
+        settings.interval = Duration::from_millis(interval_ms.max(MIN_INTERVAL_MS));
+    }
+    let subscriber = settings.subscribers.entry(window.label().to_string()).or_default();
+    if let Some(deltas) = deltas {
+        subscriber.deltas = deltas;
+    }
+    subscriber.resync = true;
+    settings.changed = true;
+    state.changed.notify_all();
+}
+
+pub fn start(app: AppHandle) {
+    thread::spawn(move || {
+        let mut snapshotter = Snapshotter::new(snapshot::DEFAULT_INTERVAL);
+        let mut system = System::new();
+        let mut previous: Vec<ProcessSnapshot> = Vec::new();
+
+        loop {
+            let processes = snapshotter.refresh();
+            let metrics = system::get_system_metrics(&mut system);
+            let state = app.state::<StreamState>();
+
+            {
+                let mut settings = state.settings.lock().unwrap();
+                let windows = app.webview_windows();
+                settings.subscribers.retain(|label, _| windows.contains_key(label));
+                // Computed once, and only if some window wants it
+                let mut changes = None;
+                for label in windows.keys() {
+                    let subscriber = settings.subscribers.entry(label.clone()).or_default();
+                    let event = if subscriber.deltas && !subscriber.resync {
+                        SnapshotEvent::Delta(changes.get_or_insert_with(|| delta::diff(&previous, &processes)).clone())
+                    } else {
+                        SnapshotEvent::Full { processes: processes.clone() }
+                    };
+                    subscriber.resync = false;
+                    let _ = app.emit_to(label.as_str(), PROCESS_SNAPSHOT_EVENT, event);
+                }
+            }
+            let _ = app.emit(SYSTEM_METRICS_EVENT, metrics);
+            previous = processes;
+
+            // Sleeps for the interval, or until `configure_stream` changes it
+            let settings = state.settings.lock().unwrap();
+            let interval = settings.interval;
+            let (mut settings, _) = state.changed.wait_timeout_while(settings, interval, |settings| !settings.changed).unwrap();
+            settings.changed = false;
+        }
+    });
+}
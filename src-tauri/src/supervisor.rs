@@ -0,0 +1,608 @@
+// Keeps the programs listed in a TOML file running, restarting them according to their policy
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::Local;
+use nix::errno::Errno;
+use nix::sys::signal::Signal;
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
+use serde::{Deserialize, Serialize};
+
+use crate::actions;
+use crate::config;
+use crate::interrupt;
+
+const TICK: Duration = Duration::from_millis(100);
+/// Upper bound of every `*_secs` setting, a day
+const MAX_SECS: f64 = 86_400.0;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    Always,
+    #[default]
+    OnFailure,
+    Never,
+}
+
+fn default_backoff() -> f64 {
+    1.0
+}
+
+fn default_max_backoff() -> f64 {
+    60.0
+}
+
+fn default_min_uptime() -> f64 {
+    10.0
+}
+
+fn default_stop_timeout() -> f64 {
+    10.0
+}
+
+fn default_autostart() -> bool {
+    true
+}
+
+/// One `[[program]]` table of the supervisor file
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProgramConfig {
+    pub name: String,
+    /// Program and arguments, passed as-is without a shell
+    pub argv: Vec<String>,
+    pub cwd: Option<PathBuf>,
+    /// Added to (or, with `clear_env`, replacing) the supervisor's environment
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub clear_env: bool,
+    /// Run as this user; needs the supervisor to run as root
+    pub user: Option<String>,
+    #[serde(default)]
+    pub restart: RestartPolicy,
+    /// Delay before the first restart, doubled for every consecutive failure
+    #[serde(default = "default_backoff")]
+    pub backoff_secs: f64,
+    #[serde(default = "default_max_backoff")]
+    pub max_backoff_secs: f64,
+    /// Consecutive restarts before giving up; unlimited when omitted
+    pub max_retries: Option<u32>,
+    /// A run lasting at least this long resets the retry count
+    #[serde(default = "default_min_uptime")]
+    pub min_uptime_secs: f64,
+    /// Time between SIGTERM and SIGKILL when stopping
+    #[serde(default = "default_stop_timeout")]
+    pub stop_timeout_secs: f64,
+    /// Log files, appended to; inherited from the supervisor when omitted
+    pub stdout: Option<PathBuf>,
+    pub stderr: Option<PathBuf>,
+    #[serde(default = "default_autostart")]
+    pub autostart: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SupervisorConfig {
+    /// Control socket used by `supervise status/start/stop/restart`
+    pub socket: Option<PathBuf>,
+    #[serde(default, rename = "program")]
+    pub programs: Vec<ProgramConfig>,
+}
+
+/// `$XDG_CONFIG_HOME/procmanager/supervisor.toml`, falling back to `~/.config`
+pub fn default_config_path() -> PathBuf {
+    config::config_home().join("procmanager").join("supervisor.toml")
+}
+
+impl SupervisorConfig {
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let config: SupervisorConfig = toml::from_str(contents).map_err(|e| e.to_string())?;
+        for (i, program) in config.programs.iter().enumerate() {
+            if program.argv.is_empty() {
+                return Err(format!("program '{}' has an empty argv", program.name));
+            }
+            if config.programs[..i].iter().any(|p| p.name == program.name) {
+                return Err(format!("program '{}' is listed twice", program.name));
+            }
+            if let Some(user) = &program.user {
+                // Caught here rather than on every restart attempt
+                if users::get_user_by_name(user).is_none() {
+                    return Err(format!("program '{}': unknown user {}", program.name, user));
+                }
+            }
+            let durations = [
+                ("backoff_secs", program.backoff_secs),
+                ("max_backoff_secs", program.max_backoff_secs),
+                ("min_uptime_secs", program.min_uptime_secs),
+                ("stop_timeout_secs", program.stop_timeout_secs),
+            ];
+            for (key, secs) in durations {
+                // Duration::from_secs_f64 panics on anything negative, NaN or infinite
+                if !(0.0..=MAX_SECS).contains(&secs) {
+                    return Err(format!("program '{}': {} must be between 0 and {}", program.name, key, MAX_SECS));
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        SupervisorConfig::parse(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn socket_path(&self) -> PathBuf {
+        if let Some(socket) = &self.socket {
+            return socket.clone();
+        }
+        match std::env::var_os("XDG_RUNTIME_DIR") {
+            Some(dir) => PathBuf::from(dir).join("procmanager-supervisor.sock"),
+            None => std::env::temp_dir().join(format!("procmanager-supervisor-{}.sock", nix::unistd::getuid())),
+        }
+    }
+}
+
+enum ProgramState {
+    Stopped,
+    Running { pid: u32, started: Instant },
+    /// SIGTERM sent; SIGKILL follows at `deadline`
+    Stopping { pid: u32, deadline: Instant, restart: bool },
+    Backoff { until: Instant },
+    /// Exited and not restarted because of the policy
+    Exited,
+    /// Gave up restarting, or could not be started
+    Fatal(String),
+}
+
+/// What `status` reports for one program
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProgramStatus {
+    pub name: String,
+    pub state: String,
+    pub pid: Option<u32>,
+    /// Restarts since the supervisor started
+    pub restarts: u32,
+    pub uptime_secs: Option<u64>,
+    /// e.g. "exit code 1" or "killed by SIGSEGV"
+    pub last_exit: Option<String>,
+    pub message: Option<String>,
+}
+
+struct Program {
+    config: ProgramConfig,
+    state: ProgramState,
+    /// Consecutive failed runs, reset after a run of `min_uptime_secs`
+    retries: u32,
+    restarts: u32,
+    last_exit: Option<String>,
+}
+
+impl Program {
+    fn status(&self) -> ProgramStatus {
+        let (state, pid, uptime, message) = match &self.state {
+            ProgramState::Stopped => ("stopped", None, None, None),
+            ProgramState::Running { pid, started } => ("running", Some(*pid), Some(started.elapsed().as_secs()), None),
+            ProgramState::Stopping { pid, .. } => ("stopping", Some(*pid), None, None),
+            ProgramState::Backoff { until } => (
+                "backoff",
+                None,
+                None,
+                Some(format!("restarting in {:.1}s", until.saturating_duration_since(Instant::now()).as_secs_f64())),
+            ),
+            ProgramState::Exited => ("exited", None, None, None),
+            ProgramState::Fatal(reason) => ("fatal", None, None, Some(reason.clone())),
+        };
+        ProgramStatus {
+            name: self.config.name.clone(),
+            state: state.to_string(),
+            pid,
+            restarts: self.restarts,
+            uptime_secs: uptime,
+            last_exit: self.last_exit.clone(),
+            message,
+        }
+    }
+
+    fn is_alive(&self) -> bool {
+        matches!(self.state, ProgramState::Running { .. } | ProgramState::Stopping { .. })
+    }
+
+    fn spawn(&self) -> io::Result<u32> {
+        let config = &self.config;
+        let mut command = Command::new(&config.argv[0]);
+        command.args(&config.argv[1..]);
+        if config.clear_env {
+            command.env_clear();
+        }
+        command.envs(&config.env);
+        if let Some(cwd) = &config.cwd {
+            command.current_dir(cwd);
+        }
+        if let Some(path) = &config.stdout {
+            command.stdout(Stdio::from(OpenOptions::new().create(true).append(true).open(path)?));
+        }
+        if let Some(path) = &config.stderr {
+            command.stderr(Stdio::from(OpenOptions::new().create(true).append(true).open(path)?));
+        }
+        if let Some(name) = &config.user {
+            let user = users::get_user_by_name(name)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("unknown user {}", name)))?;
+            command.uid(user.uid()).gid(user.primary_group_id());
+        }
+        // Own process group, so Ctrl-C in the supervisor's terminal does not reach the children
+        command.process_group(0);
+
+        // The child is reaped with waitpid in `reap`, not through `Child`
+        Ok(command.spawn()?.id())
+    }
+
+    fn start(&mut self) {
+        match self.spawn() {
+            Ok(pid) => {
+                log(&format!("{} started with PID {}", self.config.name, pid));
+                self.state = ProgramState::Running { pid, started: Instant::now() };
+            }
+            Err(e) => {
+                log(&format!("{} failed to start: {}", self.config.name, e));
+                self.last_exit = Some(format!("failed to start: {}", e));
+                self.schedule_restart(false);
+            }
+        }
+    }
+
+    fn stop(&mut self, restart: bool) {
+        match self.state {
+            ProgramState::Running { pid, .. } => {
+                let _ = actions::signal(pid, Signal::SIGTERM);
+                let deadline = Instant::now() + Duration::from_secs_f64(self.config.stop_timeout_secs);
+                self.state = ProgramState::Stopping { pid, deadline, restart };
+            }
+            ProgramState::Stopping { pid, deadline, .. } => {
+                self.state = ProgramState::Stopping { pid, deadline, restart };
+            }
+            _ if restart => self.start(),
+            _ => self.state = ProgramState::Stopped,
+        }
+    }
+
+    /// Applies the restart policy after a run ended on its own
+    fn schedule_restart(&mut self, success: bool) {
+        let config = &self.config;
+        let wanted = match config.restart {
+            RestartPolicy::Always => true,
+            RestartPolicy::OnFailure => !success,
+            RestartPolicy::Never => false,
+        };
+        if !wanted {
+            self.state = ProgramState::Exited;
+            return;
+        }
+        if config.max_retries.is_some_and(|max| self.retries >= max) {
+            log(&format!("{} gave up after {} retries", config.name, self.retries));
+            self.state = ProgramState::Fatal(format!("gave up after {} retries", self.retries));
+            return;
+        }
+
+        let delay = (config.backoff_secs * 2f64.powi(self.retries.min(30) as i32)).min(config.max_backoff_secs);
+        self.retries += 1;
+        log(&format!("{} restarting in {:.1}s", config.name, delay));
+        self.state = ProgramState::Backoff { until: Instant::now() + Duration::from_secs_f64(delay.max(0.0)) };
+    }
+
+    fn exited(&mut self, description: String, success: bool) {
+        log(&format!("{} {}", self.config.name, description));
+        self.last_exit = Some(description);
+        match self.state {
+            ProgramState::Stopping { restart: true, .. } => {
+                self.retries = 0;
+                self.restarts += 1;
+                self.start();
+            }
+            ProgramState::Stopping { .. } => self.state = ProgramState::Stopped,
+            ProgramState::Running { started, .. } => {
+                if started.elapsed().as_secs_f64() >= self.config.min_uptime_secs {
+                    self.retries = 0;
+                }
+                self.schedule_restart(success);
+            }
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self) {
+        match self.state {
+            ProgramState::Backoff { until } if Instant::now() >= until => {
+                self.restarts += 1;
+                self.start();
+            }
+            ProgramState::Stopping { pid, deadline, .. } if Instant::now() >= deadline => {
+                let _ = actions::kill(pid);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn log(message: &str) {
+    println!("[{}] {}", Local::now().format("%Y-%m-%d %H:%M:%S"), message);
+}
+
+fn describe_exit(status: WaitStatus) -> Option<(u32, String, bool)> {
+    match status {
+        WaitStatus::Exited(pid, code) => Some((pid.as_raw() as u32, format!("exited with code {}", code), code == 0)),
+        WaitStatus::Signaled(pid, signal, _) => Some((pid.as_raw() as u32, format!("killed by {}", signal.as_str()), false)),
+        _ => None,
+    }
+}
+
+pub struct Supervisor {
+    programs: Vec<Program>,
+}
+
+impl Supervisor {
+    pub fn new(config: SupervisorConfig) -> Self {
+        let programs = config
+            .programs
+            .into_iter()
+            .map(|config| Program { config, state: ProgramState::Stopped, retries: 0, restarts: 0, last_exit: None })
+            .collect();
+        Supervisor { programs }
+    }
+
+    fn select(&mut self, name: Option<&str>) -> Result<Vec<&mut Program>, String> {
+        let selected: Vec<&mut Program> = self
+            .programs
+            .iter_mut()
+            .filter(|p| name.is_none_or(|name| p.config.name == name))
+            .collect();
+        match (name, selected.is_empty()) {
+            (Some(name), true) => Err(format!("no program named '{}'", name)),
+            _ => Ok(selected),
+        }
+    }
+
+    pub fn status(&self) -> Vec<ProgramStatus> {
+        self.programs.iter().map(Program::status).collect()
+    }
+
+    /// Starts every program with `autostart`
+    pub fn start_autostart(&mut self) {
+        for program in self.programs.iter_mut().filter(|p| p.config.autostart) {
+            program.start();
+        }
+    }
+
+    pub fn start(&mut self, name: Option<&str>) -> Result<(), String> {
+        for program in self.select(name)? {
+            if !program.is_alive() {
+                program.retries = 0;
+                program.start();
+            }
+        }
+        Ok(())
+    }
+
+    pub fn stop(&mut self, name: Option<&str>) -> Result<(), String> {
+        for program in self.select(name)? {
+            program.stop(false);
+        }
+        Ok(())
+    }
+
+    pub fn restart(&mut self, name: Option<&str>) -> Result<(), String> {
+        for program in self.select(name)? {
+            program.stop(true);
+        }
+        Ok(())
+    }
+
+    /// Reaps exited children and advances backoff and stop timers
+    pub fn tick(&mut self) {
+        loop {
+            match waitpid(Pid::from_raw(-1), Some(WaitPidFlag::WNOHANG)) {
+                Ok(WaitStatus::StillAlive) | Err(Errno::ECHILD) => break,
+                Ok(status) => {
+                    let Some((pid, description, success)) = describe_exit(status) else { continue };
+                    let program = self.programs.iter_mut().find(|p| match p.state {
+                        ProgramState::Running { pid: running, .. } | ProgramState::Stopping { pid: running, .. } => running == pid,
+                        _ => false,
+                    });
+                    if let Some(program) = program {
+                        program.exited(description, success);
+                    }
+                }
+                Err(Errno::EINTR) => continue,
+                Err(_) => break,
+            }
+        }
+        for program in &mut self.programs {
+            program.tick();
+        }
+    }
+
+    /// Stops every program and waits for them to exit
+    pub fn shutdown(&mut self) {
+        let _ = self.stop(None);
+        while self.programs.iter().any(Program::is_alive) {
+            self.tick();
+            thread::sleep(TICK);
+        }
+    }
+
+    fn handle(&mut self, request: &ControlRequest) -> ControlResponse {
+        let name = request.name.as_deref();
+        let result = match request.command {
+            ControlCommand::Status => self.select(name).map(drop),
+            ControlCommand::Start => self.start(name),
+            ControlCommand::Stop => self.stop(name),
+            ControlCommand::Restart => self.restart(name),
+        };
+        match result {
+            Ok(()) => {
+                let programs = self.status().into_iter().filter(|p| name.is_none_or(|name| p.name == name)).collect();
+                ControlResponse { programs, error: None }
+            }
+            Err(error) => ControlResponse { programs: Vec::new(), error: Some(error) },
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ControlCommand {
+    Status,
+    Start,
+    Stop,
+    Restart,
+}
+
+/// One line of JSON sent to the control socket
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ControlRequest {
+    pub command: ControlCommand,
+    /// Every program when `None`
+    pub name: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ControlResponse {
+    pub programs: Vec<ProgramStatus>,
+    pub error: Option<String>,
+}
+
+fn serve_client(supervisor: &mut Supervisor, stream: UnixStream) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+
+    let response = match serde_json::from_str::<ControlRequest>(&line) {
+        Ok(request) => supervisor.handle(&request),
+        Err(e) => ControlResponse { programs: Vec::new(), error: Some(format!("bad request: {}", e)) },
+    };
+    let mut stream = &stream;
+    serde_json::to_writer(&mut stream, &response)?;
+    stream.write_all(b"\n")
+}
+
+/// Runs the supervisor in the foreground until SIGINT or SIGTERM, then stops every program
+pub fn run(config: SupervisorConfig) -> Result<(), String> {
+    let socket = config.socket_path();
+    if UnixStream::connect(&socket).is_ok() {
+        return Err(format!("a supervisor is already listening on {}", socket.display()));
+    }
+    // Left behind by a supervisor that did not shut down cleanly
+    let _ = fs::remove_file(&socket);
+    let listener = UnixListener::bind(&socket).map_err(|e| format!("{}: {}", socket.display(), e))?;
+    listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+
+    interrupt::install().map_err(|e| e.to_string())?;
+
+    log(&format!("supervising {} programs, control socket {}", config.programs.len(), socket.display()));
+    let mut supervisor = Supervisor::new(config);
+    supervisor.start_autostart();
+
+    while !interrupt::requested() {
+        supervisor.tick();
+        match listener.accept() {
+            Ok((stream, _)) => {
+                if let Err(e) = serve_client(&mut supervisor, stream) {
+                    log(&format!("control request failed: {}", e));
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(TICK),
+            Err(e) => log(&format!("control socket error: {}", e)),
+        }
+    }
+
+    log("shutting down");
+    supervisor.shutdown();
+    let _ = fs::remove_file(&socket);
+    interrupt::uninstall();
+    Ok(())
+}
+
+/// Sends one command to a running supervisor
+pub fn send(socket: &Path, request: &ControlRequest) -> Result<Vec<ProgramStatus>, String> {
+    let stream = UnixStream::connect(socket)
+        .map_err(|e| format!("no supervisor running on {} ({})", socket.display(), e))?;
+    let mut writer = &stream;
+    serde_json::to_writer(&mut writer, request).map_err(|e| e.to_string())?;
+    writer.write_all(b"\n").map_err(|e| e.to_string())?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line).map_err(|e| e.to_string())?;
+    let response: ControlResponse = serde_json::from_str(&line).map_err(|e| e.to_string())?;
+    match response.error {
+        Some(error) => Err(error),
+        None => Ok(response.programs),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(extra: &str) -> String {
+        format!("[[program]]\nname = \"web\"\nargv = [\"python3\", \"-m\", \"http.server\"]\n{}\n", extra)
+    }
+
+    /// The one program of `program(extra)`
+    fn parsed(extra: &str) -> ProgramConfig {
+        SupervisorConfig::parse(&program(extra)).unwrap().programs.remove(0)
+    }
+
+    fn error(contents: &str) -> String {
+        SupervisorConfig::parse(contents).unwrap_err()
+    }
+
+    #[test]
+    fn fills_in_defaults() {
+        let web = parsed("");
+        assert_eq!(web.argv, ["python3", "-m", "http.server"]);
+        assert_eq!(web.restart, RestartPolicy::OnFailure);
+        assert_eq!((web.backoff_secs, web.max_backoff_secs), (1.0, 60.0));
+        assert!(web.autostart);
+        assert!(web.user.is_none());
+        assert!(SupervisorConfig::parse("").unwrap().programs.is_empty());
+    }
+
+    #[test]
+    fn rejects_an_empty_argv() {
+        let contents = "[[program]]\nname = \"idle\"\nargv = []\n";
+        assert_eq!(error(contents), "program 'idle' has an empty argv");
+    }
+
+    #[test]
+    fn rejects_an_unknown_restart_policy() {
+        assert_eq!(parsed("restart = \"never\"").restart, RestartPolicy::Never);
+        assert!(error(&program("restart = \"sometimes\"")).contains("unknown variant `sometimes`"));
+        assert!(error(&program("restart = \"on_failure\"")).contains("unknown variant"));
+    }
+
+    #[test]
+    fn checks_the_user_exists() {
+        assert_eq!(parsed("user = \"root\"").user.as_deref(), Some("root"));
+        assert_eq!(
+            error(&program("user = \"no-such-user-procmanager\"")),
+            "program 'web': unknown user no-such-user-procmanager"
+        );
+    }
+
+    #[test]
+    fn rejects_durations_out_of_range() {
+        assert!(error(&program("backoff_secs = -1.0")).contains("backoff_secs must be between 0 and 86400"));
+        assert!(error(&program("stop_timeout_secs = 1e9")).contains("stop_timeout_secs"));
+        assert!(error(&program("min_uptime_secs = nan")).contains("min_uptime_secs"));
+    }
+}
@@ -0,0 +1,48 @@
+// System wide metrics shown next to the process table
+use serde::Serialize;
+use sysinfo::System;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SystemInfo {
+    /// Average over all cores, in percent
+    pub cpu_usage: f32,
+    /// Memory and swap in MB
+    pub total_memory: f32,
+    pub used_memory: f32,
+    pub available_memory: f32,
+    pub swap: f32,
+    pub used_swap: f32,
+    /// Seconds since boot
+    pub uptime: u64,
+    pub process_count: usize,
+    pub physical_cores: usize,
+    pub logical_cores: usize,
+}
+
+/// Refreshes `system` and reads its metrics. CPU usage is measured since the
+/// previous call, so keep the same `System` around between calls.
+pub fn get_system_metrics(system: &mut System) -> SystemInfo {
+    system.refresh_cpu_usage();
+    system.refresh_memory();
+
+    #[cfg(target_os = "linux")]
+    let process_count = crate::procfs::ProcFs::new().pids().len();
+    #[cfg(not(target_os = "linux"))]
+    let process_count = {
+        system.refresh_processes(sysinfo::ProcessesToUpdate::All, true);
+        system.processes().len()
+    };
+
+    SystemInfo {
+        cpu_usage: system.cpus().iter().map(|cpu| cpu.cpu_usage()).sum::<f32>() / system.cpus().len().max(1) as f32,
+        total_memory: system.total_memory() as f32 / 1024.0 / 1024.0,
+        used_memory: system.used_memory() as f32 / 1024.0 / 1024.0,
+        available_memory: system.available_memory() as f32 / 1024.0 / 1024.0,
+        swap: system.total_swap() as f32 / 1024.0 / 1024.0,
+        used_swap: system.used_swap() as f32 / 1024.0 / 1024.0,
+        uptime: System::uptime(),
+        process_count,
+        physical_cores: num_cpus::get_physical(),
+        logical_cores: num_cpus::get(),
+    }
+}
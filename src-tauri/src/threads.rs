@@ -0,0 +1,119 @@
+// Threads of a single process, read from /proc/[pid]/task
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::procfs::ProcFs;
+use crate::snapshot::ProcessState;
+
+/// One thread as seen at the time of a refresh
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ThreadSnapshot {
+    pub tid: u32,
+    /// The process the thread belongs to
+    pub pid: u32,
+    /// Thread name (comm), as set with pthread_setname_np; the process name unless renamed
+    pub name: String,
+    /// CPU usage in percent of one core over the sampling interval
+    pub cpu: f32,
+    pub state: ProcessState,
+    /// CPU the thread last ran on
+    pub last_cpu: u32,
+    pub nice: i32,
+    pub voluntary_ctxt_switches: Option<u64>,
+    pub nonvoluntary_ctxt_switches: Option<u64>,
+}
+
+/// CPU ticks per thread, keyed by tid and start time like the process snapshot
+type CpuTicks = HashMap<(u32, u64), u64>;
+
+/// Keeps the state needed to compute per-thread CPU usage between refreshes, as `Snapshotter` does for processes
+pub struct ThreadSampler {
+    procfs: ProcFs,
+    pid: u32,
+    interval: Duration,
+    previous: Option<(Instant, CpuTicks)>,
+}
+
+impl ThreadSampler {
+    /// `interval` is the CPU sampling window used for the first refresh
+    pub fn new(pid: u32, interval: Duration) -> Self {
+        ThreadSampler::with_procfs(ProcFs::new(), pid, interval)
+    }
+
+    pub fn with_procfs(procfs: ProcFs, pid: u32, interval: Duration) -> Self {
+        ThreadSampler { procfs, pid, interval, previous: None }
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    fn cpu_ticks(&self) -> CpuTicks {
+        self.procfs
+            .tids(self.pid)
+            .into_iter()
+            .filter_map(|tid| self.procfs.read_task_stat(self.pid, tid))
+            .map(|stat| ((stat.pid, stat.starttime), stat.utime + stat.stime))
+            .collect()
+    }
+
+    /// The threads sorted by TID; `None` once the process is gone
+    pub fn refresh(&mut self) -> Option<Vec<ThreadSnapshot>> {
+        if self.previous.is_none() {
+            self.previous = Some((Instant::now(), self.cpu_ticks()));
+            thread::sleep(self.interval);
+        }
+
+        let now = Instant::now();
+        let clock_ticks = self.procfs.clock_ticks();
+        let (last_refresh, previous_ticks) = self.previous.take().unwrap_or_else(|| (now, HashMap::new()));
+        let elapsed = now.duration_since(last_refresh).as_secs_f64();
+
+        let mut tids = self.procfs.tids(self.pid);
+        if tids.is_empty() {
+            return None;
+        }
+        tids.sort_unstable();
+
+        let mut ticks = HashMap::with_capacity(tids.len());
+        let mut threads = Vec::with_capacity(tids.len());
+        for tid in tids {
+            // The thread may have exited since the directory was listed
+            let Some(stat) = self.procfs.read_task_stat(self.pid, tid) else { continue };
+            let key = (stat.pid, stat.starttime);
+            let total = stat.utime + stat.stime;
+            ticks.insert(key, total);
+
+            let used = total.saturating_sub(previous_ticks.get(&key).copied().unwrap_or(0));
+            let cpu = if elapsed > 0.0 {
+                (used as f64 / clock_ticks as f64 / elapsed * 100.0) as f32
+            } else {
+                0.0
+            };
+            let status = self.procfs.read_task_status(self.pid, tid);
+
+            threads.push(ThreadSnapshot {
+                tid,
+                pid: self.pid,
+                name: stat.comm,
+                cpu,
+                state: ProcessState::from_code(stat.state),
+                last_cpu: stat.processor,
+                nice: stat.nice,
+                voluntary_ctxt_switches: status.as_ref().and_then(|s| s.voluntary_ctxt_switches),
+                nonvoluntary_ctxt_switches: status.as_ref().and_then(|s| s.nonvoluntary_ctxt_switches),
+            });
+        }
+
+        self.previous = Some((now, ticks));
+        Some(threads)
+    }
+}
+
+/// The threads of `pid`, sampling CPU usage over `interval`
+pub fn take_thread_snapshot(pid: u32, interval: Duration) -> Option<Vec<ThreadSnapshot>> {
+    ThreadSampler::new(pid, interval).refresh()
+}
@@ -0,0 +1,83 @@
+// Samples a single process's CPU and memory over time
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::actions::ActionError;
+use crate::procfs::ProcFs;
+
+/// One sample taken while tracking a process
+#[derive(Clone, Debug, Serialize)]
+pub struct TrackSample {
+    pub timestamp: String,
+    pub pid: u32,
+    /// CPU usage in percent of one core since the previous sample
+    pub cpu: f32,
+    /// Resident memory in KB
+    pub memory_kb: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TrackSummary {
+    pub pid: u32,
+    pub samples: u64,
+    pub avg_cpu: f32,
+    pub avg_memory_kb: u64,
+    /// Whether the process went away before the duration was over
+    pub exited: bool,
+}
+
+/// Samples `pid` every `interval` for `duration`, handing each sample to `on_sample`
+pub fn track<F>(pid: u32, duration: Duration, interval: Duration, mut on_sample: F) -> Result<TrackSummary, ActionError>
+where
+    F: FnMut(&TrackSample),
+{
+    let procfs = ProcFs::new();
+    let first = procfs.read_stat(pid).ok_or(ActionError::NotFound { pid })?;
+
+    let start_time = Instant::now();
+    let mut last_ticks = first.utime + first.stime;
+    let mut last_sample = start_time;
+    let mut total_cpu: f32 = 0.0;
+    let mut total_memory: u64 = 0;
+    let mut count: u64 = 0;
+    let mut exited = false;
+
+    while start_time.elapsed() < duration {
+        thread::sleep(interval);
+
+        let Some(stat) = procfs.read_stat(pid).filter(|stat| stat.starttime == first.starttime) else {
+            exited = true;
+            break;
+        };
+        let now = Instant::now();
+        let ticks = stat.utime + stat.stime;
+        let elapsed = now.duration_since(last_sample).as_secs_f64();
+        let cpu = (ticks.saturating_sub(last_ticks) as f64 / procfs.clock_ticks() as f64 / elapsed * 100.0) as f32;
+        let memory_kb = procfs
+            .read_statm(pid)
+            .map_or(0, |statm| statm.resident * procfs.page_size() / 1024);
+        last_ticks = ticks;
+        last_sample = now;
+
+        total_cpu += cpu;
+        total_memory += memory_kb;
+        count += 1;
+
+        on_sample(&TrackSample {
+            timestamp: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            pid,
+            cpu,
+            memory_kb,
+        });
+    }
+
+    Ok(TrackSummary {
+        pid,
+        samples: count,
+        avg_cpu: if count > 0 { total_cpu / count as f32 } else { 0.0 },
+        avg_memory_kb: total_memory.checked_div(count).unwrap_or(0),
+        exited,
+    })
+}
@@ -0,0 +1,85 @@
+// Parent/child hierarchy of a process list
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::snapshot::ProcessSnapshot;
+
+/// Anything that can be placed in a process tree
+pub trait TreeItem: Clone {
+    fn pid(&self) -> u32;
+    fn ppid(&self) -> Option<u32>;
+}
+
+impl TreeItem for ProcessSnapshot {
+    fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    fn ppid(&self) -> Option<u32> {
+        self.ppid
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ProcessNode<P> {
+    pub process: P,
+    pub children: Vec<ProcessNode<P>>,
+}
+
+pub fn build_process_tree<P: TreeItem>(processes: &[P]) -> Vec<ProcessNode<P>> {
+    // Group processes by their parent pid
+    let mut pid_map: HashMap<u32, Vec<P>> = HashMap::new();
+    for process in processes {
+        pid_map.entry(process.ppid().unwrap_or(0)).or_default().push(process.clone());
+    }
+
+    // Build tree starting from root processes (those without a parent)
+    let mut roots = pid_map.remove(&0).unwrap_or_default();
+    roots.sort_by_key(|process| process.pid());
+    let mut tree: Vec<ProcessNode<P>> = roots
+        .into_iter()
+        .map(|process| build_process_subtree(process, &mut pid_map))
+        .collect();
+
+    // Processes whose parent is not in the list are shown as their own roots
+    let listed: HashSet<u32> = pid_map.values().flatten().map(|process| process.pid()).collect();
+    let missing_parents: Vec<u32> = pid_map.keys().filter(|ppid| !listed.contains(ppid)).copied().collect();
+    let mut orphans: Vec<P> = missing_parents
+        .iter()
+        .filter_map(|ppid| pid_map.remove(ppid))
+        .flatten()
+        .collect();
+    orphans.sort_by_key(|process| process.pid());
+    for process in orphans {
+        tree.push(build_process_subtree(process, &mut pid_map));
+    }
+
+    tree
+}
+
+fn build_process_subtree<P: TreeItem>(process: P, pid_map: &mut HashMap<u32, Vec<P>>) -> ProcessNode<P> {
+    let mut node = ProcessNode {
+        process,
+        children: Vec::new(),
+    };
+
+    if let Some(children) = pid_map.remove(&node.process.pid()) {
+        for child in children {
+            node.children.push(build_process_subtree(child, pid_map));
+        }
+        // Sort children by PID
+        node.children.sort_by_key(|child| child.process.pid());
+    }
+
+    node
+}
+
+/// Every PID in the subtree rooted at `node`, including `node` itself
+pub fn subtree_pids<P: TreeItem>(node: &ProcessNode<P>) -> Vec<u32> {
+    let mut pids = vec![node.process.pid()];
+    for child in &node.children {
+        pids.extend(subtree_pids(child));
+    }
+    pids
+}
@@ -1,18 +1,199 @@
 const { invoke } = window.__TAURI__.core;
+const { listen } = window.__TAURI__.event;
 
-let greetInputEl;
-let greetMsgEl;
+// How often the backend pushes process-snapshot and system-metrics events
+const STREAM_INTERVAL_MS = 2000;
 
-async function greet() {
-  // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
-  greetMsgEl.textContent = await invoke("greet", { name: greetInputEl.value });
+let processes = [];
+let showTree = false;
+
+function describeError(error) {
+  // Errors from the backend are tagged objects, e.g. { kind: "NotFound", pid: 42 }
+  if (error && error.kind) {
+    switch (error.kind) {
+      case "NotFound":
+        return `Process ${error.pid} was not found`;
+      case "PermissionDenied":
+        return `Permission denied for process ${error.pid}`;
+      case "InvalidNice":
+        return `Nice value ${error.nice} is outside -20..19`;
+      default:
+        return error.message || error.kind;
+    }
+  }
+  return String(error);
+}
+
+function setStatus(message) {
+  document.querySelector("#status-msg").textContent = message;
+}
+
+function renderMetrics(m) {
+  const uptime = `${Math.floor(m.uptime / 86400)}d ${Math.floor((m.uptime % 86400) / 3600)}h ${Math.floor((m.uptime % 3600) / 60)}m`;
+  document.querySelector("#metrics").textContent =
+    `CPU ${m.cpu_usage.toFixed(1)}% | Memory ${m.used_memory.toFixed(0)}/${m.total_memory.toFixed(0)} MB | ` +
+    `Swap ${m.used_swap.toFixed(0)}/${m.swap.toFixed(0)} MB | Procs ${m.process_count} | ` +
+    `Cores ${m.logical_cores} (${m.physical_cores} phys) | Up ${uptime}`;
+}
+
+function matchesSearch(process) {
+  const query = document.querySelector("#search-input").value.trim().toLowerCase();
+  if (!query) return true;
+  return String(process.pid) === query || process.cmd.toLowerCase().includes(query) ||
+    process.cmdline.toLowerCase().includes(query);
+}
+
+function renderTable() {
+  const rows = document.querySelector("#process-rows");
+  rows.replaceChildren();
+  processes
+    .filter(matchesSearch)
+    .sort((a, b) => b.cpu - a.cpu)
+    .forEach((p) => {
+      const tr = document.createElement("tr");
+      const cells = [p.pid, p.ppid ?? "", p.user ?? "", p.cpu.toFixed(1), p.mem.toFixed(1), p.nice, p.state, p.cmd];
+      cells.forEach((value) => {
+        const td = document.createElement("td");
+        td.textContent = value;
+        tr.appendChild(td);
+      });
+      tr.lastChild.title = p.cmdline;
+
+      const actions = document.createElement("td");
+      actions.className = "actions";
+      [
+        ["Kill", () => runAction("kill", { pid: p.pid })],
+        ["Pause", () => runAction("pause", { pid: p.pid })],
+        ["Resume", () => runAction("resume", { pid: p.pid })],
+        ["Nice", () => promptRenice(p)],
+        ["Track", () => trackProcess(p)],
+      ].forEach(([label, handler]) => {
+        const button = document.createElement("button");
+        button.textContent = label;
+        button.addEventListener("click", handler);
+        actions.appendChild(button);
+      });
+      tr.appendChild(actions);
+      rows.appendChild(tr);
+    });
+}
+
+function renderTreeNode(node) {
+  const li = document.createElement("li");
+  const p = node.process;
+  li.textContent = `${p.cmd} (${p.pid}) ${p.cpu.toFixed(1)}% ${p.mem.toFixed(1)} MB`;
+  if (node.children.length > 0) {
+    const ul = document.createElement("ul");
+    node.children.forEach((child) => ul.appendChild(renderTreeNode(child)));
+    li.appendChild(ul);
+  }
+  return li;
+}
+
+async function renderTree() {
+  const roots = await invoke("process_tree");
+  const list = document.querySelector("#process-tree");
+  list.replaceChildren(...roots.map(renderTreeNode));
+}
+
+async function refresh() {
+  try {
+    if (showTree) {
+      await renderTree();
+    } else {
+      processes = await invoke("list_processes");
+      renderTable();
+    }
+    renderMetrics(await invoke("system_metrics"));
+  } catch (error) {
+    setStatus(describeError(error));
+  }
+}
+
+// Deltas patch the list by PID instead of replacing it
+function applySnapshotEvent(event) {
+  if (event.kind === "full") {
+    processes = event.processes;
+    return;
+  }
+  const removed = new Set(event.removed);
+  const changed = new Map(event.changed.map((p) => [p.pid, p]));
+  processes = processes
+    .filter((p) => !removed.has(p.pid))
+    .map((p) => changed.get(p.pid) ?? p)
+    .concat(event.added);
+}
+
+async function subscribe() {
+  await listen("process-snapshot", (event) => {
+    applySnapshotEvent(event.payload);
+    if (showTree) {
+      renderTree().catch((error) => setStatus(describeError(error)));
+    } else {
+      renderTable();
+    }
+  });
+  await listen("system-metrics", (event) => renderMetrics(event.payload));
+  await invoke("configure_stream", { intervalMs: STREAM_INTERVAL_MS, deltas: true });
+}
+
+async function runAction(command, args) {
+  try {
+    await invoke(command, args);
+    setStatus(`${command} sent to PID ${args.pid}`);
+    await refresh();
+  } catch (error) {
+    setStatus(describeError(error));
+  }
+}
+
+function promptRenice(process) {
+  const value = window.prompt(`New nice value for ${process.cmd} (${process.pid}):`, process.nice);
+  if (value === null) return;
+  const nice = parseInt(value, 10);
+  if (Number.isNaN(nice)) {
+    setStatus("Nice value must be a number");
+    return;
+  }
+  runAction("renice", { pid: process.pid, nice });
+}
+
+async function trackProcess(process) {
+  const value = window.prompt(`Track ${process.cmd} (${process.pid}) for how many seconds?`, "10");
+  if (value === null) return;
+  const durationSecs = parseInt(value, 10);
+  if (!(durationSecs > 0)) {
+    setStatus("Duration must be a positive number");
+    return;
+  }
+
+  document.querySelector("#track-panel").hidden = false;
+  document.querySelector("#track-title").textContent = `Tracking ${process.cmd} (${process.pid})...`;
+  document.querySelector("#track-output").textContent = "";
+  try {
+    const report = await invoke("track_process", { pid: process.pid, durationSecs });
+    const lines = report.samples.map((s) => `${s.timestamp}  CPU ${s.cpu.toFixed(2)}%  MEM ${s.memory_kb} KB`);
+    const summary = report.summary;
+    lines.push(`Average CPU ${summary.avg_cpu.toFixed(2)}%, average memory ${summary.avg_memory_kb} KB` +
+      (summary.exited ? " (process exited)" : ""));
+    document.querySelector("#track-title").textContent = `Tracked ${process.cmd} (${process.pid})`;
+    document.querySelector("#track-output").textContent = lines.join("\n");
+  } catch (error) {
+    document.querySelector("#track-title").textContent = describeError(error);
+  }
 }
 
 window.addEventListener("DOMContentLoaded", () => {
-  greetInputEl = document.querySelector("#greet-input");
-  greetMsgEl = document.querySelector("#greet-msg");
-  document.querySelector("#greet-form").addEventListener("submit", (e) => {
-    e.preventDefault();
-    greet();
+  document.querySelector("#refresh-btn").addEventListener("click", refresh);
+  document.querySelector("#search-input").addEventListener("input", renderTable);
+  document.querySelector("#view-btn").addEventListener("click", (e) => {
+    showTree = !showTree;
+    e.target.textContent = showTree ? "Table View" : "Tree View";
+    document.querySelector("#table-view").hidden = showTree;
+    document.querySelector("#tree-view").hidden = !showTree;
+    refresh();
   });
+
+  refresh();
+  subscribe().catch((error) => setStatus(describeError(error)));
 });